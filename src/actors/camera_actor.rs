@@ -1,12 +1,22 @@
-use anyhow::Context as Ctx;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use nokhwa::pixel_format::RgbFormat;
-use nokhwa::utils::{ApiBackend, CameraFormat, CameraIndex, RequestedFormat, RequestedFormatType};
-use nokhwa::{query, CallbackCamera};
+use anyhow::{anyhow, ensure, Context as ErrContext, Result};
 
-//use crate::generated::sessions::Session;
+use crate::config::values::update_section;
+use crate::config::CameraConfig;
+use crate::messages::camera::{CameraControlInfo, CameraFormatInfo, CameraInfo, CameraSettings};
 use crate::messages::camera_frame::CameraFrame;
+use crate::messages::config::{ConfigValue, ConfigValues};
+use crate::messages::identifiers::*;
 use crate::messages::protobuf_msg::ProtobufMsg;
+use crate::messages::registry::Routes;
+use crate::messages::state::CameraFailure;
+use crate::messages::still_capture::StillCapture;
+use crate::sources::controls::{parse_size, Control};
+use crate::sources::frame_flow::{FrameFlow, FrameStats};
+use crate::sources::frame_source::FrameSource;
 
 use crate::framework::streams::BroadcastStream;
 use crate::framework::streams::ChannelStream;
@@ -14,85 +24,430 @@ use crate::framework::streams::ChannelStream;
 use async_broadcast::{ Receiver as BroadcastReceiver, Sender as BroadcastSender };
 use async_channel::Sender as ChannelSender;
 
-use log::debug;
-use prost::Message as Msg;
+use log::{debug, info, warn};
+use prost::Message as PbMessage;
+use tokio::task::JoinHandle;
 use crate::framework::actor::Actor;
 use crate::generated::control::State;
 
 pub struct _StartCamera;
 
+const READ_ERROR_BACKOFF: Duration = Duration::from_millis(500);
+/// Reads failing one after another before the camera is taken to be gone
+const MAX_READ_ERRORS: u32 = 10;
+
+/// Work on the source itself, run by the capture loop between frames
+type SourceJob = Box<dyn FnOnce(&mut dyn FrameSource) + Send>;
+
+/// A capture loop running on a blocking task. The task hands the source back
+/// when it finishes so it can be started again later.
+struct Capture {
+    running: Arc<AtomicBool>,
+    /// Set once the source is open and `camera.opened` has been sent
+    opened: Arc<AtomicBool>,
+    jobs: ChannelSender<SourceJob>,
+    handle: JoinHandle<Box<dyn FrameSource>>,
+}
+
+enum CameraCommand {
+    Get,
+    Set(CameraSettings),
+    SetState(State),
+    Still,
+    ConfigChanged(ConfigValues),
+    Shutdown,
+}
+
+impl Drop for Capture {
+    // stop the loop if the actor goes away without stopping it, so the
+    // camera is released before a restarted actor tries to open it
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
 pub struct CameraActor {
+    routes: Routes<CameraCommand>,
+    config: CameraConfig,
     frame_tx: ChannelSender<CameraFrame>,
+    stats: Arc<FrameStats>,
+    still_tx: ChannelSender<StillCapture>,
+    // settings to save, ConfigActor writes them to the configuration
+    config_tx: ChannelSender<ConfigValues>,
     protobuf_pub_tx: BroadcastSender<ProtobufMsg>,
+    protobuf_subs_tx: BroadcastSender<ProtobufMsg>,
     protobuf_subs_rx: BroadcastReceiver<ProtobufMsg>,
-    camera: Option<CallbackCamera>,
-    format: Option<CameraFormat>,
+    source: Option<Box<dyn FrameSource>>,
+    capture: Option<Capture>,
 }
 
 impl CameraActor {
     pub(crate) fn new(
+        config: CameraConfig,
+        source: Box<dyn FrameSource>,
         frame_sender: ChannelStream<CameraFrame>,
+        still_sender: ChannelStream<StillCapture>,
+        config_sender: ChannelStream<ConfigValues>,
         protobuf_pub: BroadcastStream<ProtobufMsg>,
         protobuf_subs: BroadcastStream<ProtobufMsg>,
     ) -> Self {
         Self {
+            routes: Routes::new()
+                .on(CAMERA_GET, |()| CameraCommand::Get)
+                .on(CAMERA_SET, CameraCommand::Set)
+                .on(CAMERA_STATE_SET, CameraCommand::SetState)
+                .on(CAMERA_STILL, |()| CameraCommand::Still)
+                .on(CONFIG_CHANGED, CameraCommand::ConfigChanged)
+                .on(APP_SHUTDOWN, |()| CameraCommand::Shutdown),
+            config,
             frame_tx : frame_sender.channel_sender(),
+            stats : Arc::new(FrameStats::default()),
+            still_tx : still_sender.channel_sender(),
+            config_tx : config_sender.channel_sender(),
             protobuf_pub_tx : protobuf_pub.broadcast_sender(),
+            protobuf_subs_tx : protobuf_subs.broadcast_sender(),
             protobuf_subs_rx : protobuf_subs.broadcast_receiver(),
-            camera: None,
-            format: None,
+            source: Some(source),
+            capture: None,
+        }
+    }
+
+    fn is_capturing(&self) -> bool {
+        self.capture.as_ref().is_some_and(|capture| !capture.handle.is_finished())
+    }
+
+    async fn start_capture(&mut self) {
+        match &self.capture {
+            // a source that ran dry finishes on its own, reclaim it before restarting
+            Some(capture) if capture.handle.is_finished() => self.stop_capture().await,
+            Some(capture) => {
+                debug!("Capture already running");
+                // a restarted StateActor asks again and waits to hear the camera is open
+                if capture.opened.load(Ordering::Relaxed) {
+                    report_status(&self.protobuf_subs_tx, ProtobufMsg::new(CAMERA_OPENED, Vec::new()));
+                }
+                return;
+            }
+            None => {}
         }
+        let mut source = match self.source.take() {
+            Some(source) => source,
+            None => {
+                warn!("No frame source available");
+                return;
+            }
+        };
+
+        let running = Arc::new(AtomicBool::new(true));
+        let capture_running = running.clone();
+        let opened = Arc::new(AtomicBool::new(false));
+        let capture_opened = opened.clone();
+        let mut flow = FrameFlow::new(
+            self.frame_tx.clone(),
+            running.clone(),
+            self.config.fps,
+            self.config.when_full,
+            self.stats.clone(),
+        );
+        let status_tx = self.protobuf_subs_tx.clone();
+        let settings = self.config.clone();
+        let (jobs, jobs_rx) = async_channel::unbounded::<SourceJob>();
+
+        let handle = tokio::task::spawn_blocking(move || {
+            info!("Starting capture from {}", source.name());
+            if let Err(e) = source.open() {
+                warn!("Failed to open {}: {:#}", source.name(), e);
+                report_failure(&status_tx, format!("Failed to open {}: {:#}", source.name(), e));
+                return source;
+            }
+            apply_settings(source.as_mut(), &settings);
+            capture_opened.store(true, Ordering::Relaxed);
+            report_status(&status_tx, ProtobufMsg::new(CAMERA_OPENED, Vec::new()));
+            let mut read_errors = 0;
+            while capture_running.load(Ordering::Relaxed) {
+                while let Ok(job) = jobs_rx.try_recv() {
+                    job(source.as_mut());
+                }
+                flow.wait();
+                match source.next_frame() {
+                    Ok(Some(frame)) => {
+                        read_errors = 0;
+                        if !flow.send(frame) {
+                            debug!("Frame channel closed");
+                            break;
+                        }
+                    }
+                    Ok(None) => {
+                        info!("Frame source {} exhausted", source.name());
+                        report_failure(&status_tx, format!("Frame source {} exhausted", source.name()));
+                        break;
+                    }
+                    Err(e) => {
+                        warn!("Failed to read frame from {}: {:#}", source.name(), e);
+                        read_errors += 1;
+                        if read_errors >= MAX_READ_ERRORS {
+                            report_failure(
+                                &status_tx,
+                                format!("{} failed {} reads in a row: {:#}", source.name(), read_errors, e),
+                            );
+                            break;
+                        }
+                        std::thread::sleep(READ_ERROR_BACKOFF);
+                    }
+                }
+            }
+            // jobs left in the queue would keep their callers waiting for a reply
+            jobs_rx.close();
+            while jobs_rx.try_recv().is_ok() {}
+            if let Err(e) = source.close() {
+                warn!("Failed to close {}: {:#}", source.name(), e);
+            }
+            info!("Capture from {} stopped", source.name());
+            source
+        });
+        self.capture = Some(Capture { running, opened, jobs, handle });
     }
 
-    fn decode_state(self, buf : Vec<u8>) -> anyhow::Result<bool> {
-        let state = State::decode(&buf[..])
-            .with_context(|| "Failed to decode state")?;
-        Ok(state.state)
+    async fn stop_capture(&mut self) {
+        if let Some(mut capture) = self.capture.take() {
+            capture.running.store(false, Ordering::Relaxed);
+            match (&mut capture.handle).await {
+                Ok(source) => self.source = Some(source),
+                Err(e) => warn!("Capture task failed {}", e),
+            }
+            info!("{} frames captured, {} dropped", self.stats.captured(), self.stats.dropped());
+        }
+    }
+
+    /// Run `job` on the source. A running capture loop runs it between frames,
+    /// otherwise the source is opened just for it.
+    async fn with_source<T, F>(&mut self, job: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn FrameSource) -> Result<T> + Send + 'static,
+    {
+        match &self.capture {
+            Some(capture) if !capture.handle.is_finished() => {
+                let (reply_tx, reply_rx) = async_channel::bounded(1);
+                let job: SourceJob = Box::new(move |source| {
+                    let _ = reply_tx.send_blocking(job(source));
+                });
+                capture.jobs.send(job).await.map_err(|_| anyhow!("Capture has stopped"))?;
+                return reply_rx
+                    .recv()
+                    .await
+                    .context("Capture stopped before the camera answered")?;
+            }
+            Some(_) => self.stop_capture().await,
+            None => {}
+        }
+
+        let mut source = self.source.take().context("No frame source available")?;
+        let settings = self.config.clone();
+        let (source, result) = tokio::task::spawn_blocking(move || {
+            let result = source.open().and_then(|()| {
+                apply_settings(source.as_mut(), &settings);
+                job(source.as_mut())
+            });
+            if let Err(e) = source.close() {
+                warn!("Failed to close {}: {:#}", source.name(), e);
+            }
+            (source, result)
+        })
+        .await?;
+        self.source = Some(source);
+        result
+    }
+
+    /// Hand a full resolution still to SessionsActor, which stores it and
+    /// answers the request
+    async fn send_still(&mut self, request: &ProtobufMsg) -> Result<()> {
+        let frame = self.with_source(|source| {
+            info!("Taking a still from {}", source.name());
+            source.still()
+        }).await?;
+        self.still_tx
+            .send(StillCapture::new(request.clone(), frame))
+            .await
+            .map_err(|_| anyhow!("Stills are not being stored"))
+    }
+
+    async fn get_camera(&mut self, request: &ProtobufMsg) -> Result<()> {
+        let (name, controls, formats, format) = self.with_source(|source| {
+            Ok((source.name(), source.controls()?, source.formats()?, source.format()?))
+        }).await?;
+        let info = CameraInfo {
+            source: name,
+            capturing: self.is_capturing(),
+            width: format.map_or(0, |format| format.width),
+            height: format.map_or(0, |format| format.height),
+            controls: controls
+                .into_iter()
+                .map(|info| CameraControlInfo {
+                    name: info.control.name().to_string(),
+                    min: info.min,
+                    max: info.max,
+                    step: info.step,
+                    default: info.default,
+                    value: info.value,
+                })
+                .collect(),
+            formats: formats
+                .into_iter()
+                .map(|format| CameraFormatInfo {
+                    width: format.width,
+                    height: format.height,
+                    fps: format.fps,
+                })
+                .collect(),
+            frames_captured: self.stats.captured(),
+            frames_dropped: self.stats.dropped(),
+        };
+        let reply = request.reply(CAMERA, info.encode_to_vec());
+        self.protobuf_pub_tx.broadcast(reply).await?;
+        Ok(())
+    }
+
+    /// Change the controls and frame size on the camera, then have ConfigActor
+    /// save them so they are applied again whenever the camera is opened
+    async fn set_camera(&mut self, settings: CameraSettings) -> Result<()> {
+        let controls = settings
+            .controls
+            .iter()
+            .map(|value| Ok((value.name.parse::<Control>()?, value.value)))
+            .collect::<Result<Vec<_>>>()?;
+        let resolution = match (settings.width, settings.height) {
+            (0, 0) => None,
+            (width, height) => {
+                ensure!(width > 0 && height > 0, "Both width and height are needed");
+                Some((width, height))
+            }
+        };
+
+        let changes = controls.clone();
+        self.with_source(move |source| {
+            if let Some((width, height)) = resolution {
+                source.set_resolution(width, height)?;
+            }
+            let available = source.controls()?;
+            for (control, value) in changes {
+                let info = available
+                    .iter()
+                    .find(|info| info.control == control)
+                    .with_context(|| format!("{} has no {} control", source.name(), control))?;
+                info.check(value)?;
+                source.set_control(control, value)?;
+            }
+            Ok(())
+        }).await?;
+
+        let mut values = Vec::new();
+        if let Some((width, height)) = resolution {
+            let size = format!("{}x{}", width, height);
+            values.push(ConfigValue { key: "camera.resolution".to_string(), value: format!("\"{}\"", size) });
+            self.config.resolution = Some(size);
+        }
+        for (control, value) in controls {
+            info!("Camera {} set to {}", control, value);
+            values.push(ConfigValue { key: format!("camera.controls.{}", control), value: value.to_string() });
+            self.config.controls.set(control, value);
+        }
+        self.config_tx.send(ConfigValues { values }).await?;
+        Ok(())
+    }
+
+    /// Controls changed with `config.set` go to a running camera straight away,
+    /// otherwise they are applied when it next opens. A new frame rate or full
+    /// policy restarts a running capture, the loop paces frames with them.
+    async fn config_changed(&mut self, values: ConfigValues) -> Result<()> {
+        let previous = self.config.clone();
+        if !update_section(&mut self.config, "camera", &values.values)? || self.config == previous {
+            return Ok(());
+        }
+        let flow_changed = self.config.fps != previous.fps || self.config.when_full != previous.when_full;
+        if flow_changed && self.is_capturing() {
+            info!("Restarting capture at {} fps, {:?} when full", self.config.fps, self.config.when_full);
+            self.stop_capture().await;
+            self.start_capture().await;
+        } else if self.is_capturing() {
+            let settings = self.config.clone();
+            self.with_source(move |source| {
+                apply_settings(source, &settings);
+                Ok(())
+            }).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_command(&mut self, command: CameraCommand, msg: &ProtobufMsg) -> Result<()> {
+        match command {
+            CameraCommand::Get => self.get_camera(msg).await?,
+            CameraCommand::Set(settings) => self.set_camera(settings).await?,
+            CameraCommand::SetState(State { state: true }) => self.start_capture().await,
+            CameraCommand::SetState(State { state: false }) => self.stop_capture().await,
+            CameraCommand::ConfigChanged(values) => self.config_changed(values).await?,
+            CameraCommand::Still | CameraCommand::Shutdown => {}
+        }
+        Ok(())
     }
 }
+
+/// Put the configured frame size and controls on a source that was just opened
+fn apply_settings(source: &mut dyn FrameSource, settings: &CameraConfig) {
+    if let Some(resolution) = &settings.resolution {
+        let result = parse_size(resolution).and_then(|(width, height)| source.set_resolution(width, height));
+        if let Err(e) = result {
+            warn!("Failed to set resolution of {}: {:#}", source.name(), e);
+        }
+    }
+    for control in Control::ALL {
+        if let Some(value) = settings.controls.get(control) {
+            if let Err(e) = source.set_control(control, value) {
+                warn!("Failed to set {} of {}: {:#}", control, source.name(), e);
+            }
+        }
+    }
+}
+
+/// Tell StateActor the camera stopped without being asked to
+fn report_failure(status_tx: &BroadcastSender<ProtobufMsg>, error: String) {
+    report_status(status_tx, ProtobufMsg::new(CAMERA_FAILED, CameraFailure { error }.encode_to_vec()));
+}
+
+// Never block here, the actor may be waiting for this task to finish
+// and so not reading the stream it would be waiting on
+fn report_status(status_tx: &BroadcastSender<ProtobufMsg>, msg: ProtobufMsg) {
+    if let Err(e) = status_tx.try_broadcast(msg) {
+        warn!("Failed to report camera status {}", e);
+    }
+}
+
 impl Actor for CameraActor {
 
     async fn on_started(mut self) {
         debug!("Camera actor started");
 
-        let _ = query(ApiBackend::Auto).unwrap();
-        //cameras.iter().for_each(|cam| println!("{:?}", cam));
-        //let index = CameraIndex::Index(0);
-        //let camera = cameras.first().unwrap();
-        let format =
-            RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestResolution);
-        let camera = CallbackCamera::new(CameraIndex::Index(0), format, |_| {}).unwrap();
-
-        let _known = camera.camera_controls_known_camera_controls().unwrap();
-        let _controls = camera.camera_controls().unwrap();
-        //controls.iter().for_each(|ctl |  println!("{:?}", ctl));
-        let cam_format = camera.camera_format().unwrap();
-        debug!("{:?}", cam_format);
-
         loop {
             let res = self.protobuf_subs_rx.recv().await;
             match res {
                 Ok(msg) => {
                     debug!("->> ProtobufMsg {}", msg.identifier);
-                    match msg.identifier.as_str() {
-                        "camera.get" => {}
-                        "camera.state.set" => match self.camera.take() {
-                            Some(mut camera) => {
-                                tokio::spawn(async move {
-                                    loop {
-                                        let res = camera.poll_frame();
-                                        match res {
-                                            Ok(_buffer) => {
-                                                debug!("Frame")
-                                            }
-                                            Err(_) => {}
-                                        }
-                                    }
-                                });
-                            }
-                            None => {}
+                    let result = match self.routes.decode(&msg) {
+                        Some(Ok(CameraCommand::Shutdown)) => break,
+                        Some(Ok(CameraCommand::Still)) => match self.send_still(&msg).await {
+                            // answered by SessionsActor once the still is stored
+                            Ok(()) => continue,
+                            Err(e) => Err(e),
                         },
-                        _ => {}
+                        Some(Ok(command)) => self.handle_command(command, &msg).await,
+                        Some(Err(e)) => Err(e),
+                        None => continue,
+                    };
+                    if let Err(e) = &result {
+                        warn!("Error handling {}: {:#}", msg.identifier, e);
+                    }
+                    if let Some(done) = msg.completion(&result) {
+                        let _ = self.protobuf_pub_tx.broadcast(done).await;
                     }
                 }
                 Err(_) => {
@@ -100,5 +455,217 @@ impl Actor for CameraActor {
                 }
             }
         }
+
+        // release the device, then let the pipeline drain the frames already sent
+        self.stop_capture().await;
+        self.frame_tx.close();
+        debug!("Camera actor stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_channel::Receiver as ChannelReceiver;
+
+    use crate::messages::camera::CameraControlValue;
+    use crate::sources::frame_flow::FullPolicy;
+    use crate::sources::synthetic_source::SyntheticSource;
+
+    struct Harness {
+        camera: CameraActor,
+        // stands in for DetectionActor, without a receiver the frame channel is closed
+        _frames: ChannelReceiver<CameraFrame>,
+        replies: BroadcastReceiver<ProtobufMsg>,
+        subs: BroadcastReceiver<ProtobufMsg>,
+        saves: ChannelReceiver<ConfigValues>,
+    }
+
+    fn harness() -> Harness {
+        let protobuf_pub = BroadcastStream::new(10);
+        let protobuf_subs = BroadcastStream::new(10);
+        let frames = ChannelStream::new(10);
+        let saves = ChannelStream::new(10);
+        let camera = CameraActor::new(
+            CameraConfig::default(),
+            Box::new(SyntheticSource::new(640, 480)),
+            frames.clone(),
+            ChannelStream::new(10),
+            saves.clone(),
+            protobuf_pub.clone(),
+            protobuf_subs.clone(),
+        );
+        Harness {
+            camera,
+            _frames: frames.channel_receiver(),
+            replies: protobuf_pub.broadcast_receiver(),
+            subs: protobuf_subs.broadcast_receiver(),
+            saves: saves.channel_receiver(),
+        }
+    }
+
+    async fn next(rx: &mut BroadcastReceiver<ProtobufMsg>, identifier: &str) -> ProtobufMsg {
+        let wait = async {
+            loop {
+                let msg = rx.recv().await.unwrap();
+                if msg.identifier == identifier {
+                    return msg;
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), wait)
+            .await
+            .unwrap_or_else(|_| panic!("No {} sent", identifier))
+    }
+
+    async fn camera_info(harness: &mut Harness) -> CameraInfo {
+        let request = ProtobufMsg::new(CAMERA_GET, Vec::new());
+        harness.camera.get_camera(&request).await.unwrap();
+        let reply = next(&mut harness.replies, CAMERA).await;
+        CameraInfo::decode(reply.payload.as_slice()).unwrap()
+    }
+
+    fn control(info: &CameraInfo, name: &str) -> CameraControlInfo {
+        info.controls.iter().find(|control| control.name == name).unwrap().clone()
+    }
+
+    fn settings(controls: &[(&str, i64)], width: u32, height: u32) -> CameraSettings {
+        CameraSettings {
+            controls: controls
+                .iter()
+                .map(|&(name, value)| CameraControlValue { name: name.to_string(), value })
+                .collect(),
+            width,
+            height,
+        }
+    }
+
+    #[tokio::test]
+    async fn get_reports_the_source() {
+        let mut harness = harness();
+        let info = camera_info(&mut harness).await;
+
+        assert_eq!(info.source, "synthetic:640x480");
+        assert!(!info.capturing);
+        assert_eq!((info.width, info.height), (640, 480));
+        assert_eq!(info.controls.len(), 4);
+        let exposure = control(&info, "exposure");
+        assert_eq!((exposure.min, exposure.max, exposure.value), (1, 1000, 100));
+        assert!(info.formats.iter().any(|format| (format.width, format.height) == (1920, 1080)));
+    }
+
+    #[tokio::test]
+    async fn set_changes_the_source_and_saves_the_settings() {
+        let mut harness = harness();
+        harness
+            .camera
+            .set_camera(settings(&[("gain", 40), ("white_balance", 5000)], 1280, 960))
+            .await
+            .unwrap();
+
+        let values: Vec<_> = harness
+            .saves
+            .try_recv()
+            .unwrap()
+            .values
+            .into_iter()
+            .map(|value| (value.key, value.value))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("camera.resolution".to_string(), "\"1280x960\"".to_string()),
+                ("camera.controls.gain".to_string(), "40".to_string()),
+                ("camera.controls.white_balance".to_string(), "5000".to_string()),
+            ]
+        );
+
+        let info = camera_info(&mut harness).await;
+        assert_eq!((info.width, info.height), (1280, 960));
+        assert_eq!(control(&info, "gain").value, 40);
+        assert_eq!(control(&info, "white_balance").value, 5000);
+    }
+
+    #[tokio::test]
+    async fn set_rejects_bad_settings_without_saving() {
+        let mut harness = harness();
+        for bad in [
+            settings(&[("gain", 500)], 0, 0),
+            settings(&[("zoom", 1)], 0, 0),
+            settings(&[], 1280, 0),
+            settings(&[], 8192, 8192),
+        ] {
+            assert!(harness.camera.set_camera(bad).await.is_err());
+        }
+        assert!(harness.saves.try_recv().is_err());
+
+        let info = camera_info(&mut harness).await;
+        assert_eq!((info.width, info.height), (640, 480));
+        assert_eq!(control(&info, "gain").value, 0);
+    }
+
+    #[tokio::test]
+    async fn set_reaches_a_running_capture() {
+        let mut harness = harness();
+        harness.camera.start_capture().await;
+        next(&mut harness.subs, CAMERA_OPENED).await;
+
+        harness.camera.set_camera(settings(&[("exposure", 250)], 0, 0)).await.unwrap();
+        let info = camera_info(&mut harness).await;
+        assert!(info.capturing);
+        assert_eq!(control(&info, "exposure").value, 250);
+
+        harness.camera.stop_capture().await;
+        assert!(!harness.camera.is_capturing());
+    }
+
+    #[tokio::test]
+    async fn starting_a_running_capture_reports_it_open_again() {
+        let mut harness = harness();
+        harness.camera.start_capture().await;
+        next(&mut harness.subs, CAMERA_OPENED).await;
+
+        harness.camera.start_capture().await;
+        next(&mut harness.subs, CAMERA_OPENED).await;
+        assert!(harness.camera.is_capturing());
+
+        harness.camera.stop_capture().await;
+    }
+
+    #[tokio::test]
+    async fn set_is_answered_when_capture_ends_by_itself() {
+        let mut harness = harness();
+        harness.camera.start_capture().await;
+        next(&mut harness.subs, CAMERA_OPENED).await;
+
+        // the loop stops at its next frame, around the time the job is queued
+        harness.camera.frame_tx.close();
+        let set = harness.camera.set_camera(settings(&[("exposure", 250)], 0, 0));
+        tokio::time::timeout(Duration::from_secs(5), set)
+            .await
+            .expect("camera.set was never answered")
+            .ok();
+        harness.camera.stop_capture().await;
+    }
+
+    #[tokio::test]
+    async fn a_new_frame_rate_restarts_a_running_capture() {
+        let mut harness = harness();
+        harness.camera.start_capture().await;
+        next(&mut harness.subs, CAMERA_OPENED).await;
+
+        let values = ConfigValues {
+            values: vec![
+                ConfigValue { key: "camera.fps".to_string(), value: "2.5".to_string() },
+                ConfigValue { key: "camera.when_full".to_string(), value: "\"block\"".to_string() },
+            ],
+        };
+        harness.camera.config_changed(values).await.unwrap();
+        next(&mut harness.subs, CAMERA_OPENED).await;
+        assert!(harness.camera.is_capturing());
+        assert_eq!(harness.camera.config.fps, 2.5);
+        assert_eq!(harness.camera.config.when_full, FullPolicy::Block);
+
+        harness.camera.stop_capture().await;
     }
 }
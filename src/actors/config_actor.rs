@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use async_broadcast::{Receiver as BroadcastReceiver, Sender as BroadcastSender};
+use async_channel::{Receiver as ChannelReceiver, Sender as ChannelSender};
+use futures_util::{select, FutureExt};
+use log::{debug, info, warn};
+use prost::Message as PbMessage;
+
+use crate::config::values::{get_values, save_values, set_values};
+use crate::config::{Config, LiveConfig};
+use crate::framework::actor::Actor;
+use crate::framework::streams::{BroadcastStream, ChannelStream};
+use crate::messages::config::{ConfigQuery, ConfigValues};
+use crate::messages::identifiers::*;
+use crate::messages::protobuf_msg::ProtobufMsg;
+use crate::messages::registry::Routes;
+
+enum ConfigCommand {
+    Get(ConfigQuery),
+    Set(ConfigValues),
+    Shutdown,
+}
+
+/// Owns the running configuration. Changes are validated, written back to the
+/// configuration file, shared with the supervisor for actors it restarts and
+/// announced with `config.changed` so the actors concerned can apply them.
+/// Actors saving settings of their own send them on `saves` rather than as a
+/// `config.set`, so they never wait on the message channel they read.
+pub struct ConfigActor {
+    routes: Routes<ConfigCommand>,
+    saves_rx: ChannelReceiver<ConfigValues>,
+    // `config.changed` waiting to go out on the message channel
+    changed_tx: ChannelSender<ProtobufMsg>,
+    changed_rx: ChannelReceiver<ProtobufMsg>,
+    protobuf_pub_tx: BroadcastSender<ProtobufMsg>,
+    protobuf_subs_tx: BroadcastSender<ProtobufMsg>,
+    protobuf_subs_rx: BroadcastReceiver<ProtobufMsg>,
+    config: Config,
+    live: LiveConfig,
+    path: PathBuf,
+}
+
+impl ConfigActor {
+    pub(crate) fn new(
+        live: LiveConfig,
+        path: PathBuf,
+        saves: ChannelStream<ConfigValues>,
+        protobuf_pub: BroadcastStream<ProtobufMsg>,
+        protobuf_subs: BroadcastStream<ProtobufMsg>,
+    ) -> Self {
+        let (changed_tx, changed_rx) = async_channel::unbounded();
+        Self {
+            routes: Routes::new()
+                .on(CONFIG_GET, ConfigCommand::Get)
+                .on(CONFIG_SET, ConfigCommand::Set)
+                .on(APP_SHUTDOWN, |()| ConfigCommand::Shutdown),
+            saves_rx: saves.channel_receiver(),
+            changed_tx,
+            changed_rx,
+            protobuf_pub_tx: protobuf_pub.broadcast_sender(),
+            protobuf_subs_tx: protobuf_subs.broadcast_sender(),
+            protobuf_subs_rx: protobuf_subs.broadcast_receiver(),
+            config: live.get(),
+            live,
+            path,
+        }
+    }
+
+    async fn get_config(&mut self, query: ConfigQuery, request: &ProtobufMsg) -> Result<()> {
+        let values = ConfigValues { values: get_values(&self.config, &query.prefix)? };
+        let reply = request.reply(CONFIG, values.encode_to_vec());
+        self.protobuf_pub_tx.broadcast(reply).await?;
+        Ok(())
+    }
+
+    async fn set_config(&mut self, values: ConfigValues) -> Result<()> {
+        set_values(&mut self.config, &values.values)?;
+        self.live.set(self.config.clone());
+        for value in &values.values {
+            info!("Configuration {} set to {}", value.key, value.value);
+        }
+        if let Err(e) = save_values(&self.path, &values.values) {
+            warn!("Configuration changed but not saved: {:#}", e);
+        }
+
+        let changed = ProtobufMsg::new(CONFIG_CHANGED, values.encode_to_vec());
+        self.changed_tx.send(changed.clone()).await?;
+        self.protobuf_pub_tx.broadcast(changed).await?;
+        Ok(())
+    }
+
+    /// Put `config.changed` on the message channel in the order the changes
+    /// were made. Done apart from the actor's loop, which has to keep reading
+    /// that channel for the broadcast to find room.
+    async fn announce(
+        changed_rx: ChannelReceiver<ProtobufMsg>,
+        protobuf_subs_tx: BroadcastSender<ProtobufMsg>,
+    ) {
+        while let Ok(changed) = changed_rx.recv().await {
+            if protobuf_subs_tx.broadcast(changed).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    async fn handle_message(&mut self, msg: ProtobufMsg) -> bool {
+        let result = match self.routes.decode(&msg) {
+            Some(Ok(ConfigCommand::Get(query))) => self.get_config(query, &msg).await,
+            Some(Ok(ConfigCommand::Set(values))) => self.set_config(values).await,
+            Some(Ok(ConfigCommand::Shutdown)) => return false,
+            Some(Err(e)) => Err(e),
+            None => return true,
+        };
+        if let Err(e) = &result {
+            warn!("Error handling {}: {:#}", msg.identifier, e);
+        }
+        if let Some(done) = msg.completion(&result) {
+            let _ = self.protobuf_pub_tx.broadcast(done).await;
+        }
+        true
+    }
+}
+
+impl Actor for ConfigActor {
+    async fn on_started(mut self) {
+        debug!("Config actor started");
+        tokio::spawn(Self::announce(self.changed_rx.clone(), self.protobuf_subs_tx.clone()));
+
+        loop {
+            select! {
+                res = self.protobuf_subs_rx.recv_direct().fuse() => {
+                    let Ok(msg) = res else { break };
+                    if !self.handle_message(msg).await {
+                        break;
+                    }
+                }
+                res = self.saves_rx.recv().fuse() => {
+                    // never closed, the stream is kept by whatever built the actors
+                    let Ok(values) = res else { continue };
+                    if let Err(e) = self.set_config(values).await {
+                        warn!("Failed to save settings: {:#}", e);
+                    }
+                }
+            }
+        }
+        debug!("Config actor stopped");
+    }
+}
@@ -1,69 +1,637 @@
-use log::{debug, error};
-use ort::session::Session;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context as ErrContext, Result};
+use futures_util::{select, FutureExt};
+use log::{debug, error, info, warn};
+use nokhwa::pixel_format::RgbFormat;
+use prost::Message;
+use crate::detection::detector::Detector;
+use crate::detection::regions::admits;
+use crate::detection::registry::{model_name, ModelEntry, ModelId, ModelRegistry, StagedModel};
+use crate::detection::tiling::TileLayout;
+use crate::detection::yolo::BoundingBox;
 use crate::framework::actor::Actor;
 use crate::messages::camera_frame::CameraFrame;
+use crate::messages::detection_result::DetectionResult;
+use crate::messages::detections::{DetectionBox, FrameDetections};
+use crate::messages::config::{ConfigValue, ConfigValues};
+use crate::messages::model_install::ModelInstall;
+use crate::messages::models::{ModelInfo, ModelList, ModelSelect, TileLayoutInfo};
+use crate::messages::regions::{RegionInfo, RegionPoint, Regions};
+use crate::generated::control::State;
+use crate::messages::identifiers::{
+    CONFIG_CHANGED, DETECTION_FRAME, DETECTION_STREAM_SET, MODELS, MODEL_CHANGED, MODEL_INSTALLED,
+    MODEL_LIST, MODEL_SELECT, REGIONS, REGIONS_GET, REGIONS_SET,
+};
 use crate::messages::protobuf_msg::ProtobufMsg;
+use crate::messages::registry::Routes;
+use crate::config::values::update_section;
+use crate::config::{DetectionConfig, Region, RegionsConfig};
 
 
 use crate::framework::streams::BroadcastStream;
 use crate::framework::streams::ChannelStream;
 
 use async_broadcast::{ Receiver as BroadcastReceiver, Sender as BroadcastSender };
-use async_channel::Receiver as ChannelReceiver;
+use async_channel::{Receiver as ChannelReceiver, RecvError, Sender as ChannelSender};
+
+enum DetectionCommand {
+    ConfigChanged(ConfigValues),
+    SetStream(State),
+    ListModels,
+    SelectModel(ModelSelect),
+    GetRegions,
+    SetRegions(Regions),
+}
+
+/// A model loaded off the actor's thread, with the `model.select` that asked for it
+struct LoadedModel {
+    name: String,
+    request: Option<ProtobufMsg>,
+    result: Result<(ModelEntry, Detector)>,
+}
+
+/// The installed models read off the actor's thread for a `model.list`
+struct ListedModels {
+    request: ProtobufMsg,
+    result: Result<Vec<ModelEntry>>,
+}
+
+/// A frame run through the model off the actor's thread. The detector comes
+/// back with it, ready for the next frame.
+struct DetectedFrame {
+    frame: CameraFrame,
+    // the model that ran, a different one may have been installed meanwhile
+    model: Arc<ModelId>,
+    detector: Detector,
+    // boxes with the width and height of the frame
+    result: Result<(Vec<BoundingBox>, u32, u32)>,
+}
+
+/// An uploaded model that loaded and was installed, or why it was not
+struct InstalledModel {
+    request: ProtobufMsg,
+    select: bool,
+    result: Result<(ModelEntry, Detector)>,
+}
 
 pub struct DetectionActor {
+    routes: Routes<DetectionCommand>,
+    config: DetectionConfig,
+    registry: ModelRegistry,
+    detector: Option<Detector>,
+    // the model `detector` runs, passed on with every result
+    model: Arc<ModelId>,
+    entry: Option<ModelEntry>,
+    // input size of the running model, kept while `detector` is out detecting
+    input_size: u32,
+    // tile layout last reported in `model.changed`
+    layout: Option<TileLayout>,
+    // model being loaded, frames go to the running one until it is ready
+    loading: Option<String>,
+    loaded_tx: ChannelSender<LoadedModel>,
+    loaded_rx: ChannelReceiver<LoadedModel>,
+    install_rx: ChannelReceiver<ModelInstall>,
+    installed_tx: ChannelSender<InstalledModel>,
+    installed_rx: ChannelReceiver<InstalledModel>,
+    listed_tx: ChannelSender<ListedModels>,
+    listed_rx: ChannelReceiver<ListedModels>,
+    // a frame is being detected, the next is read once it is done
+    detecting: bool,
+    detected_tx: ChannelSender<Result<DetectedFrame>>,
+    detected_rx: ChannelReceiver<Result<DetectedFrame>>,
+    // publish `detection.frame` for the app, only while the trap is streaming
+    stream: bool,
     frame_rx: ChannelReceiver<CameraFrame>,
+    detection_tx: ChannelSender<DetectionResult>,
+    preview_tx: ChannelSender<DetectionResult>,
+    // settings to save, ConfigActor writes them to the configuration
+    config_tx: ChannelSender<ConfigValues>,
     protobuf_pub_tx: BroadcastSender<ProtobufMsg>,
     protobuf_subs_rx: BroadcastReceiver<ProtobufMsg>,
 }
 
 impl DetectionActor {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        config: DetectionConfig,
         frame_receiver: ChannelStream<CameraFrame>,
+        detection_sender: ChannelStream<DetectionResult>,
+        preview_sender: ChannelStream<DetectionResult>,
+        install_receiver: ChannelStream<ModelInstall>,
+        config_sender: ChannelStream<ConfigValues>,
         protobuf_pub: BroadcastStream<ProtobufMsg>,
         protobuf_subs: BroadcastStream<ProtobufMsg>
     ) -> Self {
+        let (loaded_tx, loaded_rx) = async_channel::unbounded();
+        let (installed_tx, installed_rx) = async_channel::unbounded();
+        let (listed_tx, listed_rx) = async_channel::unbounded();
+        let (detected_tx, detected_rx) = async_channel::unbounded();
         Self {
+            routes: Routes::new()
+                .on(CONFIG_CHANGED, DetectionCommand::ConfigChanged)
+                .on(DETECTION_STREAM_SET, DetectionCommand::SetStream)
+                .on(MODEL_LIST, |()| DetectionCommand::ListModels)
+                .on(MODEL_SELECT, DetectionCommand::SelectModel)
+                .on(REGIONS_GET, |()| DetectionCommand::GetRegions)
+                .on(REGIONS_SET, DetectionCommand::SetRegions),
+            registry: ModelRegistry::new(&config.models),
+            config,
+            detector: None,
+            model: Arc::default(),
+            entry: None,
+            input_size: 0,
+            layout: None,
+            loading: None,
+            loaded_tx,
+            loaded_rx,
+            install_rx : install_receiver.channel_receiver(),
+            installed_tx,
+            installed_rx,
+            listed_tx,
+            listed_rx,
+            detecting: false,
+            detected_tx,
+            detected_rx,
+            stream: false,
             frame_rx : frame_receiver.channel_receiver(),
+            detection_tx : detection_sender.channel_sender(),
+            preview_tx : preview_sender.channel_sender(),
+            config_tx : config_sender.channel_sender(),
             protobuf_pub_tx : protobuf_pub.broadcast_sender(),
             protobuf_subs_rx : protobuf_subs.broadcast_receiver(),
         }
     }
+
+    /// Load a model from the registry on a blocking thread, the result comes
+    /// back on `loaded_rx`
+    fn load_model(&mut self, name: &str, request: Option<ProtobufMsg>) {
+        let name = model_name(name).to_string();
+        debug!("Loading model {}", name);
+        self.loading = Some(name.clone());
+
+        let registry = self.registry.clone();
+        let (confidence, iou) = (self.config.confidence as f32, self.config.iou as f32);
+        let loaded_tx = self.loaded_tx.clone();
+        tokio::spawn(async move {
+            let find = name.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let _reading = registry.read();
+                let entry = registry.find(&find)?;
+                let detector = Detector::load(&entry.path, confidence, iou)?;
+                Ok((entry, detector))
+            })
+            .await
+            .unwrap_or_else(|e| Err(anyhow!("Loading stopped: {}", e)));
+            let _ = loaded_tx.send(LoadedModel { name, request, result }).await;
+        });
+    }
+
+    /// Swap in a loaded model, unless another was selected while it loaded
+    async fn model_loaded(&mut self, loaded: LoadedModel) {
+        let LoadedModel { name, request, result } = loaded;
+        let result = match result {
+            _ if self.loading.as_deref() != Some(name.as_str()) => {
+                Err(anyhow!("Model {} was replaced by a later selection", name))
+            }
+            Ok((entry, detector)) => {
+                self.loading = None;
+                self.install_model(entry, detector, request.is_some()).await
+            }
+            Err(e) => {
+                self.loading = None;
+                Err(e)
+            }
+        };
+
+        if let Err(e) = &result {
+            error!("Failed to load model {}: {:#}", name, e);
+        }
+        if let Some(done) = request.and_then(|request| request.completion(&result)) {
+            let _ = self.protobuf_pub_tx.broadcast(done).await;
+        }
+    }
+
+    /// Try loading an uploaded model and install it in the registry if it
+    /// loads, the result comes back on `installed_rx`
+    fn try_upload(&self, install: ModelInstall) {
+        let registry = self.registry.clone();
+        let (confidence, iou) = (self.config.confidence as f32, self.config.iou as f32);
+        let installed_tx = self.installed_tx.clone();
+        let staged: StagedModel = install.staged().clone();
+        tokio::spawn(async move {
+            let trial = staged.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let detector = Detector::load(&trial.path, confidence, iou)?;
+                Ok((registry.install(&trial)?, detector))
+            })
+            .await
+            .unwrap_or_else(|e| Err(anyhow!("Loading stopped: {}", e)));
+            if result.is_err() {
+                staged.discard();
+            }
+            let installed = InstalledModel {
+                request: install.request().clone(),
+                select: install.select(),
+                result,
+            };
+            let _ = installed_tx.send(installed).await;
+        });
+    }
+
+    /// Reply to the upload, and detect with the new model if it was asked
+    /// for or replaces the running model
+    async fn model_installed(&mut self, installed: InstalledModel) {
+        let InstalledModel { request, select, result } = installed;
+        let result = match result {
+            Ok((entry, detector)) => {
+                info!("Installed model {}", entry.id);
+                let mut result = Ok(());
+                if select || entry.id.name == self.model.name {
+                    // a model.select still loading is overtaken by this one
+                    self.loading = None;
+                    result = self.install_model(entry.clone(), detector, select).await;
+                }
+                let reply = request.reply(MODEL_INSTALLED, self.model_info(&entry).encode_to_vec());
+                let _ = self.protobuf_pub_tx.broadcast(reply).await;
+                result
+            }
+            Err(e) => Err(e.context("Model not installed")),
+        };
+
+        if let Err(e) = &result {
+            error!("{:#}", e);
+        }
+        if let Some(done) = request.completion(&result) {
+            let _ = self.protobuf_pub_tx.broadcast(done).await;
+        }
+    }
+
+    /// Make the model the one frames are run through. Models picked with
+    /// `model.select` are saved to the configuration so they survive a restart.
+    async fn install_model(&mut self, entry: ModelEntry, mut detector: Detector, save: bool) -> Result<()> {
+        info!("Detecting with model {}", entry.id);
+        detector.set_tiling(&self.config.tiling);
+        self.input_size = detector.input_size();
+        self.detector = Some(detector);
+        self.model = Arc::new(entry.id.clone());
+        self.entry = Some(entry.clone());
+        self.layout = None;
+
+        let changed = ProtobufMsg::new(MODEL_CHANGED, self.model_info(&entry).encode_to_vec());
+        self.protobuf_pub_tx.broadcast(changed).await?;
+
+        if save && model_name(&self.config.model) != entry.id.name {
+            self.config.model = entry.id.name.clone();
+            let value = ConfigValue {
+                key: "detection.model".to_string(),
+                value: format!("\"{}\"", entry.id.name),
+            };
+            self.config_tx.send(ConfigValues { values: vec![value] }).await?;
+        }
+        Ok(())
+    }
+
+    fn model_info(&self, entry: &ModelEntry) -> ModelInfo {
+        let active = entry.id == *self.model;
+        ModelInfo {
+            name: entry.id.name.clone(),
+            version: entry.id.version.clone(),
+            size: entry.size,
+            labels: entry.labels as u32,
+            active,
+            input_size: if active { self.input_size } else { 0 },
+            tiles: self.layout.filter(|_| active).map(|layout| TileLayoutInfo {
+                frame_width: layout.frame_width,
+                frame_height: layout.frame_height,
+                tile_size: layout.tile_size,
+                columns: layout.columns,
+                rows: layout.rows,
+                overlap: layout.overlap,
+            }),
+        }
+    }
+
+    /// Tell the app when the tile layout changes, as tiling is switched on
+    /// or off or the frame size changes
+    async fn report_layout(&mut self) {
+        let layout = self.detector.as_ref().and_then(Detector::layout);
+        if layout == self.layout {
+            return;
+        }
+        self.layout = layout;
+        if let Some(entry) = &self.entry {
+            let changed = ProtobufMsg::new(MODEL_CHANGED, self.model_info(entry).encode_to_vec());
+            let _ = self.protobuf_pub_tx.broadcast(changed).await;
+        }
+    }
+
+    /// Read the installed models on a blocking thread, hashing a new model
+    /// takes a while. The entries come back on `listed_rx`.
+    fn list_models(&self, request: ProtobufMsg) {
+        let registry = self.registry.clone();
+        let listed_tx = self.listed_tx.clone();
+        tokio::spawn(async move {
+            let result = tokio::task::spawn_blocking(move || registry.list())
+                .await
+                .unwrap_or_else(|e| Err(anyhow!("Listing stopped: {}", e)));
+            let _ = listed_tx.send(ListedModels { request, result }).await;
+        });
+    }
+
+    async fn models_listed(&self, listed: ListedModels) {
+        let ListedModels { request, result } = listed;
+        let result = match result {
+            Ok(entries) => {
+                let models = entries.iter().map(|entry| self.model_info(entry)).collect();
+                let reply = request.reply(MODELS, ModelList { models }.encode_to_vec());
+                self.protobuf_pub_tx.broadcast(reply).await.map(|_| ()).map_err(Into::into)
+            }
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = &result {
+            warn!("Error handling {}: {:#}", request.identifier, e);
+        }
+        if let Some(done) = request.completion(&result) {
+            let _ = self.protobuf_pub_tx.broadcast(done).await;
+        }
+    }
+
+    async fn get_regions(&self, request: &ProtobufMsg) -> Result<()> {
+        let info = |regions: &[Region]| {
+            regions
+                .iter()
+                .map(|region| RegionInfo {
+                    name: region.name.clone(),
+                    points: region.points.iter().map(|&[x, y]| RegionPoint { x, y }).collect(),
+                })
+                .collect()
+        };
+        let regions = Regions {
+            include: info(&self.config.regions.include),
+            exclude: info(&self.config.regions.exclude),
+        };
+        let reply = request.reply(REGIONS, regions.encode_to_vec());
+        self.protobuf_pub_tx.broadcast(reply).await?;
+        Ok(())
+    }
+
+    /// Replace the regions, then have ConfigActor save them and pass them on
+    /// to the preview
+    async fn set_regions(&mut self, regions: Regions) -> Result<()> {
+        let config = |regions: Vec<RegionInfo>| {
+            regions
+                .into_iter()
+                .map(|region| Region {
+                    name: region.name,
+                    points: region.points.iter().map(|point| [point.x, point.y]).collect(),
+                })
+                .collect()
+        };
+        let regions = RegionsConfig { include: config(regions.include), exclude: config(regions.exclude) };
+        regions.validate()?;
+
+        let mut values = Vec::new();
+        for (kind, list) in [("include", &regions.include), ("exclude", &regions.exclude)] {
+            values.push(ConfigValue {
+                key: format!("detection.regions.{}", kind),
+                value: toml::Value::try_from(list)?.to_string(),
+            });
+        }
+        info!(
+            "Detection regions set, {} included and {} excluded",
+            regions.include.len(),
+            regions.exclude.len()
+        );
+        self.config.regions = regions;
+        self.config_tx.send(ConfigValues { values }).await?;
+        Ok(())
+    }
+
+    /// New thresholds and tiling apply to the running model straight away,
+    /// a new model name loads that model
+    fn config_changed(&mut self, values: ConfigValues) -> Result<()> {
+        if !update_section(&mut self.config, "detection", &values.values)? {
+            return Ok(());
+        }
+        if let Some(detector) = self.detector.as_mut() {
+            detector.set_thresholds(self.config.confidence as f32, self.config.iou as f32);
+            detector.set_tiling(&self.config.tiling);
+        }
+        let name = model_name(&self.config.model).to_string();
+        if name != self.model.name && self.loading.as_deref() != Some(name.as_str()) {
+            self.load_model(&name, None);
+        }
+        Ok(())
+    }
+
+    async fn handle_message(&mut self, msg: ProtobufMsg) {
+        let result = match self.routes.decode(&msg) {
+            Some(Ok(DetectionCommand::ConfigChanged(values))) => self.config_changed(values),
+            Some(Ok(DetectionCommand::SetStream(State { state }))) => {
+                self.stream = state;
+                Ok(())
+            }
+            Some(Ok(DetectionCommand::ListModels)) => {
+                // answered once the models have been read
+                self.list_models(msg);
+                return;
+            }
+            Some(Ok(DetectionCommand::GetRegions)) => self.get_regions(&msg).await,
+            Some(Ok(DetectionCommand::SetRegions(regions))) => self.set_regions(regions).await,
+            Some(Ok(DetectionCommand::SelectModel(ModelSelect { name }))) => {
+                // answered once the model has loaded
+                self.load_model(&name, Some(msg));
+                return;
+            }
+            Some(Err(e)) => Err(e),
+            None => return,
+        };
+        if let Err(e) = &result {
+            warn!("Error handling {}: {:#}", msg.identifier, e);
+        }
+        if let Some(done) = msg.completion(&result) {
+            let _ = self.protobuf_pub_tx.broadcast(done).await;
+        }
+    }
+
+    /// Run a frame through the model on a blocking thread, tiled frames take
+    /// several runs. The detector goes with it and comes back on `detected_rx`.
+    fn detect_frame(&mut self, frame: CameraFrame) {
+        let Some(mut detector) = self.detector.take() else {
+            // no model, but the preview still helps to set up the camera
+            self.send_preview(&frame, &[]);
+            return;
+        };
+        self.detecting = true;
+        let model = self.model.clone();
+        let detected_tx = self.detected_tx.clone();
+        tokio::spawn(async move {
+            let detected = tokio::task::spawn_blocking(move || {
+                let result = frame
+                    .buffer()
+                    .decode_image::<RgbFormat>()
+                    .context("Failed to decode frame")
+                    .and_then(|image| {
+                        let (width, height) = image.dimensions();
+                        Ok((detector.detect(&image)?, width, height))
+                    });
+                DetectedFrame { frame, model, detector, result }
+            })
+            .await
+            .map_err(|e| anyhow!("Detection stopped: {}", e));
+            let _ = detected_tx.send(detected).await;
+        });
+    }
+
+    /// Pass on what was found in a frame and take the detector back, unless
+    /// another model was installed while the frame was detected
+    async fn frame_detected(&mut self, detected: Result<DetectedFrame>) {
+        self.detecting = false;
+        let DetectedFrame { frame, model, mut detector, result } = match detected {
+            Ok(detected) => detected,
+            Err(e) => {
+                // the detector went with the thread, load the model again
+                error!("{:#}", e);
+                if self.detector.is_none() && self.loading.is_none() {
+                    let name = self.model.name.clone();
+                    self.load_model(&name, None);
+                }
+                return;
+            }
+        };
+        if self.detector.is_none() && Arc::ptr_eq(&model, &self.model) {
+            // settings changed while it was out
+            detector.set_thresholds(self.config.confidence as f32, self.config.iou as f32);
+            detector.set_tiling(&self.config.tiling);
+            self.detector = Some(detector);
+        }
+
+        match result {
+            Ok((mut boxes, width, height)) => {
+                boxes.retain(|bbox| admits(&self.config.regions, bbox, width, height));
+                debug!("->> Frame {} boxes", boxes.len());
+                self.report_layout().await;
+                if self.stream {
+                    let msg = self.encode_detections(&frame, &boxes);
+                    let _ = self.protobuf_pub_tx.broadcast(msg).await;
+                    self.send_preview(&frame, &boxes);
+                }
+                let result = DetectionResult::new(frame, boxes, model);
+                if self.detection_tx.send(result).await.is_err() {
+                    warn!("Detection channel closed");
+                }
+            }
+            Err(e) => warn!("Detection failed {:#}", e),
+        }
+    }
+
+    /// The next frame, none while the last is still being detected
+    async fn next_frame(
+        frame_rx: &ChannelReceiver<CameraFrame>,
+        detecting: bool,
+    ) -> Result<CameraFrame, RecvError> {
+        if detecting {
+            std::future::pending().await
+        } else {
+            frame_rx.recv().await
+        }
+    }
+
+    /// Hand the frame to PreviewActor while streaming. Frames are dropped
+    /// rather than wait for the preview to catch up.
+    fn send_preview(&self, frame: &CameraFrame, boxes: &[BoundingBox]) {
+        if self.stream {
+            let result = DetectionResult::new(frame.clone(), boxes.to_vec(), self.model.clone());
+            let _ = self.preview_tx.try_send(result);
+        }
+    }
+
+    fn encode_detections(&self, frame: &CameraFrame, boxes: &[BoundingBox]) -> ProtobufMsg {
+        let resolution = frame.buffer().resolution();
+        ProtobufMsg::new(
+            DETECTION_FRAME,
+            FrameDetections {
+                timestamp: frame.timestamp(),
+                width: resolution.width() as i32,
+                height: resolution.height() as i32,
+                boxes: boxes
+                    .iter()
+                    .map(|b| DetectionBox {
+                        x1: b.x1,
+                        y1: b.y1,
+                        x2: b.x2,
+                        y2: b.y2,
+                        score: b.score,
+                        clazz: b.clazz,
+                    })
+                    .collect(),
+                model: self.model.name.clone(),
+                model_version: self.model.version.clone(),
+            }
+            .encode_to_vec(),
+        )
+    }
 }
 
 impl Actor for DetectionActor {
     async fn on_started(mut self) {
         debug!("Detection actor started");
 
-        let result = Session::builder()
-            .unwrap().
-            commit_from_file("models/insects-320.onnx");
-        
-        match result {
-            Ok(_model) => { debug!("Model loaded"); },
-            Err(e) => { error!("Error {}", e)}
-        }
-        
-        let res = self.protobuf_subs_rx.recv().await;
-        match res {
-            Ok(msg) => {
-                debug!("->> ProtobufMsg {}", msg.identifier);
-            }
-            Err(_) => {
-                debug!("No message received");
-                
-            }
+        // the first model is loaded before any frames are looked at
+        let name = self.config.model.clone();
+        self.load_model(&name, None);
+        if let Ok(loaded) = self.loaded_rx.recv().await {
+            self.model_loaded(loaded).await;
         }
+
         loop {
-            let res = self.frame_rx.recv().await;
-            match res {
-                Ok(_msg) => {
-                    debug!("->> Frame ");
+            select! {
+                res = self.protobuf_subs_rx.recv().fuse() => {
+                    let Ok(msg) = res else {
+                        debug!("No message received");
+                        continue;
+                    };
+                    self.handle_message(msg).await;
                 }
-                Err(_) => {
-                    debug!("No message received");
+                res = self.loaded_rx.recv().fuse() => {
+                    if let Ok(loaded) = res {
+                        self.model_loaded(loaded).await;
+                    }
+                }
+                res = self.install_rx.recv().fuse() => {
+                    // ModelUploadActor never closes this one
+                    if let Ok(install) = res {
+                        self.try_upload(install);
+                    }
+                }
+                res = self.installed_rx.recv().fuse() => {
+                    if let Ok(installed) = res {
+                        self.model_installed(installed).await;
+                    }
+                }
+                res = self.listed_rx.recv().fuse() => {
+                    if let Ok(listed) = res {
+                        self.models_listed(listed).await;
+                    }
+                }
+                res = self.detected_rx.recv().fuse() => {
+                    if let Ok(detected) = res {
+                        self.frame_detected(detected).await;
+                    }
+                }
+                res = Self::next_frame(&self.frame_rx, self.detecting).fuse() => {
+                    match res {
+                        Ok(frame) => self.detect_frame(frame),
+                        // closed by CameraActor on shutdown once the frames are drained
+                        Err(_) => break,
+                    }
                 }
             }
         }
+        self.detection_tx.close();
+        self.preview_tx.close();
+        debug!("Detection actor stopped");
     }
-}
\ No newline at end of file
+}
@@ -4,3 +4,8 @@ pub mod state_actor;
 pub mod camera_actor;
 pub mod detection_actor;
 pub mod websocket_actor;
+pub mod tracking_actor;
+pub mod config_actor;
+pub mod scheduler_actor;
+pub mod preview_actor;
+pub mod model_upload_actor;
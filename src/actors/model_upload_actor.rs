@@ -0,0 +1,351 @@
+use std::fs::File;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use anyhow::{ensure, Context as ErrContext, Result};
+use async_broadcast::{Receiver as BroadcastReceiver, Sender as BroadcastSender};
+use async_channel::Sender as ChannelSender;
+use log::{debug, info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::config::DetectionConfig;
+use crate::detection::registry::{to_hex, ModelRegistry, StagedModel};
+use crate::framework::actor::Actor;
+use crate::framework::streams::{BroadcastStream, ChannelStream};
+use crate::messages::identifiers::*;
+use crate::messages::model_install::ModelInstall;
+use crate::messages::models::{ModelUploadBegin, ModelUploadChunk};
+use crate::messages::protobuf_msg::ProtobufMsg;
+use crate::messages::registry::Routes;
+
+/// An upload with no chunk for this long is taken to be abandoned by its
+/// client, and another `model.upload.begin` may replace it
+const UPLOAD_IDLE: Duration = Duration::from_secs(60);
+
+enum UploadCommand {
+    Begin(ModelUploadBegin),
+    Chunk(ModelUploadChunk),
+    Commit,
+    Shutdown,
+}
+
+/// A model being received
+struct Upload {
+    staged: StagedModel,
+    select: bool,
+    size: u64,
+    sha256: String,
+    received: u64,
+    hasher: Sha256,
+    file: File,
+    /// When the upload began or last received a chunk
+    active: Instant,
+}
+
+impl Upload {
+    /// Check the whole model arrived intact and is on disk
+    fn finish(self) -> Result<StagedModel> {
+        ensure!(
+            self.received == self.size,
+            "Received {} of {} bytes of model {}",
+            self.received,
+            self.size,
+            self.staged.name
+        );
+        let sha256 = to_hex(&self.hasher.finalize());
+        ensure!(sha256 == self.sha256, "Checksum of model {} does not match, got {}", self.staged.name, sha256);
+        self.file.sync_all().context("Failed to write model")?;
+        Ok(self.staged)
+    }
+}
+
+/// Receives models sent in chunks with `model.upload.*` and passes them to
+/// DetectionActor, which tries them before they are installed. There is one
+/// upload at a time, a `model.upload.begin` while another upload is receiving
+/// chunks is refused and one that has gone idle is abandoned. An upload that
+/// goes wrong is abandoned and its file removed.
+pub struct ModelUploadActor {
+    routes: Routes<UploadCommand>,
+    registry: ModelRegistry,
+    max_size: u64,
+    max_chunk: u64,
+    upload: Option<Upload>,
+    install_tx: ChannelSender<ModelInstall>,
+    protobuf_pub_tx: BroadcastSender<ProtobufMsg>,
+    protobuf_subs_rx: BroadcastReceiver<ProtobufMsg>,
+}
+
+impl ModelUploadActor {
+    pub(crate) fn new(
+        config: &DetectionConfig,
+        install_sender: ChannelStream<ModelInstall>,
+        protobuf_pub: BroadcastStream<ProtobufMsg>,
+        protobuf_subs: BroadcastStream<ProtobufMsg>,
+    ) -> Self {
+        Self {
+            routes: Routes::new()
+                .on(MODEL_UPLOAD_BEGIN, UploadCommand::Begin)
+                .on(MODEL_UPLOAD_CHUNK, UploadCommand::Chunk)
+                .on(MODEL_UPLOAD_COMMIT, |()| UploadCommand::Commit)
+                .on(APP_SHUTDOWN, |()| UploadCommand::Shutdown),
+            registry: ModelRegistry::new(&config.models),
+            max_size: config.max_upload_mb * 1024 * 1024,
+            max_chunk: config.max_chunk_kb * 1024,
+            upload: None,
+            install_tx: install_sender.channel_sender(),
+            protobuf_pub_tx: protobuf_pub.broadcast_sender(),
+            protobuf_subs_rx: protobuf_subs.broadcast_receiver(),
+        }
+    }
+
+    fn abandon(&mut self) {
+        if let Some(upload) = self.upload.take() {
+            warn!("Abandoned upload of model {} at {} of {} bytes", upload.staged.name, upload.received, upload.size);
+            upload.staged.discard();
+        }
+    }
+
+    fn begin(&mut self, begin: ModelUploadBegin) -> Result<()> {
+        ensure!(
+            begin.size > 0 && begin.size <= self.max_size,
+            "Model size must be between 1 and {} bytes",
+            self.max_size
+        );
+        ensure!(
+            begin.sha256.len() == 64 && begin.sha256.chars().all(|c| c.is_ascii_hexdigit()),
+            "sha256 must be 64 hex digits"
+        );
+        if let Some(upload) = &self.upload {
+            ensure!(
+                upload.active.elapsed() >= UPLOAD_IDLE,
+                "Model {} is being uploaded, {} of {} bytes received",
+                upload.staged.name,
+                upload.received,
+                upload.size
+            );
+        }
+        self.abandon();
+
+        let staged = self.registry.stage(&begin.name, &begin.version, begin.labels)?;
+        let file = File::create_new(&staged.path)
+            .with_context(|| format!("Failed to create {}", staged.path.display()))?;
+        info!("Receiving model {}, {} bytes", staged.name, begin.size);
+        self.upload = Some(Upload {
+            staged,
+            select: begin.select,
+            size: begin.size,
+            sha256: begin.sha256.to_ascii_lowercase(),
+            received: 0,
+            hasher: Sha256::new(),
+            file,
+            active: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// Add a chunk to the upload. A chunk that does not fit ends the upload,
+    /// the client starts again with `model.upload.begin`.
+    fn chunk(&mut self, chunk: ModelUploadChunk) -> Result<()> {
+        let result = self.write_chunk(chunk);
+        if result.is_err() {
+            self.abandon();
+        }
+        result
+    }
+
+    fn write_chunk(&mut self, chunk: ModelUploadChunk) -> Result<()> {
+        let upload = self.upload.as_mut().context("No model upload in progress")?;
+        let len = chunk.data.len() as u64;
+        ensure!(len <= self.max_chunk, "Chunks must be at most {} bytes", self.max_chunk);
+        // a chunk sent again after a lost reply or one that went missing
+        ensure!(chunk.offset == upload.received, "Expected the chunk at offset {}", upload.received);
+        ensure!(upload.received + len <= upload.size, "Model is larger than the {} bytes announced", upload.size);
+
+        upload.file.write_all(&chunk.data).context("Failed to write model")?;
+        upload.hasher.update(&chunk.data);
+        upload.received += len;
+        upload.active = Instant::now();
+        debug!("Received {} of {} bytes of model {}", upload.received, upload.size, upload.staged.name);
+        Ok(())
+    }
+
+    async fn commit(&mut self, request: &ProtobufMsg) -> Result<()> {
+        let upload = self.upload.take().context("No model upload in progress")?;
+        let select = upload.select;
+        let staged = upload.staged.clone();
+        let staged = match upload.finish() {
+            Ok(staged) => staged,
+            Err(e) => {
+                staged.discard();
+                return Err(e);
+            }
+        };
+        info!("Received model {}, trying it before installing", staged.name);
+        self.install_tx.send(ModelInstall::new(request.clone(), staged, select)).await?;
+        Ok(())
+    }
+}
+
+impl Actor for ModelUploadActor {
+    async fn on_started(mut self) {
+        debug!("Model upload actor started");
+
+        loop {
+            let Ok(msg) = self.protobuf_subs_rx.recv_direct().await else { continue };
+            let result = match self.routes.decode(&msg) {
+                Some(Ok(UploadCommand::Begin(begin))) => self.begin(begin),
+                Some(Ok(UploadCommand::Chunk(chunk))) => self.chunk(chunk),
+                Some(Ok(UploadCommand::Commit)) => match self.commit(&msg).await {
+                    // answered by DetectionActor once the model is installed
+                    Ok(()) => continue,
+                    Err(e) => Err(e),
+                },
+                Some(Ok(UploadCommand::Shutdown)) => break,
+                Some(Err(e)) => Err(e),
+                None => continue,
+            };
+            if let Err(e) = &result {
+                warn!("Error handling {}: {:#}", msg.identifier, e);
+            }
+            if let Some(done) = msg.completion(&result) {
+                let _ = self.protobuf_pub_tx.broadcast(done).await;
+            }
+        }
+        self.abandon();
+        debug!("Model upload actor stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    struct Harness {
+        actor: ModelUploadActor,
+        installs: async_channel::Receiver<ModelInstall>,
+        _dir: TestDir,
+    }
+
+    fn harness(name: &str) -> Harness {
+        let dir = TestDir::new(&format!("model-upload-{}", name));
+        let config =
+            DetectionConfig { models: dir.to_path_buf(), max_chunk_kb: 1, ..DetectionConfig::default() };
+        let install = ChannelStream::new(1);
+        let installs = install.channel_receiver();
+        let actor = ModelUploadActor::new(&config, install, BroadcastStream::new(1), BroadcastStream::new(1));
+        Harness { actor, installs, _dir: dir }
+    }
+
+    fn begin(name: &str, data: &[u8]) -> ModelUploadBegin {
+        ModelUploadBegin {
+            name: name.to_string(),
+            size: data.len() as u64,
+            sha256: to_hex(&Sha256::digest(data)),
+            ..ModelUploadBegin::default()
+        }
+    }
+
+    fn chunk(offset: u64, data: &[u8]) -> ModelUploadChunk {
+        ModelUploadChunk { offset, data: data.to_vec() }
+    }
+
+    fn staged_path(actor: &ModelUploadActor) -> PathBuf {
+        actor.upload.as_ref().expect("no upload").staged.path.clone()
+    }
+
+    /// The upload has gone with its file, and nothing else is left staged
+    fn assert_abandoned(harness: &Harness, staged: &Path) {
+        assert!(harness.actor.upload.is_none());
+        assert!(!staged.exists(), "{} left behind", staged.display());
+        let left = fs::read_dir(staged.parent().unwrap()).unwrap().count();
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    fn concurrent_upload_is_refused_until_the_first_goes_idle() {
+        let mut harness = harness("concurrent");
+        let actor = &mut harness.actor;
+        actor.begin(begin("insects", b"model 1")).unwrap();
+        actor.chunk(chunk(0, b"model")).unwrap();
+        let first = staged_path(actor);
+
+        let error = actor.begin(begin("moths", b"model 2")).unwrap_err();
+        assert_eq!(error.to_string(), "Model insects is being uploaded, 5 of 7 bytes received");
+        // the first upload carries on
+        actor.chunk(chunk(5, b" 1")).unwrap();
+        assert_eq!(fs::read(&first).unwrap(), b"model 1");
+
+        // a host up for less than the idle time has no instant that far back
+        let Some(idle) = Instant::now().checked_sub(UPLOAD_IDLE) else { return };
+        actor.upload.as_mut().unwrap().active = idle;
+        actor.begin(begin("moths", b"model 2")).unwrap();
+        assert_eq!(actor.upload.as_ref().unwrap().staged.name, "moths");
+        assert!(!first.exists());
+    }
+
+    #[test]
+    fn chunk_at_the_wrong_offset_abandons_the_upload() {
+        let mut harness = harness("offset");
+        harness.actor.begin(begin("insects", b"model 1")).unwrap();
+        harness.actor.chunk(chunk(0, b"model")).unwrap();
+        let staged = staged_path(&harness.actor);
+
+        let error = harness.actor.chunk(chunk(0, b"model")).unwrap_err();
+        assert_eq!(error.to_string(), "Expected the chunk at offset 5");
+        assert_abandoned(&harness, &staged);
+        assert!(harness.actor.chunk(chunk(5, b" 1")).is_err());
+    }
+
+    #[test]
+    fn oversize_chunks_abandon_the_upload() {
+        let model = vec![1u8; 2048];
+        let mut harness = harness("oversize");
+        harness.actor.begin(begin("insects", &model)).unwrap();
+        let staged = staged_path(&harness.actor);
+        let error = harness.actor.chunk(chunk(0, &model[..1025])).unwrap_err();
+        assert_eq!(error.to_string(), "Chunks must be at most 1024 bytes");
+        assert_abandoned(&harness, &staged);
+
+        // more than the size announced
+        harness.actor.begin(begin("insects", &model[..1000])).unwrap();
+        let staged = staged_path(&harness.actor);
+        let error = harness.actor.chunk(chunk(0, &model[..1001])).unwrap_err();
+        assert_eq!(error.to_string(), "Model is larger than the 1000 bytes announced");
+        assert_abandoned(&harness, &staged);
+    }
+
+    #[tokio::test]
+    async fn commit_checks_the_whole_model_arrived_intact() {
+        let request = ProtobufMsg::new(MODEL_UPLOAD_COMMIT, Vec::new());
+        let mut harness = harness("commit");
+
+        harness.actor.begin(begin("insects", b"model 1")).unwrap();
+        harness.actor.chunk(chunk(0, b"model")).unwrap();
+        let staged = staged_path(&harness.actor);
+        let error = harness.actor.commit(&request).await.unwrap_err();
+        assert_eq!(error.to_string(), "Received 5 of 7 bytes of model insects");
+        assert_abandoned(&harness, &staged);
+
+        harness.actor.begin(begin("insects", b"model 1")).unwrap();
+        harness.actor.chunk(chunk(0, b"model 2")).unwrap();
+        let staged = staged_path(&harness.actor);
+        let error = harness.actor.commit(&request).await.unwrap_err();
+        assert!(error.to_string().starts_with("Checksum of model insects does not match"), "{}", error);
+        assert_abandoned(&harness, &staged);
+        assert!(harness.installs.try_recv().is_err());
+
+        // a model that arrived intact goes to DetectionActor to be tried
+        harness.actor.begin(begin("insects", b"model 1")).unwrap();
+        harness.actor.chunk(chunk(0, b"model 1")).unwrap();
+        let staged = staged_path(&harness.actor);
+        harness.actor.commit(&request).await.unwrap();
+        let install = harness.installs.try_recv().unwrap();
+        assert_eq!(install.staged().path, staged);
+        assert_eq!(fs::read(&staged).unwrap(), b"model 1");
+        install.staged().discard();
+    }
+}
@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_broadcast::{Receiver as BroadcastReceiver, Sender as BroadcastSender};
+use async_channel::Receiver as ChannelReceiver;
+use futures_util::{select, FutureExt};
+use log::{debug, info, warn};
+use prost::Message as PbMessage;
+
+use crate::config::values::update_section;
+use crate::config::{PreviewConfig, RegionsConfig};
+use crate::framework::actor::Actor;
+use crate::framework::streams::{BroadcastStream, ChannelStream};
+use crate::messages::config::ConfigValues;
+use crate::messages::detection_result::DetectionResult;
+use crate::messages::identifiers::*;
+use crate::messages::preview::PreviewFrame;
+use crate::messages::protobuf_msg::ProtobufMsg;
+use crate::messages::registry::Routes;
+use crate::preview::render::render_preview;
+
+enum PreviewCommand {
+    ConfigChanged(ConfigValues),
+    Shutdown,
+}
+
+/// Publishes `stream.frame` for the frames DetectionActor passes on while the
+/// trap is streaming, at no more than the configured rate.
+pub struct PreviewActor {
+    routes: Routes<PreviewCommand>,
+    config: PreviewConfig,
+    // followed from `config.changed` so the preview shows the regions in use
+    regions: RegionsConfig,
+    preview_rx: ChannelReceiver<DetectionResult>,
+    protobuf_pub_tx: BroadcastSender<ProtobufMsg>,
+    protobuf_subs_rx: BroadcastReceiver<ProtobufMsg>,
+    last_sent: Option<Instant>,
+}
+
+impl PreviewActor {
+    pub(crate) fn new(
+        config: PreviewConfig,
+        regions: RegionsConfig,
+        preview_receiver: ChannelStream<DetectionResult>,
+        protobuf_pub: BroadcastStream<ProtobufMsg>,
+        protobuf_subs: BroadcastStream<ProtobufMsg>,
+    ) -> Self {
+        Self {
+            routes: Routes::new()
+                .on(CONFIG_CHANGED, PreviewCommand::ConfigChanged)
+                .on(APP_SHUTDOWN, |()| PreviewCommand::Shutdown),
+            config,
+            regions,
+            preview_rx: preview_receiver.channel_receiver(),
+            protobuf_pub_tx: protobuf_pub.broadcast_sender(),
+            protobuf_subs_rx: protobuf_subs.broadcast_receiver(),
+            last_sent: None,
+        }
+    }
+
+    fn due(&self) -> bool {
+        let interval = Duration::from_secs_f64(1.0 / self.config.rate);
+        self.last_sent.is_none_or(|sent| sent.elapsed() >= interval)
+    }
+
+    async fn send_preview(&mut self, result: &DetectionResult) -> Result<()> {
+        if !self.due() {
+            return Ok(());
+        }
+        self.last_sent = Some(Instant::now());
+
+        let boxes = match self.config.boxes {
+            true => result.boxes(),
+            false => &[],
+        };
+        let regions = self.config.regions.then_some(&self.regions);
+        let preview = render_preview(result.frame(), boxes, regions, self.config.width, self.config.quality)?;
+        let frame = PreviewFrame {
+            timestamp: result.frame().timestamp(),
+            width: preview.width,
+            height: preview.height,
+            jpeg: preview.jpeg,
+        };
+        let msg = ProtobufMsg::new(STREAM_FRAME, frame.encode_to_vec());
+        self.protobuf_pub_tx.broadcast(msg).await?;
+        Ok(())
+    }
+}
+
+impl Actor for PreviewActor {
+    async fn on_started(mut self) {
+        debug!("Preview actor started");
+
+        loop {
+            select! {
+                res = self.protobuf_subs_rx.recv_direct().fuse() => {
+                    let Ok(msg) = res else { continue };
+                    match self.routes.decode(&msg) {
+                        Some(Ok(PreviewCommand::ConfigChanged(values))) => {
+                            match update_section(&mut self.config, "preview", &values.values) {
+                                Ok(true) => info!("Preview settings changed {:?}", self.config),
+                                Ok(false) => {}
+                                Err(e) => warn!("Failed to apply configuration {:#}", e),
+                            }
+                            if let Err(e) = update_section(&mut self.regions, "detection.regions", &values.values) {
+                                warn!("Failed to apply configuration {:#}", e);
+                            }
+                        }
+                        Some(Ok(PreviewCommand::Shutdown)) => break,
+                        Some(Err(e)) => warn!("Error handling {}: {:#}", msg.identifier, e),
+                        None => {}
+                    }
+                }
+                res = self.preview_rx.recv().fuse() => {
+                    let Ok(result) = res else {
+                        debug!("Preview channel closed");
+                        break;
+                    };
+                    if let Err(e) = self.send_preview(&result).await {
+                        warn!("Failed to send preview {:#}", e);
+                    }
+                }
+            }
+        }
+        debug!("Preview actor stopped");
+    }
+}
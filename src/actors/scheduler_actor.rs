@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use async_broadcast::{Receiver as BroadcastReceiver, Sender as BroadcastSender};
+use chrono::Local;
+use futures_util::{select, FutureExt};
+use log::{debug, info, warn};
+use prost::Message as PbMessage;
+
+use crate::config::Config;
+use crate::framework::actor::Actor;
+use crate::framework::streams::BroadcastStream;
+use crate::generated::control::State;
+use crate::messages::identifiers::*;
+use crate::messages::protobuf_msg::ProtobufMsg;
+use crate::messages::registry::Routes;
+use crate::messages::schedule::ScheduleInfo;
+use crate::schedule::{Desired, Schedule};
+use crate::state::machine::{LiveState, TrapState};
+
+/// How often the schedule is checked
+const TICK: Duration = Duration::from_secs(30);
+
+enum SchedulerCommand {
+    Get,
+    Shutdown,
+}
+
+/// Drives StateActor from the capture schedule. Requests are only sent when
+/// the schedule changes its mind, so someone switching capture by hand is not
+/// overruled until the next change.
+pub struct SchedulerActor {
+    routes: Routes<SchedulerCommand>,
+    protobuf_pub_tx: BroadcastSender<ProtobufMsg>,
+    protobuf_subs_tx: BroadcastSender<ProtobufMsg>,
+    protobuf_subs_rx: BroadcastReceiver<ProtobufMsg>,
+    schedule: Option<Schedule>,
+    last: Option<Desired>,
+    state: LiveState,
+}
+
+impl SchedulerActor {
+    pub(crate) fn new(
+        config: &Config,
+        state: LiveState,
+        protobuf_pub: BroadcastStream<ProtobufMsg>,
+        protobuf_subs: BroadcastStream<ProtobufMsg>,
+    ) -> Self {
+        let schedule = match config.schedule.enabled {
+            // validated with the rest of the configuration
+            true => Schedule::from_config(&config.schedule, &config.trap).ok(),
+            false => None,
+        };
+        Self {
+            routes: Routes::new()
+                .on(SCHEDULE_GET, |()| SchedulerCommand::Get)
+                .on(APP_SHUTDOWN, |()| SchedulerCommand::Shutdown),
+            protobuf_pub_tx: protobuf_pub.broadcast_sender(),
+            protobuf_subs_tx: protobuf_subs.broadcast_sender(),
+            protobuf_subs_rx: protobuf_subs.broadcast_receiver(),
+            schedule,
+            last: None,
+            state,
+        }
+    }
+
+    async fn request(&self, identifier: &str, state: bool) -> Result<()> {
+        let msg = ProtobufMsg::new(identifier, State { state }.encode_to_vec());
+        self.protobuf_subs_tx.broadcast(msg).await?;
+        Ok(())
+    }
+
+    async fn check_schedule(&mut self) -> Result<()> {
+        let Some(schedule) = &self.schedule else { return Ok(()) };
+        let desired = schedule.desired(Local::now());
+        if self.last == Some(desired) {
+            return Ok(());
+        }
+        let Some(state) = self.state.get() else {
+            debug!("Trap state not restored yet");
+            return Ok(());
+        };
+        info!("Schedule: capture {:?}", desired);
+
+        // the request that gets there depends on what the trap is doing, a
+        // paused trap resumes rather than starts
+        match desired {
+            Desired::Off => self.request(STATE_CAPTURE_SET, false).await?,
+            Desired::On if state == TrapState::Paused => self.request(STATE_PAUSE_SET, false).await?,
+            Desired::On => self.request(STATE_CAPTURE_SET, true).await?,
+            Desired::Paused if matches!(state, TrapState::Capturing | TrapState::Streaming) => {
+                self.request(STATE_PAUSE_SET, true).await?
+            }
+            Desired::Paused if state == TrapState::Paused => {}
+            // capture has to be running before it can be paused, so start it
+            // and pause on a later check
+            Desired::Paused => {
+                self.request(STATE_CAPTURE_SET, true).await?;
+                self.last = Some(Desired::On);
+                return Ok(());
+            }
+        }
+        self.last = Some(desired);
+        Ok(())
+    }
+
+    async fn get_schedule(&self, request: &ProtobufMsg) -> Result<()> {
+        let window = self
+            .schedule
+            .as_ref()
+            .and_then(|schedule| schedule.current_or_next(Local::now()));
+        let info = ScheduleInfo {
+            enabled: self.schedule.is_some(),
+            window_start: window.map(|w| w.start.timestamp_millis()).unwrap_or_default(),
+            window_end: window.map(|w| w.end.timestamp_millis()).unwrap_or_default(),
+        };
+        let reply = request.reply(SCHEDULE, info.encode_to_vec());
+        self.protobuf_pub_tx.broadcast(reply).await?;
+        Ok(())
+    }
+}
+
+impl Actor for SchedulerActor {
+    async fn on_started(mut self) {
+        debug!("Scheduler actor started");
+        if let Some(window) = self.schedule.as_ref().and_then(|s| s.current_or_next(Local::now())) {
+            info!("Next capture window {} to {}", window.start, window.end);
+        }
+
+        let mut ticks = tokio::time::interval(TICK);
+        loop {
+            select! {
+                _ = ticks.tick().fuse() => {
+                    if let Err(e) = self.check_schedule().await {
+                        warn!("Error applying schedule {:#}", e);
+                    }
+                }
+                res = self.protobuf_subs_rx.recv_direct().fuse() => {
+                    let Ok(msg) = res else { continue };
+                    let result = match self.routes.decode(&msg) {
+                        Some(Ok(SchedulerCommand::Get)) => self.get_schedule(&msg).await,
+                        Some(Ok(SchedulerCommand::Shutdown)) => break,
+                        Some(Err(e)) => Err(e),
+                        None => continue,
+                    };
+                    if let Err(e) = &result {
+                        warn!("Error handling {}: {:#}", msg.identifier, e);
+                    }
+                    if let Some(done) = msg.completion(&result) {
+                        let _ = self.protobuf_pub_tx.broadcast(done).await;
+                    }
+                }
+            }
+        }
+        debug!("Scheduler actor stopped");
+    }
+}
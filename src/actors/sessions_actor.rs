@@ -1,243 +1,1160 @@
-use anyhow::Result;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::{anyhow, ensure, Context as ErrContext, Result};
 use chrono::{DateTime, Local};
-use log::{debug, warn};
+use futures_util::{select, FutureExt};
+use image::RgbImage;
+use log::{debug, error, info, warn};
+use nokhwa::pixel_format::RgbFormat;
 use native_db::*;
-use native_model::{native_model, Model};
+use native_db::transaction::RwTransaction;
 use prost::Message as PbMessage;
-use serde::{Deserialize, Serialize};
 
-use crate::generated::sessions::{Detection, Session, SessionDetails};
+use crate::config::values::update_section;
+use crate::config::{ClassifierConfig, DatabaseConfig, DetectionConfig};
+use crate::database::models::{
+    DetectionModel, DetectionModelKey, SessionDetector, SessionModel, SessionModelKey, SpeciesScore,
+    StillModel, StillModelKey,
+};
+use crate::database::open_database;
+use crate::detection::classifier::Classifier;
+use crate::detection::crop::{crop_jpeg, encode_jpeg, Crop};
+use crate::detection::labels::ModelLabels;
+use crate::detection::registry::{model_name, ModelId, ModelRegistry};
+use crate::detection::yolo::BoundingBox;
+use crate::export::archive::{archive_name, write_archive};
+use crate::export::session_export::SessionExport;
+use crate::generated::control::State;
+use crate::generated::sessions::Session;
+use crate::messages::camera_frame::CameraFrame;
+use crate::messages::config::ConfigValues;
+use crate::messages::exports::{SessionArchive, SessionArchiveRead};
+use crate::messages::still_capture::StillCapture;
+use crate::messages::track_update::TrackUpdate;
+use crate::messages::identifiers::*;
 use crate::messages::protobuf_msg::ProtobufMsg;
+use crate::messages::registry::Routes;
 
 use crate::framework::actor::Actor;
-use crate::framework::streams::BroadcastStream;
+use crate::framework::streams::{BroadcastStream, ChannelStream};
 use async_broadcast::{Receiver as BroadcastReceiver, Sender as BroadcastSender};
-use once_cell::sync::Lazy;
+use async_channel::{Receiver as ChannelReceiver, RecvError, Sender as ChannelSender};
 //use futures_util::StreamExt;
 
-// ==============================================================================
-// Database models
-// ==============================================================================
-#[derive(Serialize, Deserialize, Debug)]
-#[native_model(id = 1, version = 1)]
-#[native_db]
-struct DetectionModel {
-    #[primary_key]
-    detection: i32,
-    #[secondary_key]
-    session: String,
-    created: i64,
-    updated: i64,
-    score: f32,
-    clazz: i32,
-    width: i32,
-    height: i32,
-    image: Vec<u8>,
-}
+const STILL_QUALITY: u8 = 95;
+/// Largest piece of a session archive sent in reply to `session.export.read`
+const EXPORT_CHUNK: u64 = 1024 * 1024;
 
-impl DetectionModel {
-    fn to_event(self) -> ProtobufMsg {
-        ProtobufMsg {
-            identifier: "detection".to_string(),
-            payload: Detection {
-                session: self.session,
-                detection: self.detection,
-                created: self.created,
-                updated: self.updated,
-                score: self.score,
-                clazz: self.clazz,
-                width: self.width,
-                height: self.height,
-                image: Some(self.image),
-            }
-            .encode_to_vec(),
-        }
-    }
-}
+/// Species named for a crop, with the classifier's name and version
+type Species = (Vec<SpeciesScore>, String, String);
 
-#[native_model(id = 2, version = 1)]
-#[native_db]
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct SessionModel {
-    #[primary_key]
+/// The crops of a track update cut out off the actor's thread, by track, for
+/// the tracks whose record takes a new crop
+struct CroppedTracks {
+    update: TrackUpdate,
     session: String,
-    #[secondary_key]
-    active: i32,
-    opened: i64,
-    closed: Option<i64>,
+    crops: Result<HashMap<u64, (Crop, Species)>>,
 }
 
-impl SessionModel {
-    fn to_event(self, event: &str, detections: i32) -> ProtobufMsg {
-        ProtobufMsg {
-            identifier: event.to_string(),
-            payload: SessionDetails {
-                session: self.session,
-                active: self.active == 1,
-                opened: self.opened,
-                closed: self.closed,
-                detections,
-            }
-            .encode_to_vec(),
-        }
-    }
+enum SessionCommand {
+    Open,
+    Close,
+    SetState(State),
+    Resume(State),
+    All,
+    Export(Session),
+    ExportRead(SessionArchiveRead),
+    Detections(Session),
+    Stills(Session),
+    ConfigChanged(ConfigValues),
 }
-// ==============================================================================
-// Database
-// ==============================================================================
-
-static MODELS: Lazy<Models> = Lazy::new(|| {
-    let mut models = Models::new();
-    models.define::<SessionModel>().unwrap();
-    models.define::<DetectionModel>().unwrap();
-    models
-});
 
 pub struct SessionsActor {
+    routes: Routes<SessionCommand>,
+    track_rx: ChannelReceiver<TrackUpdate>,
+    still_rx: ChannelReceiver<StillCapture>,
     protobuf_pub_tx: BroadcastSender<ProtobufMsg>,
     protobuf_subs_rx: BroadcastReceiver<ProtobufMsg>,
-    db: Database<'static>,
+    // shared with the tasks that build session archives
+    db: Arc<Database<'static>>,
+    active_session: Option<String>,
+    // session archives are built here and read from by the clients
+    exports: PathBuf,
+    // keeps two exports of the same session from building into one file
+    next_export: u64,
+    next_detection: i32,
+    next_still: i32,
+    // detection record for each live track in the active session
+    track_detections: HashMap<u64, i32>,
+    // a track update is being cropped, the next is read once it is stored
+    cropping: bool,
+    cropped_tx: ChannelSender<Result<CroppedTracks>>,
+    cropped_rx: ChannelReceiver<Result<CroppedTracks>>,
+    registry: ModelRegistry,
+    // names the classes of detections stored before models were recorded
+    model: String,
+    classifier_config: ClassifierConfig,
+    // names the species in each crop that is kept, if one is set up
+    classifier: Option<(ModelId, Arc<Mutex<Classifier>>)>,
 }
 
 impl SessionsActor {
     pub(crate) fn new(
+        track_receiver: ChannelStream<TrackUpdate>,
+        still_receiver: ChannelStream<StillCapture>,
         protobuf_pub: BroadcastStream<ProtobufMsg>,
         protobuf_subs: BroadcastStream<ProtobufMsg>,
-        db_location: String,
-    ) -> Self {
-        Self {
+        config: &DatabaseConfig,
+        detection: &DetectionConfig,
+    ) -> Result<Self> {
+        let db = open_database(&config.path)
+            .with_context(|| format!("Failed to open database '{}'", config.path))?;
+        let (cropped_tx, cropped_rx) = async_channel::unbounded();
+        Ok(Self {
+            routes: Routes::new()
+                .on(SESSION_OPEN, |()| SessionCommand::Open)
+                .on(SESSION_CLOSE, |()| SessionCommand::Close)
+                .on(SESSION_STATE_SET, SessionCommand::SetState)
+                .on(SESSION_RESUME, SessionCommand::Resume)
+                .on(SESSION_ALL, |()| SessionCommand::All)
+                .on(SESSION_EXPORT, SessionCommand::Export)
+                .on(SESSION_EXPORT_READ, SessionCommand::ExportRead)
+                .on(SESSION_DETECTIONS, SessionCommand::Detections)
+                .on(SESSION_STILLS, SessionCommand::Stills)
+                .on(CONFIG_CHANGED, SessionCommand::ConfigChanged),
+            track_rx: track_receiver.channel_receiver(),
+            still_rx: still_receiver.channel_receiver(),
             protobuf_pub_tx: protobuf_pub.broadcast_sender(),
             protobuf_subs_rx: protobuf_subs.broadcast_receiver(),
-            db: Builder::new()
-                .create(&MODELS, db_location)
-                .expect("Failed to create database"),
+            db: Arc::new(db),
+            active_session: None,
+            exports: config.exports.clone(),
+            next_export: 0,
+            next_detection: 0,
+            next_still: 0,
+            track_detections: HashMap::new(),
+            cropping: false,
+            cropped_tx,
+            cropped_rx,
+            registry: ModelRegistry::new(&detection.models),
+            model: detection.model.clone(),
+            classifier_config: detection.classifier.clone(),
+            classifier: None,
+        })
+    }
+
+    /// Load the classifier named in the configuration, or drop the running
+    /// one when none is
+    async fn load_classifier(&mut self) -> Result<()> {
+        let config = self.classifier_config.clone();
+        if config.model.is_empty() {
+            if let Some((id, _)) = self.classifier.take() {
+                info!("Stopped classifying with {}", id);
+            }
+            return Ok(());
         }
+        let name = model_name(&config.model).to_string();
+        if let Some((id, _)) = self.classifier.as_ref().filter(|(id, _)| id.name == name) {
+            // applied with the next crop, the classifier may be busy
+            debug!("Keeping the top {} species from classifier {}", config.top_k, id);
+            return Ok(());
+        }
+
+        let registry = self.registry.clone();
+        let (id, classifier) = tokio::task::spawn_blocking(move || {
+            let _reading = registry.read();
+            let entry = registry.find(&name)?;
+            let classifier = Classifier::load(&entry.path, config.top_k)?;
+            Ok::<_, anyhow::Error>((entry.id, classifier))
+        })
+        .await
+        .unwrap_or_else(|e| Err(anyhow!("Loading stopped: {}", e)))
+        .context("Failed to load classifier")?;
+        info!("Classifying species with {}", id);
+        self.classifier = Some((id, Arc::new(Mutex::new(classifier))));
+        Ok(())
     }
 
-    async fn open_session(&mut self) -> Result<()> {
-        debug!("Opening session");
-        let local_now: DateTime<Local> = Local::now();
-        let session_id = local_now.format("%Y%m%d%H%M%S").to_string();
-        let opened = local_now.timestamp_millis();
+    async fn config_changed(&mut self, values: ConfigValues) -> Result<()> {
+        if update_section(&mut self.classifier_config, "detection.classifier", &values.values)? {
+            self.load_classifier().await?;
+        }
+        Ok(())
+    }
+
+    /// Pick up the active session and the id counters from the database
+    fn load_state(&mut self) -> Result<()> {
+        let r = self.db.r_transaction()?;
+
+        self.active_session = None;
+        for active in r.scan().secondary::<SessionModel>(SessionModelKey::active)?.range(1..=1)? {
+            let session: SessionModel = active?;
+            self.active_session = Some(session.session);
+        }
+
+        self.next_detection = match r.scan().primary::<DetectionModel>()?.all()?.next_back() {
+            Some(last) => last?.detection + 1,
+            None => 1,
+        };
+        self.next_still = match r.scan().primary::<StillModel>()?.all()?.next_back() {
+            Some(last) => last?.still + 1,
+            None => 1,
+        };
+        Ok(())
+    }
+
+    /// Store a record for each new track and keep the records of continuing
+    /// tracks up to date with the latest sighting and the best crop so far.
+    /// New and improved tracks are cropped and classified on a blocking
+    /// thread, and the records are written once the crops come back.
+    fn store_tracks(&mut self, update: TrackUpdate) -> Result<()> {
+        let tracking = update.tracking();
+        for track in &tracking.ended {
+            self.track_detections.remove(track);
+        }
+
+        let session = match &self.active_session {
+            Some(session) if !tracking.tracked.is_empty() => session.clone(),
+            _ => return Ok(()),
+        };
+
+        let r = self.db.r_transaction()?;
+        let mut improved = Vec::new();
+        for tracked in &tracking.tracked {
+            // a continuing track without a record started before the session did
+            let existing: Option<DetectionModel> = match self.track_detections.get(&tracked.track) {
+                Some(detection) if !tracked.is_new => r.get().primary(*detection)?,
+                _ => None,
+            };
+            if existing.is_none_or(|model| tracked.bbox.score > model.score) {
+                improved.push((tracked.track, tracked.bbox));
+            }
+        }
+        drop(r);
+
+        self.cropping = true;
+        let classifier = self.classifier.clone();
+        let top_k = self.classifier_config.top_k;
+        let cropped_tx = self.cropped_tx.clone();
+        tokio::spawn(async move {
+            let cropped = tokio::task::spawn_blocking(move || {
+                let crops = Self::crop_tracks(update.frame(), &improved, classifier, top_k);
+                CroppedTracks { update, session, crops }
+            })
+            .await
+            .map_err(|e| anyhow!("Cropping stopped: {}", e));
+            let _ = cropped_tx.send(cropped).await;
+        });
+        Ok(())
+    }
+
+    /// Cut out and classify the tracks whose record takes a new crop
+    fn crop_tracks(
+        frame: &CameraFrame,
+        improved: &[(u64, BoundingBox)],
+        classifier: Option<(ModelId, Arc<Mutex<Classifier>>)>,
+        top_k: usize,
+    ) -> Result<HashMap<u64, (Crop, Species)>> {
+        if improved.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let image = frame.buffer().decode_image::<RgbFormat>().context("Failed to decode frame")?;
+        let mut crops = HashMap::new();
+        for (track, bbox) in improved {
+            let species = Self::classify(classifier.as_ref(), top_k, &image, bbox);
+            crops.insert(*track, (crop_jpeg(&image, bbox)?, species));
+        }
+        Ok(crops)
+    }
+
+    /// Write the records of a track update once its crops are ready
+    async fn tracks_cropped(&mut self, cropped: Result<CroppedTracks>) -> Result<()> {
+        self.cropping = false;
+        let CroppedTracks { update, session, crops } = cropped?;
+        let mut crops = crops?;
+        if self.active_session.as_ref() != Some(&session) {
+            debug!("Session {} closed while its tracks were cropped", session);
+            return Ok(());
+        }
+
+        let timestamp = update.frame().timestamp();
+        let model = update.model();
+        let mut new_detections = Vec::new();
 
         let rw = self.db.rw_transaction()?;
+        Self::record_model(&rw, &session, model)?;
+        for tracked in &update.tracking().tracked {
+            let existing = match self.track_detections.get(&tracked.track) {
+                Some(detection) if !tracked.is_new => rw.get().primary::<DetectionModel>(*detection)?,
+                _ => None,
+            };
+            let crop = crops.remove(&tracked.track);
 
-        let mut close_event: Option<ProtobufMsg> = None;
+            match existing {
+                Some(orig) => {
+                    let mut new = orig.clone();
+                    new.updated = timestamp;
+                    if let Some((crop, species)) = crop {
+                        new.score = tracked.bbox.score;
+                        new.clazz = tracked.bbox.clazz;
+                        new.width = crop.width as i32;
+                        new.height = crop.height as i32;
+                        new.image = crop.jpeg;
+                        new.model = model.name.clone();
+                        new.model_version = model.version.clone();
+                        (new.species, new.classifier, new.classifier_version) = species;
+                    }
+                    rw.update(orig, new)?;
+                }
+                None => {
+                    let (crop, (species, classifier, classifier_version)) =
+                        crop.context("Missing crop for new track")?;
+                    let model = DetectionModel {
+                        detection: self.next_detection,
+                        session: session.clone(),
+                        created: timestamp,
+                        updated: timestamp,
+                        score: tracked.bbox.score,
+                        clazz: tracked.bbox.clazz,
+                        width: crop.width as i32,
+                        height: crop.height as i32,
+                        image: crop.jpeg,
+                        model: model.name.clone(),
+                        model_version: model.version.clone(),
+                        species,
+                        classifier,
+                        classifier_version,
+                    };
+                    self.track_detections.insert(tracked.track, self.next_detection);
+                    self.next_detection += 1;
+                    rw.insert(model.clone())?;
+                    new_detections.push(model);
+                }
+            }
+        }
+        rw.commit()?;
 
-        // Check to see if there is an active session
-        // If so, set it to inactive and send close event
-        for active in rw.scan().secondary(SessionModelKey::active)?.range(1..1)? {
-            let orig: SessionModel = active?;
+        for model in new_detections {
+            self.protobuf_pub_tx.broadcast(model.to_event()).await?;
+        }
+        Ok(())
+    }
+
+    /// The next track update, none while the last is still being cropped
+    async fn next_update(
+        track_rx: &ChannelReceiver<TrackUpdate>,
+        cropping: bool,
+    ) -> Result<TrackUpdate, RecvError> {
+        if cropping {
+            std::future::pending().await
+        } else {
+            track_rx.recv().await
+        }
+    }
+
+    /// Store a still taken by CameraActor with the active session and reply
+    /// to the request with a reference to it
+    async fn store_still(&mut self, still: &StillCapture) -> Result<()> {
+        let image = still
+            .frame()
+            .buffer()
+            .decode_image::<RgbFormat>()
+            .context("Failed to decode still")?;
+        let model = StillModel {
+            still: self.next_still,
+            session: self.active_session.clone().unwrap_or_default(),
+            created: still.frame().timestamp(),
+            width: image.width() as i32,
+            height: image.height() as i32,
+            image: encode_jpeg(&image, STILL_QUALITY).context("Failed to encode still")?,
+        };
+
+        let rw = self.db.rw_transaction()?;
+        rw.insert(model.clone())?;
+        rw.commit()?;
+        self.next_still += 1;
+        info!("Stored still {} ({}x{})", model.still, model.width, model.height);
+
+        let reply = model.into_event(false).in_reply_to(still.request());
+        self.protobuf_pub_tx.broadcast(reply).await?;
+        Ok(())
+    }
+
+    /// Add the model to the session the first time it finds an insect there
+    fn record_model(rw: &RwTransaction, session: &str, model: &ModelId) -> Result<()> {
+        let orig: SessionModel = rw
+            .get()
+            .primary(session.to_string())?
+            .with_context(|| format!("No session {}", session))?;
+        let used = SessionDetector { name: model.name.clone(), version: model.version.clone() };
+        if !orig.models.contains(&used) {
             let mut new = orig.clone();
-            new.active = 0;
-            new.closed = Option::from(opened);
+            new.models.push(used);
+            rw.update(orig, new)?;
+        }
+        Ok(())
+    }
 
-            let detections = rw
+    /// Species the classifier names for a crop that is kept, with the
+    /// classifier's name and version. All empty without a classifier, or when
+    /// it fails on the crop.
+    fn classify(
+        classifier: Option<&(ModelId, Arc<Mutex<Classifier>>)>,
+        top_k: usize,
+        image: &RgbImage,
+        bbox: &BoundingBox,
+    ) -> Species {
+        let Some((id, classifier)) = classifier else {
+            return (Vec::new(), String::new(), String::new());
+        };
+        let mut classifier = classifier.lock().unwrap_or_else(PoisonError::into_inner);
+        classifier.set_top_k(top_k);
+        match classifier.classify(image, bbox) {
+            Ok(species) => (
+                species
+                    .into_iter()
+                    .map(|species| SpeciesScore { label: species.label, score: species.score })
+                    .collect(),
+                id.name.clone(),
+                id.version.clone(),
+            ),
+            Err(e) => {
+                warn!("Classification failed {:#}", e);
+                (Vec::new(), String::new(), String::new())
+            }
+        }
+    }
+
+    /// Close every session still marked active. Sessions are stamped with `closed_at`,
+    /// or with their last sighting when recovering sessions left open by a restart.
+    fn close_active(rw: &RwTransaction, closed_at: Option<i64>) -> Result<Vec<ProtobufMsg>> {
+        let mut close_events = Vec::new();
+
+        let active: Vec<SessionModel> = rw
+            .scan()
+            .secondary(SessionModelKey::active)?
+            .range(1..=1)?
+            .collect::<Result<_, _>>()?;
+
+        for orig in active {
+            let mut detections = 0;
+            let mut last_seen = orig.opened;
+            for res in rw
                 .scan()
                 .secondary::<DetectionModel>(DetectionModelKey::session)?
-                .start_with(orig.session.clone())
-                .iter()
-                .count() as i32;
-            close_event = Some(new.clone().to_event("session.closed", detections));
-            rw.update(orig.clone(), new.clone())?;
+                .range(orig.session.clone()..=orig.session.clone())? {
+                let detection = res?;
+                detections += 1;
+                last_seen = last_seen.max(detection.updated);
+            }
+
+            let mut new = orig.clone();
+            new.active = 0;
+            new.closed = Some(closed_at.unwrap_or(last_seen));
+            close_events.push(new.clone().to_event(SESSION_CLOSED, detections));
+            rw.update(orig, new)?;
         }
+        Ok(close_events)
+    }
+
+    /// A session id from the time it opened. Capture stopped and started again
+    /// within a second gets the same time, so later sessions in that second
+    /// have `-1`, `-2` and so on added.
+    fn session_id(rw: &RwTransaction, opened: DateTime<Local>) -> Result<String> {
+        let base = opened.format("%Y%m%d%H%M%S").to_string();
+        let mut session_id = base.clone();
+        let mut n = 0;
+        while rw.get().primary::<SessionModel>(session_id.clone())?.is_some() {
+            n += 1;
+            session_id = format!("{}-{}", base, n);
+        }
+        Ok(session_id)
+    }
+
+    async fn open_session(&mut self, local_now: DateTime<Local>) -> Result<()> {
+        debug!("Opening session");
+        let opened = local_now.timestamp_millis();
+
+        let rw = self.db.rw_transaction()?;
+
+        // Check to see if there is an active session
+        // If so, set it to inactive and send close event
+        let close_events = Self::close_active(&rw, Some(opened))?;
+        let session_id = Self::session_id(&rw, local_now)?;
 
         let session = SessionModel {
             session: session_id,
             active: 1,
             opened,
             closed: None,
+            models: Vec::new(),
         };
         rw.insert(session.clone())?;
         rw.commit()?;
+        self.active_session = Some(session.session.clone());
+        self.track_detections.clear();
 
         // send the events after committing
-        if close_event.is_some() {
-            let msg = close_event.unwrap();
+        for msg in close_events {
             self.protobuf_pub_tx.broadcast(msg).await?;
         }
 
         debug!("Session opened");
-        let add_event = session.to_event("session.opened", 0);
+        let add_event = session.to_event(SESSION_OPENED, 0);
         self.protobuf_pub_tx.broadcast(add_event).await?;
 
         Ok(())
     }
 
-    async fn all_sessions(&mut self) -> Result<()> {
+    async fn close_session(&mut self, closed_at: Option<i64>) -> Result<()> {
+        debug!("Closing session");
+        let rw = self.db.rw_transaction()?;
+        let close_events = Self::close_active(&rw, closed_at)?;
+        rw.commit()?;
+        self.active_session = None;
+        self.track_detections.clear();
+
+        for msg in close_events {
+            // at shutdown the WebSocket server may have stopped already, and
+            // a broadcast with nobody listening would wait for ever
+            if self.protobuf_pub_tx.receiver_count() == 0 {
+                break;
+            }
+            self.protobuf_pub_tx.broadcast(msg).await?;
+        }
+        debug!("Session closed");
+        Ok(())
+    }
+
+    /// Capture switched on or off in StateActor
+    async fn set_session_state(&mut self, state: State) -> Result<()> {
+        match (state.state, &self.active_session) {
+            (true, None) => self.open_session(Local::now()).await,
+            (true, Some(session)) => {
+                debug!("Session {} already active", session);
+                Ok(())
+            }
+            (false, _) => self.close_session(Some(Local::now().timestamp_millis())).await,
+        }
+    }
+
+    /// Sent by StateActor on start with the capture state saved before the restart.
+    /// A session left active carries on if capture was on, otherwise it is closed
+    /// at its last sighting.
+    async fn resume_session(&mut self, state: State) -> Result<()> {
+        match (state.state, &self.active_session) {
+            (true, Some(session)) => {
+                info!("Continuing session {}", session);
+                Ok(())
+            }
+            (true, None) => self.open_session(Local::now()).await,
+            (false, _) => self.close_session(None).await,
+        }
+    }
+
+    async fn all_sessions(&mut self, request: &ProtobufMsg) -> Result<()> {
         debug!("Reading sessions from database");
 
         let r = self.db.r_transaction()?;
 
         // Get all values
         for res in r.scan().primary::<SessionModel>()?.all()? {
-            let session = res?;
-            let details_event = session.to_event("session.details", 0);
+            let session: SessionModel = res?;
+            let detections = r
+                .scan()
+                .secondary::<DetectionModel>(DetectionModelKey::session)?
+                .range(session.session.clone()..=session.session.clone())?
+                .count() as i32;
+            let details_event = session
+                .to_event(SESSION_DETAILS, detections)
+                .in_reply_to(request);
             self.protobuf_pub_tx.broadcast(details_event).await?;
         }
         debug!("Finished reading sessions from database");
         Ok(())
     }
+
+    async fn session_detections(&mut self, sess: Session, request: &ProtobufMsg) -> Result<()> {
+        ensure!(!sess.session.is_empty(), "No session given");
+        let r = self.db.r_transaction()?;
+        for res in r
+            .scan()
+            .secondary::<DetectionModel>(DetectionModelKey::session)?
+            .range(sess.session.clone()..=sess.session)? {
+            let model = res?;
+            let detection_event = model.to_event().in_reply_to(request);
+            self.protobuf_pub_tx.broadcast(detection_event).await?;
+        }
+        Ok(())
+    }
+
+    /// Stills of exactly this session, an empty session gives the stills taken
+    /// while no session was open
+    async fn session_stills(&mut self, sess: Session, request: &ProtobufMsg) -> Result<()> {
+        let r = self.db.r_transaction()?;
+        for res in r
+            .scan()
+            .secondary::<StillModel>(StillModelKey::session)?
+            .range(sess.session.clone()..=sess.session)? {
+            let model = res?;
+            let still_event = model.into_event(true).in_reply_to(request);
+            self.protobuf_pub_tx.broadcast(still_event).await?;
+        }
+        Ok(())
+    }
+
+    /// Remove the archives left behind by downloads that never finished
+    fn clear_exports(&self) -> Result<()> {
+        fs::create_dir_all(&self.exports)
+            .with_context(|| format!("Failed to create {}", self.exports.display()))?;
+        for entry in fs::read_dir(&self.exports)? {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            if name.starts_with("session-") && (name.ends_with(".zip") || name.ends_with(".part")) {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the archive into the exports directory on a task of its own, so
+    /// detections keep being stored while the crops are zipped, and answer
+    /// with its size. The client then reads it with `session.export.read`.
+    fn export_session(&mut self, sess: Session, request: ProtobufMsg) {
+        debug!("Exporting session {}", sess.session);
+        let db = self.db.clone();
+        let labels = ModelLabels::new(self.registry.clone(), &self.model);
+        let protobuf_pub_tx = self.protobuf_pub_tx.clone();
+        let path = self.exports.join(archive_name(&sess.session));
+        let part = path.with_extension(format!("zip.{}.part", self.next_export));
+        self.next_export += 1;
+        tokio::spawn(async move {
+            let session = sess.session.clone();
+            let result = async {
+                let size = tokio::task::spawn_blocking(move || {
+                    Self::build_archive(&db, &sess.session, labels, &part, &path)
+                })
+                .await
+                .unwrap_or_else(|e| Err(anyhow!("Export stopped: {}", e)))?;
+                info!("Built archive of session {}, {} bytes", session, size);
+                let archive = SessionArchive {
+                    filename: archive_name(&session),
+                    session: session.clone(),
+                    data: Vec::new(),
+                    offset: 0,
+                    size,
+                };
+                protobuf_pub_tx.broadcast(request.reply(SESSION_EXPORTED, archive.encode_to_vec())).await?;
+                Ok(())
+            }
+            .await;
+            if let Err(e) = &result {
+                warn!("Error exporting session {}: {:#}", session, e);
+            }
+            if let Some(done) = request.completion(&result) {
+                let _ = protobuf_pub_tx.broadcast(done).await;
+            }
+        });
+    }
+
+    /// Write the archive beside its final path and move it there once it is
+    /// complete, so a half built archive is never read
+    fn build_archive(
+        db: &Database,
+        session: &str,
+        mut labels: ModelLabels,
+        part: &Path,
+        path: &Path,
+    ) -> Result<u64> {
+        let mut build = || -> Result<u64> {
+            let export = SessionExport::load(db, session)?;
+            let file = File::create(part)
+                .with_context(|| format!("Failed to create {}", part.display()))?;
+            let file = write_archive(db, &export, &mut labels, file)?;
+            file.sync_all()?;
+            let size = file.metadata()?.len();
+            fs::rename(part, path)?;
+            Ok(size)
+        };
+        let result = build();
+        if result.is_err() {
+            let _ = fs::remove_file(part);
+        }
+        result
+    }
+
+    /// Send the piece of a built archive at the offset asked for. The archive
+    /// is removed once its last piece has been read.
+    async fn read_export(&mut self, read: SessionArchiveRead, request: &ProtobufMsg) -> Result<()> {
+        // session ids come from the database, never a path from the client
+        let known: Option<SessionModel> = self.db.r_transaction()?.get().primary(read.session.clone())?;
+        ensure!(known.is_some(), "Unknown session '{}'", read.session);
+
+        let path = self.exports.join(archive_name(&read.session));
+        let mut file = File::open(&path)
+            .with_context(|| format!("No archive of session {}, export it first", read.session))?;
+        let size = file.metadata()?.len();
+        ensure!(read.offset <= size, "Offset {} is past the end of the {} byte archive", read.offset, size);
+
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(read.offset))?;
+        file.take(EXPORT_CHUNK).read_to_end(&mut data)?;
+        let end = read.offset + data.len() as u64;
+        let archive = SessionArchive {
+            filename: archive_name(&read.session),
+            session: read.session,
+            data,
+            offset: read.offset,
+            size,
+        };
+        let reply = request.reply(SESSION_EXPORTED, archive.encode_to_vec());
+        self.protobuf_pub_tx.broadcast(reply).await?;
+
+        if end == size {
+            info!("Session {} downloaded", archive.session);
+            fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    async fn handle_message(&mut self, msg: ProtobufMsg) {
+        let result = match self.routes.decode(&msg) {
+            Some(Ok(SessionCommand::Open)) => {
+                debug!("Open session received");
+                self.open_session(Local::now()).await
+            }
+            Some(Ok(SessionCommand::Close)) => {
+                debug!("Close session received");
+                self.close_session(Some(Local::now().timestamp_millis())).await
+            }
+            Some(Ok(SessionCommand::SetState(state))) => self.set_session_state(state).await,
+            Some(Ok(SessionCommand::Resume(state))) => self.resume_session(state).await,
+            Some(Ok(SessionCommand::All)) => {
+                debug!("Received sessions.all");
+                self.all_sessions(&msg).await
+            }
+            Some(Ok(SessionCommand::Export(session))) => {
+                // answered once the archive has been built
+                self.export_session(session, msg);
+                return;
+            }
+            Some(Ok(SessionCommand::ExportRead(read))) => self.read_export(read, &msg).await,
+            Some(Ok(SessionCommand::Detections(session))) => {
+                debug!("Received session.detections");
+                self.session_detections(session, &msg).await
+            }
+            Some(Ok(SessionCommand::Stills(session))) => self.session_stills(session, &msg).await,
+            Some(Ok(SessionCommand::ConfigChanged(values))) => self.config_changed(values).await,
+            Some(Err(e)) => Err(e),
+            None => return,
+        };
+
+        if let Err(e) = &result {
+            warn!("Error handling {}: {:#}", msg.identifier, e);
+        }
+        if let Some(done) = msg.completion(&result) {
+            let _ = self.protobuf_pub_tx.broadcast(done).await;
+        }
+    }
 }
 
 impl Actor for SessionsActor {
     async fn on_started(mut self) {
         debug!("Sessions actor started");
 
+        // A session still active at this point was left open by a crash or power
+        // loss, StateActor tells us whether to continue or close it
+        if let Err(e) = self.load_state() {
+            warn!("Error reading sessions from database {}", e);
+        }
+        if let Err(e) = self.load_classifier().await {
+            error!("{:#}", e);
+        }
+        if let Err(e) = self.clear_exports() {
+            warn!("Error clearing session archives {:#}", e);
+        }
+
         loop {
-            let res = self.protobuf_subs_rx.recv_direct().await;
-            match res {
-                Ok(msg) => {
-                    match msg.identifier.as_str() {
-                        "session.open" => {
-                            debug!("Open session received");
-                            let result = self.open_session().await;
-                            match result {
-                                Ok(_) => {
-                                    debug!("Finished adding session to database");
-                                }
-                                Err(e) => {
-                                    warn!("Error adding session to database {}", e);
-                                }
+            select! {
+                res = self.protobuf_subs_rx.recv_direct().fuse() => {
+                    if let Ok(msg) = res {
+                        self.handle_message(msg).await;
+                    }
+                }
+                res = self.still_rx.recv().fuse() => {
+                    // CameraActor never closes this one, stills can be taken until the end
+                    if let Ok(still) = res {
+                        let result = self.store_still(&still).await;
+                        if let Err(e) = &result {
+                            warn!("Error storing still {:#}", e);
+                        }
+                        if let Some(done) = still.request().completion(&result) {
+                            let _ = self.protobuf_pub_tx.broadcast(done).await;
+                        }
+                    }
+                }
+                res = self.cropped_rx.recv().fuse() => {
+                    if let Ok(cropped) = res {
+                        if let Err(e) = self.tracks_cropped(cropped).await {
+                            warn!("Error adding detections to database {:#}", e);
+                        }
+                    }
+                }
+                res = Self::next_update(&self.track_rx, self.cropping).fuse() => {
+                    match res {
+                        Ok(update) => {
+                            if let Err(e) = self.store_tracks(update) {
+                                warn!("Error adding detections to database {:#}", e);
                             }
                         }
+                        // closed by TrackingActor on shutdown once every track is stored
+                        Err(_) => break,
+                    }
+                }
+            }
+        }
+
+        if let Err(e) = self.close_session(Some(Local::now().timestamp_millis())).await {
+            warn!("Error closing session {:#}", e);
+        }
+        debug!("Sessions actor stopped");
+    }
+}
 
-                        "session.close" => {}
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::time::Duration;
 
-                        "session.all" => {
-                            debug!("Received sessions.all");
-                            self.all_sessions().await.unwrap();
-                        }
+    use super::*;
+    use crate::generated::sessions::{Detection, SessionDetails};
+    use crate::sources::frame_source::FrameSource;
+    use crate::sources::synthetic_source::SyntheticSource;
+    use crate::tracking::sort::{TrackedBox, TrackingResult};
+    use crate::messages::envelope::EnvelopeStatus;
+    use crate::test_dir::TestDir;
 
-                        "session.detections" => {
-                            debug!("Received session.detections");
-                            let sess = Session::decode(&msg.payload[..]).unwrap();
-                            //let r = state.lock().unwrap().db.r_transaction().unwrap();
-
-                            // Get all values
-                            for res in self
-                                .db.r_transaction()
-                                .unwrap()
-                                .scan()
-                                .secondary::<DetectionModel>(DetectionModelKey::session)
-                                .unwrap()
-                                .start_with(sess.session)
-                                .unwrap() {
-
-                                let model = res.unwrap();
-                                let detection_event = model.to_event();
-                                self.protobuf_pub_tx
-                                    .broadcast(detection_event)
-                                    .await
-                                    .unwrap();
-                            }
-                        },
-                        &_ => { }
-                    }
-                    //_ => {}
+    /// An actor on a fresh database in `dir`, with a receiver for what it publishes
+    fn sessions_actor(dir: &Path) -> (SessionsActor, BroadcastReceiver<ProtobufMsg>) {
+        let config = DatabaseConfig {
+            path: dir.join("trap.db").to_string_lossy().to_string(),
+            state: dir.join("state.json"),
+            exports: dir.join("exports"),
+        };
+        let protobuf_pub = BroadcastStream::new(100);
+        let events = protobuf_pub.broadcast_receiver();
+        let actor = SessionsActor::new(
+            ChannelStream::new(1),
+            ChannelStream::new(1),
+            protobuf_pub,
+            BroadcastStream::new(1),
+            &config,
+            &DetectionConfig::default(),
+        )
+        .unwrap();
+        actor.clear_exports().unwrap();
+        (actor, events)
+    }
+
+    fn detection(detection: i32, session: &str, image: Vec<u8>) -> DetectionModel {
+        DetectionModel {
+            detection,
+            session: session.to_string(),
+            created: 1_000,
+            updated: 2_000,
+            score: 0.9,
+            clazz: 0,
+            width: 10,
+            height: 10,
+            image,
+            model: String::new(),
+            model_version: String::new(),
+            species: Vec::new(),
+            classifier: String::new(),
+            classifier_version: String::new(),
+        }
+    }
+
+    fn request(identifier: &str, payload: Vec<u8>, correlation: u64) -> ProtobufMsg {
+        let mut msg = ProtobufMsg::new(identifier, payload);
+        msg.correlation = Some(correlation);
+        msg
+    }
+
+    /// The replies to a request, up to and including its End or Error
+    async fn replies(events: &mut BroadcastReceiver<ProtobufMsg>, correlation: u64) -> Vec<ProtobufMsg> {
+        let mut replies = Vec::new();
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(10), events.recv())
+                .await
+                .expect("no reply")
+                .unwrap();
+            if msg.correlation == Some(correlation) {
+                let finished = msg.status != EnvelopeStatus::Message;
+                replies.push(msg);
+                if finished {
+                    return replies;
                 }
-                Err(_) => {}
             }
         }
     }
+
+    #[tokio::test]
+    async fn export_is_built_to_a_file_and_read_in_pieces() {
+        let dir = TestDir::new("sessions-export");
+        let (mut actor, mut events) = sessions_actor(&dir);
+        actor.open_session(Local::now()).await.unwrap();
+        let session = actor.active_session.clone().unwrap();
+        // larger than one piece, the crops are stored uncompressed
+        let crop = vec![7u8; EXPORT_CHUNK as usize + 1000];
+        let rw = actor.db.rw_transaction().unwrap();
+        rw.insert(detection(1, &session, crop.clone())).unwrap();
+        rw.commit().unwrap();
+
+        let export = Session { session: session.clone() }.encode_to_vec();
+        actor.handle_message(request(SESSION_EXPORT, export, 1)).await;
+        let built = replies(&mut events, 1).await;
+        assert_eq!(built.len(), 2);
+        assert_eq!(built[1].status, EnvelopeStatus::End);
+        let size = SessionArchive::decode(&built[0].payload[..]).unwrap().size;
+        let path = dir.join("exports").join(archive_name(&session));
+        assert_eq!(fs::metadata(&path).unwrap().len(), size);
+
+        let mut data = Vec::new();
+        let mut correlation = 2;
+        while (data.len() as u64) < size {
+            let read = SessionArchiveRead { session: session.clone(), offset: data.len() as u64 };
+            actor.handle_message(request(SESSION_EXPORT_READ, read.encode_to_vec(), correlation)).await;
+            let read = replies(&mut events, correlation).await;
+            assert_eq!(read.len(), 2);
+            assert_eq!(read[1].status, EnvelopeStatus::End);
+            let piece = SessionArchive::decode(&read[0].payload[..]).unwrap();
+            assert_eq!((piece.offset, piece.size), (data.len() as u64, size));
+            data.extend(piece.data);
+            correlation += 1;
+        }
+        assert_eq!(correlation, 4, "expected two pieces");
+        assert!(!path.exists(), "archive kept after the last piece was read");
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(data)).unwrap();
+        let mut image = Vec::new();
+        zip.by_name("images/000001.jpg").unwrap().read_to_end(&mut image).unwrap();
+        assert_eq!(image, crop);
+
+        // only sessions in the database name an archive
+        let read = SessionArchiveRead { session: "../trap.db".to_string(), offset: 0 };
+        actor.handle_message(request(SESSION_EXPORT_READ, read.encode_to_vec(), 9)).await;
+        assert_eq!(replies(&mut events, 9).await[0].status, EnvelopeStatus::Error);
+    }
+
+    fn tracked(track: u64, score: f32, is_new: bool) -> TrackedBox {
+        let bbox = BoundingBox { x1: 4.0, y1: 4.0, x2: 20.0, y2: 12.0, score, clazz: track as i32 };
+        TrackedBox { track, bbox, is_new }
+    }
+
+    /// Store a track update the way the actor's loop does, waiting for its crops
+    async fn store(actor: &mut SessionsActor, frame: &CameraFrame, tracked: Vec<TrackedBox>) {
+        let tracking = TrackingResult { tracked, ended: Vec::new() };
+        let model = Arc::new(ModelId { name: "insects".to_string(), version: "1".to_string() });
+        actor.store_tracks(TrackUpdate::new(frame.clone(), tracking, model)).unwrap();
+        assert!(actor.cropping);
+        let cropped = tokio::time::timeout(Duration::from_secs(10), actor.cropped_rx.recv())
+            .await
+            .expect("tracks never cropped")
+            .unwrap();
+        actor.tracks_cropped(cropped).await.unwrap();
+        assert!(!actor.cropping);
+    }
+
+    #[tokio::test]
+    async fn tracks_are_cropped_off_the_loop_and_stored() {
+        let dir = TestDir::new("sessions-tracks");
+        let (mut actor, mut events) = sessions_actor(&dir);
+        actor.load_state().unwrap();
+        actor.open_session(Local::now()).await.unwrap();
+        let session = actor.active_session.clone().unwrap();
+        let mut source = SyntheticSource::new(64, 48);
+        source.open().unwrap();
+        let frame = source.next_frame().unwrap().unwrap();
+
+        store(&mut actor, &frame, vec![tracked(1, 0.5, true), tracked(2, 0.5, true)]).await;
+        let added = Detection::decode(&event(&mut events, DETECTION).await.payload[..]).unwrap();
+        assert_eq!((added.session.as_str(), added.detection), (session.as_str(), 1));
+        let stored = |actor: &SessionsActor, detection: i32| -> DetectionModel {
+            actor.db.r_transaction().unwrap().get().primary(detection).unwrap().unwrap()
+        };
+        let first = stored(&actor, 1);
+        assert_eq!((first.width, first.height, first.model.as_str()), (16, 8, "insects"));
+        assert!(!first.image.is_empty());
+
+        // only the track seen better takes a new crop
+        store(&mut actor, &frame, vec![tracked(1, 0.3, false), tracked(2, 0.9, false)]).await;
+        let (first, second) = (stored(&actor, 1), stored(&actor, 2));
+        assert_eq!((first.score, second.score), (0.5, 0.9));
+        assert_eq!(first.updated, frame.timestamp());
+
+        // a session closed while the crops were cut keeps none of them
+        let tracking = TrackingResult { tracked: vec![tracked(3, 0.5, true)], ended: Vec::new() };
+        let update = TrackUpdate::new(frame.clone(), tracking, Arc::default());
+        actor.store_tracks(update).unwrap();
+        actor.close_session(Some(5_000)).await.unwrap();
+        let cropped = actor.cropped_rx.recv().await.unwrap();
+        actor.tracks_cropped(cropped).await.unwrap();
+        assert!(actor.db.r_transaction().unwrap().get().primary::<DetectionModel>(3).unwrap().is_none());
+    }
+
+    fn stored_session(actor: &SessionsActor, session: &str) -> SessionModel {
+        actor.db.r_transaction().unwrap().get().primary(session.to_string()).unwrap().unwrap()
+    }
+
+    /// The next event published under `identifier`
+    async fn event(events: &mut BroadcastReceiver<ProtobufMsg>, identifier: &str) -> ProtobufMsg {
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(10), events.recv())
+                .await
+                .expect("no event")
+                .unwrap();
+            if msg.identifier == identifier {
+                return msg;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn sessions_opened_in_the_same_second_get_their_own_ids() {
+        let dir = TestDir::new("sessions-same-second");
+        let (mut actor, _events) = sessions_actor(&dir);
+        let now = Local::now();
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            actor.open_session(now).await.unwrap();
+            ids.push(actor.active_session.clone().unwrap());
+            actor.close_session(Some(now.timestamp_millis())).await.unwrap();
+        }
+        let base = now.format("%Y%m%d%H%M%S").to_string();
+        assert_eq!(ids, [base.clone(), format!("{}-1", base), format!("{}-2", base)]);
+        for id in &ids {
+            let session = stored_session(&actor, id);
+            assert_eq!((session.active, session.closed), (0, Some(now.timestamp_millis())));
+        }
+    }
+
+    #[tokio::test]
+    async fn capture_off_closes_the_session() {
+        let dir = TestDir::new("sessions-capture-off");
+        let (mut actor, mut events) = sessions_actor(&dir);
+        let capture = |state| ProtobufMsg::new(SESSION_STATE_SET, State { state }.encode_to_vec());
+        actor.handle_message(capture(true)).await;
+        let session = actor.active_session.clone().expect("no session opened");
+        assert_eq!(stored_session(&actor, &session).active, 1);
+        event(&mut events, SESSION_OPENED).await;
+
+        // capture on again leaves the open session be
+        actor.handle_message(capture(true)).await;
+        assert_eq!(actor.active_session.as_deref(), Some(session.as_str()));
+
+        actor.handle_message(capture(false)).await;
+        assert_eq!(actor.active_session, None);
+        let closed = stored_session(&actor, &session);
+        assert_eq!(closed.active, 0);
+        assert!(closed.closed.is_some());
+        let event = event(&mut events, SESSION_CLOSED).await;
+        let details = SessionDetails::decode(&event.payload[..]).unwrap();
+        assert_eq!((details.session.as_str(), details.active), (session.as_str(), false));
+    }
+
+    /// A session left active by a crash, with its last sighting at 5000
+    fn crashed_session(dir: &Path) {
+        let (actor, _events) = sessions_actor(dir);
+        let rw = actor.db.rw_transaction().unwrap();
+        rw.insert(SessionModel {
+            session: "20260101220000".to_string(),
+            active: 1,
+            opened: 1_000,
+            closed: None,
+            models: Vec::new(),
+        })
+        .unwrap();
+        let mut last = detection(1, "20260101220000", Vec::new());
+        last.updated = 5_000;
+        rw.insert(last).unwrap();
+        rw.commit().unwrap();
+    }
+
+    #[tokio::test]
+    async fn session_left_active_is_closed_at_its_last_sighting() {
+        let dir = TestDir::new("sessions-recover-closed");
+        crashed_session(&dir);
+        let (mut actor, _events) = sessions_actor(&dir);
+        actor.load_state().unwrap();
+        assert_eq!(actor.active_session.as_deref(), Some("20260101220000"));
+        assert_eq!(actor.next_detection, 2);
+
+        actor.handle_message(ProtobufMsg::new(SESSION_RESUME, State { state: false }.encode_to_vec())).await;
+        assert_eq!(actor.active_session, None);
+        let session = stored_session(&actor, "20260101220000");
+        assert_eq!((session.active, session.closed), (0, Some(5_000)));
+    }
+
+    #[tokio::test]
+    async fn sessions_count_only_their_own_detections() {
+        let dir = TestDir::new("sessions-counts");
+        let (mut actor, mut events) = sessions_actor(&dir);
+        let rw = actor.db.rw_transaction().unwrap();
+        for (session, active) in [("20260101220000", 1), ("20260101220000-1", 0)] {
+            rw.insert(SessionModel {
+                session: session.to_string(),
+                active,
+                opened: 1_000,
+                closed: (active == 0).then_some(9_000),
+                models: Vec::new(),
+            })
+            .unwrap();
+        }
+        rw.insert(detection(1, "20260101220000", Vec::new())).unwrap();
+        for id in [2, 3] {
+            let mut later = detection(id, "20260101220000-1", Vec::new());
+            later.updated = 8_000;
+            rw.insert(later).unwrap();
+        }
+        rw.commit().unwrap();
+
+        // recovered at its own last sighting, not that of the later session
+        actor.load_state().unwrap();
+        actor.handle_message(ProtobufMsg::new(SESSION_RESUME, State { state: false }.encode_to_vec())).await;
+        let closed = SessionDetails::decode(&event(&mut events, SESSION_CLOSED).await.payload[..]).unwrap();
+        assert_eq!((closed.session.as_str(), closed.detections), ("20260101220000", 1));
+        assert_eq!(closed.closed, Some(2_000));
+
+        actor.handle_message(request(SESSION_ALL, Vec::new(), 1)).await;
+        let counts: Vec<(String, i32)> = replies(&mut events, 1)
+            .await
+            .iter()
+            .filter(|msg| msg.status == EnvelopeStatus::Message)
+            .map(|msg| SessionDetails::decode(&msg.payload[..]).unwrap())
+            .map(|details| (details.session, details.detections))
+            .collect();
+        assert_eq!(
+            counts,
+            [("20260101220000".to_string(), 1), ("20260101220000-1".to_string(), 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn session_left_active_continues_when_capture_resumes() {
+        let dir = TestDir::new("sessions-recover-continued");
+        crashed_session(&dir);
+        let (mut actor, _events) = sessions_actor(&dir);
+        actor.load_state().unwrap();
+
+        actor.handle_message(ProtobufMsg::new(SESSION_RESUME, State { state: true }.encode_to_vec())).await;
+        assert_eq!(actor.active_session.as_deref(), Some("20260101220000"));
+        let session = stored_session(&actor, "20260101220000");
+        assert_eq!((session.active, session.closed), (1, None));
+    }
 }
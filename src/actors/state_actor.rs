@@ -1,50 +1,199 @@
 use async_broadcast::{ Receiver as BroadcastReceiver, Sender as BroadcastSender };
 //use async_channel::{Receiver as ChannelReceiver, Sender as ChannelSender};
 
-use anyhow::{Context as ErrContext, Result};
+use std::path::PathBuf;
+
+use anyhow::Result;
 use futures_util::StreamExt;
-use log::debug;
+use log::{debug, info, warn};
 
 use prost::Message;
+use crate::config::DatabaseConfig;
+use crate::database::saved_state::SavedState;
 use crate::framework::actor::Actor;
 use crate::framework::streams::BroadcastStream;
 use crate::generated::control::State;
+use crate::messages::identifiers::*;
 use crate::messages::protobuf_msg::ProtobufMsg;
+use crate::messages::registry::Routes;
+use crate::messages::state::{CameraFailure, StateChanged};
+use crate::state::machine::{effects, Effect, LiveState, TrapState, Trigger};
 
 #[derive(Clone, Debug)]
+enum StateCommand {
+    Get,
+    GetCapture,
+    SetCapture(State),
+    GetStreaming,
+    SetStreaming(State),
+    SetPause(State),
+    CameraOpened,
+    CameraFailed(CameraFailure),
+    Shutdown,
+}
+
+/// Runs the trap state machine. Requests from the app and reports from the
+/// camera become triggers, and each transition is fanned out to the actors
+/// concerned, saved, and announced with `state.changed`.
+#[derive(Debug)]
 pub struct StateActor {
+    state_path : PathBuf,
     protobuf_pub_tx :  BroadcastSender<ProtobufMsg>,
     protobuf_subs_tx : BroadcastSender<ProtobufMsg>,
     protobuf_subs_rx : BroadcastReceiver<ProtobufMsg>,
-    capture_state : bool,
-    streaming_state : bool,
+    routes : Routes<StateCommand>,
+    state : TrapState,
+    live : LiveState,
+    last_change : StateChanged,
 }
 
 impl StateActor {
     pub(crate) fn new(
+        config : &DatabaseConfig,
+        live : LiveState,
         protobuf_pub :  BroadcastStream<ProtobufMsg>,
         protobuf_subs : BroadcastStream<ProtobufMsg>,
     ) -> Self {
+        // a restarted actor knows nothing until it has restored the saved state
+        live.set(None);
         Self {
+            state_path : config.state.clone(),
             protobuf_pub_tx : protobuf_pub.broadcast_sender(),
             protobuf_subs_tx : protobuf_subs.broadcast_sender(),
             protobuf_subs_rx : protobuf_subs.broadcast_receiver(),
-            capture_state: false,
-            streaming_state: false,
+            routes : Routes::new()
+                .on(STATE_GET, |()| StateCommand::Get)
+                .on(STATE_CAPTURE_GET, |()| StateCommand::GetCapture)
+                .on(STATE_CAPTURE_SET, StateCommand::SetCapture)
+                .on(STATE_STREAMING_GET, |()| StateCommand::GetStreaming)
+                .on(STATE_STREAMING_SET, StateCommand::SetStreaming)
+                .on(STATE_PAUSE_SET, StateCommand::SetPause)
+                .on(CAMERA_OPENED, |()| StateCommand::CameraOpened)
+                .on(CAMERA_FAILED, StateCommand::CameraFailed)
+                .on(APP_SHUTDOWN, |()| StateCommand::Shutdown),
+            state : TrapState::Idle,
+            live,
+            last_change : StateChanged::default(),
         }
     }
     
-    async fn encode_state(self, identifier : &str, state : bool) -> Result<ProtobufMsg, anyhow::Error> {
-        Ok(ProtobufMsg {
-            identifier: identifier.to_string(),
-            payload: State { state }.encode_to_vec()
-        })
+    fn save_state(&self) {
+        let Some(state) = self.state.resumable() else { return };
+        if let Err(e) = (SavedState { state }).save(&self.state_path) {
+            warn!("Failed to save trap state {:#}", e);
+        }
+    }
+
+    /// Pick up the state saved before the last restart. The session is resumed
+    /// first so it continues, or is closed if capture was off, before the
+    /// camera starts again.
+    async fn restore_state(&mut self) -> Result<()> {
+        // a file cut short by a power cut should not keep the trap from starting
+        let saved = SavedState::load(&self.state_path).unwrap_or_else(|e| {
+            warn!("Ignoring saved trap state {:#}", e);
+            SavedState { state: TrapState::Idle }
+        });
+
+        let has_session = saved.state != TrapState::Idle;
+        let resume = Self::encode_state(SESSION_RESUME, has_session);
+        self.protobuf_subs_tx.broadcast(resume).await?;
+
+        if has_session {
+            // the session is open again, so carry on as if capture was paused
+            self.state = TrapState::Paused;
+            self.last_change = StateChanged {
+                previous: TrapState::Idle as i32,
+                current: TrapState::Paused as i32,
+                reason: "session resumed after restart".to_string(),
+            };
+            if saved.state.is_capturing() {
+                info!("Resuming capture");
+                self.apply(Trigger::Resume, "resumed after restart").await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_state(identifier : &str, state : bool) -> ProtobufMsg {
+        ProtobufMsg::new(identifier, State { state }.encode_to_vec())
+    }
+
+    /// Move to the state `trigger` leads to and carry out the side effects
+    async fn apply(&mut self, trigger: Trigger, reason: &str) -> Result<()> {
+        let previous = self.state;
+        let next = previous.next(&trigger)?;
+        if next == previous {
+            debug!("Trap already {:?}", previous);
+            return Ok(());
+        }
+
+        for effect in effects(previous, next) {
+            let msg = match effect {
+                Effect::Session(open) => Self::encode_state(SESSION_STATE_SET, open),
+                Effect::Camera(on) => Self::encode_state(CAMERA_STATE_SET, on),
+                Effect::DetectionStream(on) => Self::encode_state(DETECTION_STREAM_SET, on),
+            };
+            self.protobuf_subs_tx.broadcast(msg).await?;
+        }
+        self.state = next;
+        self.live.set(Some(next));
+        self.save_state();
+        info!("Trap state {:?} -> {:?} ({})", previous, next, reason);
+
+        self.last_change = StateChanged {
+            previous: previous as i32,
+            current: next as i32,
+            reason: reason.to_string(),
+        };
+        let changed = ProtobufMsg::new(STATE_CHANGED, self.last_change.encode_to_vec());
+        self.protobuf_pub_tx.broadcast(changed).await?;
+
+        // the flags older clients know the trap by
+        if previous.is_capturing() != next.is_capturing() {
+            let capture = Self::encode_state(STATE_CAPTURE, next.is_capturing());
+            self.protobuf_pub_tx.broadcast(capture).await?;
+        }
+        if previous.is_streaming() != next.is_streaming() {
+            let streaming = Self::encode_state(STATE_STREAMING, next.is_streaming());
+            self.protobuf_pub_tx.broadcast(streaming).await?;
+        }
+        Ok(())
     }
 
-    fn decode_state(self, buf : Vec<u8>) -> Result<bool> {
-        let state = State::decode(&buf[..])
-            .with_context(|| "Failed to decode state")?;
-        Ok(state.state)
+    async fn handle_command(&mut self, command: StateCommand, msg: &ProtobufMsg) -> Result<()> {
+        let reason = msg.identifier.as_str();
+        match command {
+            StateCommand::Get => {
+                // the current state comes from the machine, the last change only adds how it got there
+                let state = StateChanged { current: self.state as i32, ..self.last_change.clone() };
+                let state_msg = msg.reply(STATE, state.encode_to_vec());
+                self.protobuf_pub_tx.broadcast(state_msg).await?;
+            }
+            StateCommand::GetCapture => {
+                debug!("Trap state is {:?}", self.state);
+                let state_msg = Self::encode_state(STATE_CAPTURE, self.state.is_capturing())
+                    .in_reply_to(msg);
+                self.protobuf_pub_tx.broadcast(state_msg).await?;
+            }
+            StateCommand::SetCapture(State { state: true }) => self.apply(Trigger::Start, reason).await?,
+            StateCommand::SetCapture(State { state: false }) => self.apply(Trigger::Stop, reason).await?,
+            StateCommand::GetStreaming => {
+                debug!("Trap state is {:?}", self.state);
+                let state_msg = Self::encode_state(STATE_STREAMING, self.state.is_streaming())
+                    .in_reply_to(msg);
+                self.protobuf_pub_tx.broadcast(state_msg).await?;
+            }
+            StateCommand::SetStreaming(State { state: true }) => self.apply(Trigger::StreamOn, reason).await?,
+            StateCommand::SetStreaming(State { state: false }) => self.apply(Trigger::StreamOff, reason).await?,
+            StateCommand::SetPause(State { state: true }) => self.apply(Trigger::Pause, reason).await?,
+            StateCommand::SetPause(State { state: false }) => self.apply(Trigger::Resume, reason).await?,
+            StateCommand::CameraOpened => self.apply(Trigger::CameraOpened, reason).await?,
+            StateCommand::CameraFailed(failure) => {
+                self.apply(Trigger::CameraFailed(failure.error.clone()), &failure.error).await?
+            }
+            StateCommand::Shutdown => self.apply(Trigger::Shutdown, reason).await?,
+        }
+        Ok(())
     }
 }
 
@@ -52,83 +201,34 @@ impl Actor for StateActor {
     async fn on_started(mut self) {
         debug!("State actor started");
 
+        if let Err(e) = self.restore_state().await {
+            warn!("Failed to restore trap state {:#}", e);
+        }
+        self.live.set(Some(self.state));
+
         loop {
             let res = self.protobuf_subs_rx.next().await;
             match res {
                 Some(msg) => {
                     debug!("ProtobufMsg received identifier = [{}]", msg.identifier);
-                    match msg.identifier.as_str() {
-                        "state.capture.get" => {
-                            debug!("Capture state is {}", self.capture_state);
-                            let state_msg = self
-                                .clone()
-                                .encode_state("state.capture", self.capture_state)
-                                .await.unwrap();
-                            let _ = self.protobuf_pub_tx.broadcast(state_msg).await;
-                        }
-                        "state.capture.set" => {
-                            let new_state = self
-                                .clone()
-                                .decode_state(msg.payload)
-                                .unwrap();
-                            if new_state != self.capture_state {
-                                self.capture_state = new_state;
-
-                                if self.capture_state {
-                                    let msg = self
-                                        .clone()
-                                        .encode_state("session.state.set", true)
-                                        .await
-                                        .unwrap();
-                                    self.protobuf_subs_tx.broadcast(msg).await.expect("Failed to send");
-                                    let msg = self
-                                        .clone()
-                                        .encode_state("camera.state.set", true)
-                                        .await
-                                        .unwrap();
-                                    self.protobuf_subs_tx.broadcast(msg).await.expect("Failed to send");
-                                }
-                                debug!("Capture state set to {}", self.capture_state);
-                                let state_msg = self
-                                    .clone()
-                                    .encode_state("state.capture", self.capture_state)
-                                    .await
-                                    .unwrap();
-                                self.protobuf_pub_tx.broadcast(state_msg).await.unwrap();
-                            }
-                        }
-
-                        "state.streaming.get" => {
-                            debug!("Streaming state is {}",
-                                self.streaming_state);
-                            let state_msg = self
-                                .clone()
-                                .encode_state("state.streaming", self.streaming_state)
-                                .await
-                                .unwrap();
-                            self.protobuf_pub_tx.broadcast(state_msg).await.unwrap();
-                        }
-                        "state.streaming.set" => {
-                            let set_stream_fn = async || -> Result<()> {
-                                let new_state = self.clone().decode_state(msg.payload)?;
-                                self.clone().streaming_state = new_state;
-
-                                debug!("Stream state set to {}", self.streaming_state);
-                                let state_msg = self
-                                    .clone()
-                                    .encode_state("state.stream", self.streaming_state)
-                                    .await?;
-                                self.protobuf_pub_tx.broadcast(state_msg).await?;
-                                Ok(())
-                            };
-                            let _ = set_stream_fn().await;
-                        }
-                        _ => { debug!("unhandled message {}", msg.identifier.as_str())}
+                    let result = match self.routes.decode(&msg) {
+                        Some(Ok(command)) => self.handle_command(command, &msg).await,
+                        Some(Err(e)) => Err(e),
+                        None => continue,
+                    };
+                    if let Err(e) = &result {
+                        warn!("Error handling {}: {:#}", msg.identifier, e);
+                    }
+                    if let Some(done) = msg.completion(&result) {
+                        let _ = self.protobuf_pub_tx.broadcast(done).await;
+                    }
+                    if self.state == TrapState::ShuttingDown {
+                        break;
                     }
                 }
-                None => {}
+                None => break,
             }
         };
+        debug!("State actor stopped");
     }
 }
-
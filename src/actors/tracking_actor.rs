@@ -0,0 +1,56 @@
+use log::{debug, warn};
+
+use crate::framework::actor::Actor;
+use crate::framework::streams::ChannelStream;
+use crate::messages::detection_result::DetectionResult;
+use crate::messages::track_update::TrackUpdate;
+use crate::tracking::sort::Tracker;
+
+use async_channel::{Receiver as ChannelReceiver, Sender as ChannelSender};
+
+/// Sits between DetectionActor and SessionsActor and turns per-frame detections
+/// into tracks, so an insect resting on the sheet is only counted once.
+pub struct TrackingActor {
+    detection_rx: ChannelReceiver<DetectionResult>,
+    track_tx: ChannelSender<TrackUpdate>,
+    tracker: Tracker,
+}
+
+impl TrackingActor {
+    pub(crate) fn new(
+        detection_receiver: ChannelStream<DetectionResult>,
+        track_sender: ChannelStream<TrackUpdate>,
+    ) -> Self {
+        Self {
+            detection_rx: detection_receiver.channel_receiver(),
+            track_tx: track_sender.channel_sender(),
+            tracker: Tracker::new(),
+        }
+    }
+}
+
+impl Actor for TrackingActor {
+    async fn on_started(mut self) {
+        debug!("Tracking actor started");
+
+        loop {
+            let res = self.detection_rx.recv().await;
+            match res {
+                Ok(result) => {
+                    let tracking = self.tracker.update(result.boxes());
+                    if tracking.tracked.is_empty() && tracking.ended.is_empty() {
+                        continue;
+                    }
+                    let update = TrackUpdate::new(result.frame().clone(), tracking, result.model().clone());
+                    if self.track_tx.send(update).await.is_err() {
+                        warn!("Track channel closed");
+                    }
+                }
+                // closed by DetectionActor on shutdown once the detections are drained
+                Err(_) => break,
+            }
+        }
+        self.track_tx.close();
+        debug!("Tracking actor stopped");
+    }
+}
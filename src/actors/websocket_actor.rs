@@ -1,112 +1,418 @@
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, PoisonError};
 
-//use std::sync::{Arc, Mutex, RwLock};
+use anyhow::{Context as ErrContext, Result};
 use futures_util::{select, FutureExt, SinkExt, StreamExt};
-use futures_util::stream::{SplitSink, SplitStream};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use tokio_tungstenite::tungstenite::{Message as WsMessage};
-use tokio_tungstenite::{accept_async, WebSocketStream};
+use tokio_tungstenite::accept_async;
 use tokio::net::{TcpListener, TcpStream};
 
+use crate::config::ServerConfig;
 use crate::framework::streams::BroadcastStream;
 
 use async_broadcast::{ Receiver as BroadcastReceiver, Sender as BroadcastSender };
-use prost::Message;
+use async_channel::{Receiver as ChannelReceiver, Sender as ChannelSender, TrySendError};
 use crate::framework::actor::Actor;
+use crate::messages::envelope::EnvelopeStatus;
+use crate::messages::identifiers::APP_SHUTDOWN;
 use crate::messages::protobuf_msg::ProtobufMsg;
 use crate::messages::raw_message::RawMessage;
+use crate::messages::registry::check_request;
 
-type WsRxStream = SplitStream<WebSocketStream<TcpStream>>;
-type WsTxStream = SplitSink<WebSocketStream<TcpStream>, WsMessage>;
+/// Messages queued for a client before further messages to it are dropped
+const CLIENT_QUEUE_SIZE: usize = 64;
 
+type ClientId = u64;
+
+enum ClientEvent {
+    Connected(ClientId, SocketAddr, ChannelSender<WsMessage>),
+    Disconnected(ClientId),
+}
+
+/// Correlation ids are chosen by clients, so two clients may use the same one.
+/// Requests are renumbered with a server-wide id on the way in, and replies go
+/// back only to the client that made the request, under the id it chose.
+#[derive(Clone, Default)]
+struct Correlations(Arc<Mutex<CorrelationMap>>);
+
+#[derive(Default)]
+struct CorrelationMap {
+    next: u64,
+    owners: HashMap<u64, (ClientId, u64)>,
+}
+
+enum Route {
+    /// An event nobody asked for, every client gets it
+    Everyone(ProtobufMsg),
+    /// A reply, restored to the correlation id the client chose
+    Client(ClientId, ProtobufMsg),
+    /// A reply to a client that has gone
+    Nobody,
+}
+
+impl Correlations {
+    fn assign(&self, client: ClientId, mut msg: ProtobufMsg) -> ProtobufMsg {
+        if let Some(original) = msg.correlation {
+            let mut map = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+            map.next += 1;
+            let id = map.next;
+            map.owners.insert(id, (client, original));
+            msg.correlation = Some(id);
+        }
+        msg
+    }
+
+    /// Where a published message goes. The id is forgotten with the End or
+    /// Error that finishes the request.
+    fn route(&self, mut msg: ProtobufMsg) -> Route {
+        let Some(id) = msg.correlation else { return Route::Everyone(msg) };
+        let mut map = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let owner = match msg.status {
+            EnvelopeStatus::Message => map.owners.get(&id).copied(),
+            EnvelopeStatus::End | EnvelopeStatus::Error => map.owners.remove(&id),
+        };
+        match owner {
+            Some((client, original)) => {
+                msg.correlation = Some(original);
+                Route::Client(client, msg)
+            }
+            None => Route::Nobody,
+        }
+    }
+
+    /// Drop the requests of a client that disconnected
+    fn forget(&self, client: ClientId) {
+        let mut map = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        map.owners.retain(|_, (owner, _)| *owner != client);
+    }
+}
 
 pub struct WebsocketActor {
+    bind : SocketAddr,
+    // bound as the actor is built, so an address in use fails its start
+    listener : std::net::TcpListener,
     protobuf_pub_rx :  BroadcastReceiver<ProtobufMsg>,
     protobuf_subs_tx : BroadcastSender<ProtobufMsg>,
+    protobuf_subs_rx : BroadcastReceiver<ProtobufMsg>,
 }
 
 impl WebsocketActor {
     pub(crate) fn new(
+        config: &ServerConfig,
         protobuf_pub: BroadcastStream<ProtobufMsg>,
         protobuf_subs: BroadcastStream<ProtobufMsg>,
-    ) -> Self {
-        Self {
+    ) -> Result<Self> {
+        let listener = std::net::TcpListener::bind(config.bind)
+            .with_context(|| format!("Failed to listen on {}", config.bind))?;
+        listener.set_nonblocking(true).context("Failed to set up the listener")?;
+        Ok(Self {
+            bind: config.bind,
+            listener,
             protobuf_pub_rx: protobuf_pub.broadcast_receiver(),
             protobuf_subs_tx: protobuf_subs.broadcast_sender(),
-        }
+            protobuf_subs_rx: protobuf_subs.broadcast_receiver(),
+        })
     }
-}
-
-impl Actor for WebsocketActor {
-    async fn on_started(mut self) {
 
-        debug!("Websocket actor started");
+    fn encode(msg: &ProtobufMsg) -> Option<WsMessage> {
+        match msg.to_raw_message() {
+            Ok(raw_msg) => Some(WsMessage::Binary(raw_msg.message.into())),
+            Err(e) => {
+                warn!("Failed to encode {}: {:#}", msg.identifier, e);
+                None
+            }
+        }
+    }
 
-        //let mut tx_stream :  Mutex<Option<WsTxStream>> = Mutex::new(None);
-        let (stream_tx, stream_rx) = async_channel::bounded::<Option<WsTxStream>>(4);
-
-        // Task to listen on protobuf publish stream and send messages to the websocket
-        // if it exists
-        let _ = tokio::spawn(async move {
-            let mut write_stream: Option<WsTxStream> = None;
-            loop {
-                select! {
-                    prot_res = self.protobuf_pub_rx.recv().fuse() => {
-                        match prot_res {
-                            Ok(msg) => {
-                                match write_stream {
-                                    Some(ref mut stream) => {
-                                        let raw_msg = msg.to_raw_message().unwrap();
-                                        let ws_msg = WsMessage::Binary(raw_msg.message.into());
-                                        let _ = stream.send(ws_msg).await;
+    /// Encode each published message once and queue it for every connected client,
+    /// or only for the client that asked when it is a reply. A client that cannot
+    /// keep up loses messages rather than holding up the others, and a request
+    /// that lost replies that way ends with an error rather than its End.
+    async fn fan_out(
+        mut protobuf_pub_rx: BroadcastReceiver<ProtobufMsg>,
+        client_rx: ChannelReceiver<ClientEvent>,
+        correlations: Correlations,
+    ) {
+        let mut clients: HashMap<ClientId, (SocketAddr, ChannelSender<WsMessage>)> = HashMap::new();
+        // requests, by client and the correlation id it chose, that lost replies
+        let mut lossy: HashSet<(ClientId, u64)> = HashSet::new();
+        loop {
+            select! {
+                prot_res = protobuf_pub_rx.recv().fuse() => {
+                    let msg = match prot_res {
+                        Ok(msg) => msg,
+                        Err(_e) => continue,
+                    };
+                    if clients.is_empty() {
+                        continue;
+                    }
+                    match correlations.route(msg) {
+                        Route::Everyone(msg) => {
+                            let Some(ws_msg) = Self::encode(&msg) else { continue };
+                            clients.retain(|id, (peer_addr, tx)| match tx.try_send(ws_msg.clone()) {
+                                Ok(()) => true,
+                                Err(TrySendError::Full(_)) => {
+                                    warn!("Client {} ({}) is too slow, dropped {}", id, peer_addr, msg.identifier);
+                                    true
+                                }
+                                Err(TrySendError::Closed(_)) => false,
+                            });
+                        }
+                        Route::Client(id, mut msg) => {
+                            let Some((peer_addr, tx)) = clients.get(&id) else { continue };
+                            let request = (id, msg.correlation.unwrap_or_default());
+                            let finished = msg.status != EnvelopeStatus::Message;
+                            if finished && lossy.remove(&request) && msg.status == EnvelopeStatus::End {
+                                msg.status = EnvelopeStatus::Error;
+                                msg.error = Some("Replies were dropped, the client was too slow".to_string());
+                            }
+                            let Some(ws_msg) = Self::encode(&msg) else { continue };
+                            match tx.try_send(ws_msg) {
+                                Ok(()) => {}
+                                Err(TrySendError::Full(_)) => {
+                                    warn!("Client {} ({}) is too slow, dropped {}", id, peer_addr, msg.identifier);
+                                    if msg.status == EnvelopeStatus::Message {
+                                        lossy.insert(request);
                                     }
-                                    None => {}
+                                }
+                                Err(TrySendError::Closed(_)) => {
+                                    clients.remove(&id);
                                 }
                             }
-                            Err(_e) => {}
                         }
+                        Route::Nobody => {}
                     }
-                    stream_res = stream_rx.recv().fuse() => {
-                        match stream_res {
-                            Ok(msg) => {
-                                write_stream = msg;
+                }
+                client_res = client_rx.recv().fuse() => {
+                    match client_res {
+                        Ok(ClientEvent::Connected(id, peer_addr, tx)) => {
+                            clients.insert(id, (peer_addr, tx));
+                            info!("Client {} ({}) connected, {} connected", id, peer_addr, clients.len());
+                        }
+                        Ok(ClientEvent::Disconnected(id)) => {
+                            correlations.forget(id);
+                            lossy.retain(|(client, _)| *client != id);
+                            if let Some((peer_addr, _)) = clients.remove(&id) {
+                                info!("Client {} ({}) disconnected, {} connected", id, peer_addr, clients.len());
                             }
-                            Err(_e) => {}
                         }
+                        Err(_e) => break,
                     }
                 }
             }
+        }
+    }
+
+    /// Answer a rejected request straight to the client that sent it
+    async fn reply_error(queue_tx: &ChannelSender<WsMessage>, msg: &ProtobufMsg, e: anyhow::Error) {
+        let Some(done) = msg.completion(&Err(e)) else { return };
+        match done.to_raw_message() {
+            Ok(raw_msg) => {
+                let _ = queue_tx.send(WsMessage::Binary(raw_msg.message.into())).await;
+            }
+            Err(e) => warn!("Failed to encode {}: {:#}", done.identifier, e),
+        }
+    }
+
+    /// Serve a single client: a writer task drains the client's queue while this
+    /// task forwards everything the client sends onto the subscription stream
+    async fn serve_client(
+        id: ClientId,
+        stream: TcpStream,
+        peer_addr: SocketAddr,
+        protobuf_subs_tx: BroadcastSender<ProtobufMsg>,
+        client_tx: ChannelSender<ClientEvent>,
+        correlations: Correlations,
+    ) {
+        let wss = match accept_async(stream).await {
+            Ok(wss) => wss,
+            Err(e) => {
+                warn!("WebSocket handshake with {} failed: {}", peer_addr, e);
+                return;
+            }
+        };
+        debug!("Connection request from {} accepted", peer_addr);
+        let (mut write, mut read) = wss.split();
+
+        let (queue_tx, queue_rx) = async_channel::bounded::<WsMessage>(CLIENT_QUEUE_SIZE);
+        let writer = tokio::spawn(async move {
+            while let Ok(ws_msg) = queue_rx.recv().await {
+                if let Err(e) = write.send(ws_msg).await {
+                    debug!("Write to {} failed: {}", peer_addr, e);
+                    break;
+                }
+            }
+            let _ = write.close().await;
         });
+        let _ = client_tx.send(ClientEvent::Connected(id, peer_addr, queue_tx.clone())).await;
 
-        let listener = TcpListener::bind("0.0.0.0:8096").await.unwrap();
-        info!("Listening on 0.0.0.0:8096");
+        while let Some(message) = read.next().await {
+            match message {
+                Ok(msg) => {
+                    if msg.is_binary() {
+                        let raw_msg = RawMessage { message: msg.into_data().to_vec() };
+                        match ProtobufMsg::from_raw_message(raw_msg) {
+                            Ok(msg) => match check_request(&msg) {
+                                Ok(()) => {
+                                    let msg = correlations.assign(id, msg);
+                                    let _ = protobuf_subs_tx.broadcast(msg).await;
+                                }
+                                Err(e) => {
+                                    warn!("Rejected message from {}: {:#}", peer_addr, e);
+                                    Self::reply_error(&queue_tx, &msg, e).await;
+                                }
+                            },
+                            Err(e) => warn!("Invalid message from {}: {:#}", peer_addr, e),
+                        }
+                    } else if msg.is_close() {
+                        debug!("Client {} sent a close message.", peer_addr);
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("Error receiving message from {}: {}", peer_addr, e);
+                    break;
+                }
+            }
+        }
+
+        let _ = client_tx.send(ClientEvent::Disconnected(id)).await;
+        writer.abort();
+        debug!("Connection to {} closed", peer_addr);
+    }
+}
 
+impl Actor for WebsocketActor {
+    async fn on_started(mut self) {
+
+        debug!("Websocket actor started");
+
+        let (client_tx, client_rx) = async_channel::unbounded::<ClientEvent>();
+        let correlations = Correlations::default();
+        tokio::spawn(Self::fan_out(self.protobuf_pub_rx, client_rx, correlations.clone()));
+
+        let listener = match TcpListener::from_std(self.listener) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to listen on {}: {}", self.bind, e);
+                return;
+            }
+        };
+        info!("Listening on {}", self.bind);
+
+        let mut next_id: ClientId = 1;
         loop {
-            let (stream, peer_addr) = listener.accept().await.expect("Accept connection failed");
-            let wss = accept_async(stream).await.expect("Failed to accept WebSocket connection");
-            debug!("Connection request accepted");
-            let (write, mut read) = wss.split();
-
-            stream_tx.send(Some(write)).await.unwrap();
-            while let Some(message) = read.next().await {
-                match message {
-                    Ok(msg) => {
-                        if msg.is_binary() {
-                            let raw_msg = RawMessage { message: msg.into_data().encode_to_vec() };
-                            let msg = ProtobufMsg::from_raw_message(raw_msg).unwrap();
-                            let _ = self.protobuf_subs_tx.broadcast(msg).await.unwrap();
-                        } else if msg.is_close() {
-                            debug!("Client {} sent a close message.", peer_addr);
-                            break;
-                        }
+            select! {
+                res = listener.accept().fuse() => match res {
+                    Ok((stream, peer_addr)) => {
+                        tokio::spawn(Self::serve_client(
+                            next_id,
+                            stream,
+                            peer_addr,
+                            self.protobuf_subs_tx.clone(),
+                            client_tx.clone(),
+                            correlations.clone(),
+                        ));
+                        next_id += 1;
                     }
-                    Err(e) => {
-                        error!("Error receiving message from {}: {}", peer_addr, e);
+                    Err(e) => warn!("Accept connection failed {}", e),
+                },
+                res = self.protobuf_subs_rx.recv_direct().fuse() => {
+                    if matches!(res, Ok(msg) if msg.identifier == APP_SHUTDOWN) {
                         break;
                     }
                 }
             }
-            debug!("Connection closed");
-            stream_tx.send(None).await.unwrap();
+        }
+        debug!("Websocket actor stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+    use super::*;
+    use crate::messages::identifiers::{STATE, STATE_GET};
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn connect(bind: SocketAddr) -> Client {
+        for _ in 0..50 {
+            if let Ok((client, _)) = connect_async(format!("ws://{}", bind)).await {
+                return client;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("Could not connect to {}", bind);
+    }
+
+    async fn send(client: &mut Client, payload: &[u8], correlation: u64) {
+        let mut msg = ProtobufMsg::new(STATE_GET, payload.to_vec());
+        msg.correlation = Some(correlation);
+        let raw_msg = msg.to_raw_message().unwrap();
+        client.send(WsMessage::Binary(raw_msg.message.into())).await.unwrap();
+    }
+
+    async fn receive(client: &mut Client) -> Option<ProtobufMsg> {
+        let ws_msg = tokio::time::timeout(Duration::from_millis(500), client.next()).await.ok()??;
+        let raw_msg = RawMessage { message: ws_msg.unwrap().into_data().to_vec() };
+        Some(ProtobufMsg::from_raw_message(raw_msg).unwrap())
+    }
+
+    /// Answer every request with its own payload, as an actor would
+    async fn echo(mut requests: BroadcastReceiver<ProtobufMsg>, replies: BroadcastSender<ProtobufMsg>) {
+        let mut seen = Vec::new();
+        while let Ok(request) = requests.recv().await {
+            assert!(!seen.contains(&request.correlation), "correlation {:?} reused", request.correlation);
+            seen.push(request.correlation);
+            replies.broadcast(request.reply(STATE, request.payload.clone())).await.unwrap();
+            replies.broadcast(request.completion(&Ok(())).unwrap()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn clients_using_the_same_correlation_get_only_their_own_replies() {
+        let bind = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let protobuf_pub = BroadcastStream::new(10);
+        let protobuf_subs = BroadcastStream::new(10);
+        let config = ServerConfig { bind };
+        let actor = WebsocketActor::new(&config, protobuf_pub.clone(), protobuf_subs.clone()).unwrap();
+        let responder = echo(protobuf_subs.broadcast_receiver(), protobuf_pub.broadcast_sender());
+
+        let clients = async {
+            let mut first = connect(bind).await;
+            let mut second = connect(bind).await;
+            send(&mut first, b"first", 1).await;
+            send(&mut second, b"second", 1).await;
+
+            for (client, payload) in [(&mut first, b"first".as_slice()), (&mut second, b"second".as_slice())] {
+                let reply = receive(client).await.expect("no reply");
+                assert_eq!((reply.identifier.as_str(), reply.payload.as_slice()), (STATE, payload));
+                assert_eq!(reply.correlation, Some(1));
+                let done = receive(client).await.expect("no completion");
+                assert_eq!((done.status, done.correlation), (EnvelopeStatus::End, Some(1)));
+                assert!(receive(client).await.is_none(), "got a reply meant for the other client");
+            }
         };
+        tokio::select! {
+            _ = actor.on_started() => panic!("Websocket actor stopped"),
+            _ = responder => panic!("Requests stopped"),
+            _ = clients => {}
+        }
+    }
+
+    #[test]
+    fn address_in_use_fails_to_build_the_actor() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let bind = taken.local_addr().unwrap();
+        let (protobuf_pub, protobuf_subs) = (BroadcastStream::new(1), BroadcastStream::new(1));
+        let built = WebsocketActor::new(&ServerConfig { bind }, protobuf_pub, protobuf_subs);
+        let error = format!("{:#}", built.err().expect("bound the address twice"));
+        assert!(error.starts_with(&format!("Failed to listen on {}: ", bind)), "{}", error);
     }
 }
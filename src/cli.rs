@@ -0,0 +1,117 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand};
+
+use crate::config::Config;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "AI insect trap", args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Options for running the trap when no command is given
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+/// Overrides for values in the configuration file
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// Configuration file
+    #[arg(short, long, env = "AI_TRAP_CONFIG", default_value = "ai-trap.toml")]
+    pub config: PathBuf,
+    /// Frame source: camera:<index>, dir:<path>, video:<path> or synthetic[:<width>x<height>]
+    #[arg(long, env = "AI_TRAP_SOURCE")]
+    pub source: Option<String>,
+    /// Address the WebSocket server listens on
+    #[arg(long, env = "AI_TRAP_BIND")]
+    pub bind: Option<SocketAddr>,
+    /// Trap database
+    #[arg(long, env = "AI_TRAP_DATABASE")]
+    pub database: Option<String>,
+    /// Detection model, by name in the model registry
+    #[arg(long, env = "AI_TRAP_MODEL")]
+    pub model: Option<String>,
+}
+
+impl RunArgs {
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(source) = &self.source {
+            config.camera.source = source.clone();
+        }
+        if let Some(bind) = self.bind {
+            config.server.bind = bind;
+        }
+        if let Some(database) = &self.database {
+            config.database.path = database.clone();
+        }
+        if let Some(model) = &self.model {
+            config.detection.model = model.clone();
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the trap (the default)
+    Run(RunArgs),
+    /// Export a session as a zip archive with CSV, JSON, COCO and crop images
+    Export {
+        /// Session identifier
+        session: String,
+        /// Archive to write, defaults to session-<id>.zip
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Configuration file the database is found from
+        #[arg(short, long, env = "AI_TRAP_CONFIG", default_value = "ai-trap.toml")]
+        config: PathBuf,
+        /// Trap database, instead of the configured one. The trap must not be
+        /// running while exporting.
+        #[arg(long, env = "AI_TRAP_DATABASE")]
+        database: Option<String>,
+    },
+    /// Export sessions as a Darwin Core Archive for biodiversity data publishing
+    Dwca {
+        /// Export a single session
+        #[arg(long, conflicts_with_all = ["from", "to"])]
+        session: Option<String>,
+        /// First day of the sessions to export (YYYY-MM-DD)
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Last day of the sessions to export (YYYY-MM-DD)
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// Archive to write
+        #[arg(short, long, default_value = "dwca.zip")]
+        output: PathBuf,
+        /// Configuration file the database and models are found from
+        #[arg(short, long, env = "AI_TRAP_CONFIG", default_value = "ai-trap.toml")]
+        config: PathBuf,
+        /// Trap database, instead of the configured one. The trap must not be
+        /// running while exporting.
+        #[arg(long, env = "AI_TRAP_DATABASE")]
+        database: Option<String>,
+        /// Trap identifier, prefixes the event and occurrence ids. Defaults
+        /// to trap.id.
+        #[arg(long)]
+        trap_id: Option<String>,
+        /// Place name of the trap site, defaults to trap.locality
+        #[arg(long)]
+        locality: Option<String>,
+        /// Trap latitude in decimal degrees (WGS84), defaults to trap.latitude
+        #[arg(long, allow_negative_numbers = true)]
+        latitude: Option<f64>,
+        /// Trap longitude in decimal degrees (WGS84), defaults to trap.longitude
+        #[arg(long, allow_negative_numbers = true)]
+        longitude: Option<f64>,
+        /// Dataset title for the EML metadata
+        #[arg(long, default_value = "Automated light trap records")]
+        title: String,
+        /// Organisation publishing the dataset
+        #[arg(long, default_value = "")]
+        publisher: String,
+    },
+}
@@ -0,0 +1,553 @@
+pub mod values;
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+
+use anyhow::{ensure, Context as ErrContext, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::detection::registry::{check_name, model_name};
+use crate::framework::supervisor::{RestartPolicy, Strategy};
+use crate::schedule::Schedule;
+use crate::sources::controls::{parse_size, Control};
+use crate::sources::frame_flow::FullPolicy;
+use crate::sources::frame_source::create_frame_source;
+
+// ==============================================================================
+// Trap configuration, read from a TOML file at startup. Every value has a
+// default so the file only needs the ones that differ.
+// ==============================================================================
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub trap: SiteConfig,
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub camera: CameraConfig,
+    pub detection: DetectionConfig,
+    pub streams: StreamsConfig,
+    pub supervisor: SupervisorConfig,
+    pub schedule: ScheduleConfig,
+    pub preview: PreviewConfig,
+}
+
+/// The configuration in force, kept up to date by ConfigActor so that an
+/// actor restarted by the supervisor comes back with the changes made while
+/// the trap was running rather than the file as it was read at startup
+#[derive(Clone, Debug)]
+pub struct LiveConfig(Arc<RwLock<Config>>);
+
+impl LiveConfig {
+    pub fn new(config: Config) -> Self {
+        Self(Arc::new(RwLock::new(config)))
+    }
+
+    pub fn get(&self) -> Config {
+        self.0.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    pub fn set(&self, config: Config) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = config;
+    }
+}
+
+/// Where the trap stands, written into the records it exports
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    /// Prefixes the ids of exported events and occurrences
+    pub id: String,
+    /// Place name of the trap site
+    pub locality: String,
+    /// Position in decimal degrees (WGS84), longitude positive east
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            id: "ai-trap".to_string(),
+            locality: String::new(),
+            latitude: None,
+            longitude: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address the WebSocket server listens on
+    pub bind: SocketAddr,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self { bind: SocketAddr::from(([0, 0, 0, 0], 8096)) }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub path: String,
+    /// File the capture state is kept in so it survives a restart
+    pub state: PathBuf,
+    /// Directory session archives are built in while clients download them
+    pub exports: PathBuf,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            path: "location".to_string(),
+            state: PathBuf::from("trap-state.json"),
+            exports: PathBuf::from("exports"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    /// Frame source specification, see `create_frame_source`
+    pub source: String,
+    /// `<width>x<height>`, the highest the source offers when not set
+    pub resolution: Option<String>,
+    /// Frames per second passed to detection, 0 for as many as the source delivers
+    pub fps: f64,
+    /// What to do with frames when detection falls behind
+    pub when_full: FullPolicy,
+    pub controls: CameraControls,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            source: "camera:0".to_string(),
+            resolution: None,
+            fps: 10.0,
+            when_full: FullPolicy::default(),
+            controls: CameraControls::default(),
+        }
+    }
+}
+
+/// Control values set from the app, applied whenever the camera is opened.
+/// Controls not set are left as the camera has them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraControls {
+    pub exposure: Option<i64>,
+    pub gain: Option<i64>,
+    pub white_balance: Option<i64>,
+    pub focus: Option<i64>,
+}
+
+impl CameraControls {
+    pub fn get(&self, control: Control) -> Option<i64> {
+        match control {
+            Control::Exposure => self.exposure,
+            Control::Gain => self.gain,
+            Control::WhiteBalance => self.white_balance,
+            Control::Focus => self.focus,
+        }
+    }
+
+    pub fn set(&mut self, control: Control, value: i64) {
+        let field = match control {
+            Control::Exposure => &mut self.exposure,
+            Control::Gain => &mut self.gain,
+            Control::WhiteBalance => &mut self.white_balance,
+            Control::Focus => &mut self.focus,
+        };
+        *field = Some(value);
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DetectionConfig {
+    /// Registry directory the models are installed in, see `ModelRegistry`
+    pub models: PathBuf,
+    /// Name of the model to run, its file name in the registry without `.onnx`
+    pub model: String,
+    // f64 so values read back as written, 0.45f32 prints as 0.44999998807907104
+    /// Minimum score for a box to be kept
+    pub confidence: f64,
+    /// Overlap above which the weaker of two boxes of a class is suppressed
+    pub iou: f64,
+    /// Largest model accepted by `model.upload.begin`
+    pub max_upload_mb: u64,
+    /// Largest `model.upload.chunk`
+    pub max_chunk_kb: u64,
+    pub tiling: TilingConfig,
+    pub classifier: ClassifierConfig,
+    pub regions: RegionsConfig,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            models: PathBuf::from("models"),
+            model: "insects-320".to_string(),
+            confidence: 0.25,
+            iou: 0.45,
+            max_upload_mb: 256,
+            max_chunk_kb: 1024,
+            tiling: TilingConfig::default(),
+            classifier: ClassifierConfig::default(),
+            regions: RegionsConfig::default(),
+        }
+    }
+}
+
+/// Second stage model naming the species of each detection
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClassifierConfig {
+    /// Name of the classification model in the registry, empty for none
+    pub model: String,
+    /// Species kept for each detection, most likely first
+    pub top_k: usize,
+}
+
+impl Default for ClassifierConfig {
+    fn default() -> Self {
+        Self { model: String::new(), top_k: 3 }
+    }
+}
+
+/// Parts of the frame detections are kept from. Boxes are kept when their
+/// centre lies in one of the `include` regions, or anywhere when there are
+/// none, and not in any of the `exclude` regions.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegionsConfig {
+    pub include: Vec<Region>,
+    pub exclude: Vec<Region>,
+}
+
+/// A polygon with corners as fractions of the frame width and height, so it
+/// stays in place when the resolution changes
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Region {
+    pub name: String,
+    pub points: Vec<[f64; 2]>,
+}
+
+impl RegionsConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (kind, regions) in [("include", &self.include), ("exclude", &self.exclude)] {
+            ensure!(regions.len() <= 32, "detection.regions.{} may have at most 32 regions", kind);
+            for region in regions {
+                ensure!(
+                    (3..=64).contains(&region.points.len()),
+                    "Region '{}' needs between 3 and 64 points",
+                    region.name
+                );
+                ensure!(
+                    region.points.iter().flatten().all(|value| (0.0..=1.0).contains(value)),
+                    "Points of region '{}' must be between 0 and 1",
+                    region.name
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Detection on overlapping tiles of the full frame for small insects the
+/// model would lose when the frame is shrunk to its input size
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TilingConfig {
+    pub enabled: bool,
+    /// Fraction of a tile shared with each neighbour
+    pub overlap: f64,
+    /// Also run the model on the whole frame, for insects larger than a tile
+    pub whole_frame: bool,
+    /// Tiles run together, for models that take several images at once
+    pub batch: usize,
+}
+
+impl Default for TilingConfig {
+    fn default() -> Self {
+        Self { enabled: false, overlap: 0.2, whole_frame: true, batch: 4 }
+    }
+}
+
+/// Live preview sent to the app while the trap is streaming
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PreviewConfig {
+    /// Frames per second
+    pub rate: f64,
+    /// Frames are scaled down to at most this width
+    pub width: u32,
+    /// JPEG quality, 1 to 100
+    pub quality: u8,
+    /// Draw the detection boxes on the frames
+    pub boxes: bool,
+    /// Outline the regions detections are kept from and shade the excluded ones
+    pub regions: bool,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self { rate: 2.0, width: 640, quality: 70, boxes: true, regions: true }
+    }
+}
+
+/// Capacity of the streams between the actors
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamsConfig {
+    pub messages: usize,
+    pub frames: usize,
+    pub detections: usize,
+    pub tracks: usize,
+    pub preview: usize,
+}
+
+impl Default for StreamsConfig {
+    fn default() -> Self {
+        Self { messages: 10, frames: 10, detections: 10, tracks: 10, preview: 2 }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SupervisorConfig {
+    pub strategy: Strategy,
+    pub max_restarts: usize,
+    pub window_secs: u64,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Time the actors get to finish their work after SIGINT or SIGTERM
+    pub shutdown_deadline_secs: u64,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        let policy = RestartPolicy::default();
+        Self {
+            strategy: policy.strategy,
+            max_restarts: policy.max_restarts,
+            window_secs: policy.window.as_secs(),
+            initial_backoff_ms: policy.initial_backoff.as_millis() as u64,
+            max_backoff_ms: policy.max_backoff.as_millis() as u64,
+            shutdown_deadline_secs: 10,
+        }
+    }
+}
+
+impl SupervisorConfig {
+    pub fn restart_policy(&self) -> RestartPolicy {
+        RestartPolicy {
+            strategy: self.strategy,
+            max_restarts: self.max_restarts,
+            window: Duration::from_secs(self.window_secs),
+            initial_backoff: Duration::from_millis(self.initial_backoff_ms),
+            max_backoff: Duration::from_millis(self.max_backoff_ms),
+        }
+    }
+
+    pub fn shutdown_deadline(&self) -> Duration {
+        Duration::from_secs(self.shutdown_deadline_secs)
+    }
+}
+
+/// Capture window for each night, see `schedule::Schedule`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// Switch capture on and off automatically. Manual changes still work and
+    /// hold until the schedule next changes its mind.
+    pub enabled: bool,
+    /// HH:MM in local time, sunset or sunrise at trap.latitude and trap.longitude
+    pub start: String,
+    pub start_offset_min: i64,
+    pub end: String,
+    pub end_offset_min: i64,
+    /// Capture this many minutes then pause for duty_off_min, 0 to capture throughout
+    pub duty_on_min: u64,
+    pub duty_off_min: u64,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start: "sunset".to_string(),
+            start_offset_min: 0,
+            end: "sunrise".to_string(),
+            end_offset_min: 0,
+            duty_on_min: 0,
+            duty_off_min: 0,
+        }
+    }
+}
+
+impl Config {
+    /// Read the configuration file. A missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            info!("No configuration at {}, using defaults", path.display());
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let config = toml::from_str(&text)
+            .with_context(|| format!("Invalid configuration in {}", path.display()))?;
+        info!("Configuration loaded from {}", path.display());
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.trap.id.is_empty(), "trap.id must not be empty");
+        ensure!(
+            self.trap.latitude.is_none_or(|latitude| (-90.0..=90.0).contains(&latitude)),
+            "trap.latitude must be between -90 and 90"
+        );
+        ensure!(
+            self.trap.longitude.is_none_or(|longitude| (-180.0..=180.0).contains(&longitude)),
+            "trap.longitude must be between -180 and 180"
+        );
+        create_frame_source(&self.camera.source)?;
+        if let Some(resolution) = &self.camera.resolution {
+            parse_size(resolution).context("Invalid camera.resolution")?;
+        }
+        ensure!(
+            (0.0..=120.0).contains(&self.camera.fps),
+            "camera.fps must be between 0 and 120"
+        );
+        check_name(model_name(&self.detection.model)).context("Invalid detection.model")?;
+        ensure!(
+            self.detection.confidence > 0.0 && self.detection.confidence < 1.0,
+            "detection.confidence must be between 0 and 1"
+        );
+        ensure!(
+            self.detection.iou > 0.0 && self.detection.iou <= 1.0,
+            "detection.iou must be between 0 and 1"
+        );
+        let tiling = &self.detection.tiling;
+        ensure!(
+            (0.0..=0.5).contains(&tiling.overlap),
+            "detection.tiling.overlap must be between 0 and 0.5"
+        );
+        ensure!(tiling.batch > 0, "detection.tiling.batch must be at least 1");
+        self.detection.regions.validate()?;
+        let classifier = &self.detection.classifier;
+        if !classifier.model.is_empty() {
+            check_name(model_name(&classifier.model)).context("Invalid detection.classifier.model")?;
+        }
+        ensure!(
+            (1..=20).contains(&classifier.top_k),
+            "detection.classifier.top_k must be between 1 and 20"
+        );
+        ensure!(self.detection.max_upload_mb > 0, "detection.max_upload_mb must be at least 1");
+        // a chunk has to fit in one WebSocket frame
+        ensure!(
+            (1..=16 * 1024).contains(&self.detection.max_chunk_kb),
+            "detection.max_chunk_kb must be between 1 and 16384"
+        );
+        let streams = &self.streams;
+        ensure!(
+            streams.messages > 0
+                && streams.frames > 0
+                && streams.detections > 0
+                && streams.tracks > 0
+                && streams.preview > 0,
+            "stream sizes must be at least 1"
+        );
+        ensure!(
+            self.supervisor.initial_backoff_ms <= self.supervisor.max_backoff_ms,
+            "supervisor.initial_backoff_ms must not exceed supervisor.max_backoff_ms"
+        );
+        if self.schedule.enabled {
+            Schedule::from_config(&self.schedule, &self.trap)?;
+        }
+        ensure!(
+            self.preview.rate > 0.0 && self.preview.rate <= 30.0,
+            "preview.rate must be between 0 and 30 frames per second"
+        );
+        ensure!(self.preview.width >= 16, "preview.width must be at least 16");
+        ensure!(
+            (1..=100).contains(&self.preview.quality),
+            "preview.quality must be between 1 and 100"
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn missing_file_gives_the_defaults() {
+        let dir = TestDir::new("config-missing");
+        let path = dir.join("ai-trap.toml");
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.server.bind, ServerConfig::default().bind);
+        assert_eq!(config.camera, CameraConfig::default());
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn file_overrides_the_defaults() {
+        let dir = TestDir::new("config-load");
+        let path = dir.join("ai-trap.toml");
+        fs::write(
+            &path,
+            "[trap]\nlatitude = 51.5\nlongitude = -0.13\n\n\
+             [camera]\nsource = \"synthetic:320x240\"\nfps = 5.0\n\n\
+             [schedule]\nenabled = true\nend_offset_min = 30\n",
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.camera.source, "synthetic:320x240");
+        assert_eq!(config.camera.fps, 5.0);
+        assert_eq!(config.camera.when_full, FullPolicy::default());
+        assert_eq!(config.trap.latitude, Some(51.5));
+        assert!(config.schedule.enabled);
+        assert_eq!(config.schedule.end_offset_min, 30);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let dir = TestDir::new("config-unknown");
+        let path = dir.join("ai-trap.toml");
+        fs::write(&path, "[camera]\nfsp = 5.0\n").unwrap();
+        assert!(Config::load(&path).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_bad_values() {
+        let cases: [fn(&mut Config); 7] = [
+            |c| c.trap.id.clear(),
+            |c| c.trap.latitude = Some(91.0),
+            |c| c.camera.source = "webcam".to_string(),
+            |c| c.camera.fps = 500.0,
+            |c| c.detection.confidence = 1.0,
+            |c| c.detection.model = "insects 320".to_string(),
+            // sunset and sunrise without the trap position
+            |c| c.schedule.enabled = true,
+        ];
+        for (index, case) in cases.iter().enumerate() {
+            let mut config = Config::default();
+            case(&mut config);
+            assert!(config.validate().is_err(), "case {} passed", index);
+        }
+    }
+}
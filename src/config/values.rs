@@ -0,0 +1,203 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context as ErrContext, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use toml::{Table, Value};
+
+use crate::config::Config;
+use crate::database::saved_state::replace_file;
+use crate::messages::config::ConfigValue;
+
+/// Values that can be changed while the trap is running with `config.set`.
+/// Everything else is read once at startup.
+pub const TUNABLE: &[&str] = &[
+    "camera.resolution",
+    "camera.fps",
+    "camera.when_full",
+    "camera.controls.exposure",
+    "camera.controls.gain",
+    "camera.controls.white_balance",
+    "camera.controls.focus",
+    "detection.model",
+    "detection.confidence",
+    "detection.iou",
+    "detection.tiling.enabled",
+    "detection.tiling.overlap",
+    "detection.tiling.whole_frame",
+    "detection.tiling.batch",
+    "detection.classifier.model",
+    "detection.classifier.top_k",
+    "detection.regions.include",
+    "detection.regions.exclude",
+    "preview.rate",
+    "preview.width",
+    "preview.quality",
+    "preview.boxes",
+    "preview.regions",
+];
+
+// Configuration values travel as dotted keys with TOML literals as values,
+// e.g. `detection.confidence` = `0.3` or `camera.source` = `"camera:1"`.
+
+/// Every value whose key starts with `prefix`, all of them for an empty prefix
+pub fn get_values(config: &Config, prefix: &str) -> Result<Vec<ConfigValue>> {
+    let mut values = Vec::new();
+    flatten("", &Value::try_from(config)?, &mut values);
+    values.retain(|value| value.key.starts_with(prefix));
+    if values.is_empty() {
+        bail!("No configuration value {}", prefix);
+    }
+    Ok(values)
+}
+
+fn flatten(prefix: &str, value: &Value, values: &mut Vec<ConfigValue>) {
+    match value {
+        Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                flatten(&key, value, values);
+            }
+        }
+        value => values.push(ConfigValue { key: prefix.to_string(), value: value.to_string() }),
+    }
+}
+
+fn parse_value(value: &ConfigValue) -> Result<Value> {
+    let table: Table = toml::from_str(&format!("value = {}", value.value))
+        .with_context(|| format!("Invalid value for {}: {}", value.key, value.value))?;
+    table.get("value").cloned().ok_or_else(|| anyhow!("Missing value for {}", value.key))
+}
+
+fn set_value(table: &mut Table, key: &str, value: Value) -> Result<()> {
+    let (parents, name) = match key.rsplit_once('.') {
+        Some((parents, name)) => (parents.split('.').collect::<Vec<_>>(), name),
+        None => (Vec::new(), key),
+    };
+    let mut table = table;
+    for parent in parents {
+        table = table
+            .entry(parent)
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+            .with_context(|| format!("{} is not a table", parent))?;
+    }
+    table.insert(name.to_string(), value);
+    Ok(())
+}
+
+/// Apply tunable values to the configuration, all of them or none
+pub fn set_values(config: &mut Config, values: &[ConfigValue]) -> Result<()> {
+    let mut table = Table::try_from(&*config)?;
+    for value in values {
+        if !TUNABLE.contains(&value.key.as_str()) {
+            bail!("{} cannot be changed while the trap is running", value.key);
+        }
+        set_value(&mut table, &value.key, parse_value(value)?)?;
+    }
+    let updated: Config = table.try_into().context("Invalid configuration")?;
+    updated.validate()?;
+    *config = updated;
+    Ok(())
+}
+
+/// Bring one section of the configuration, as held by an actor, up to date
+/// with changed values. Returns whether any of them were for the section.
+pub fn update_section<T>(section: &mut T, name: &str, values: &[ConfigValue]) -> Result<bool>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let prefix = format!("{}.", name);
+    let mut table = Table::try_from(&*section)?;
+    let mut changed = false;
+    for value in values {
+        if let Some(key) = value.key.strip_prefix(&prefix) {
+            set_value(&mut table, key, parse_value(value)?)?;
+            changed = true;
+        }
+    }
+    if changed {
+        *section = table.try_into().with_context(|| format!("Invalid {} configuration", name))?;
+    }
+    Ok(changed)
+}
+
+/// Write changed values to the configuration file, keeping whatever else it
+/// holds. The file is replaced whole, a power cut leaves the old or the new one.
+pub fn save_values(path: &Path, values: &[ConfigValue]) -> Result<()> {
+    let mut table: Table = match fs::read_to_string(path) {
+        Ok(text) => toml::from_str(&text)
+            .with_context(|| format!("Invalid configuration in {}", path.display()))?,
+        Err(_) => Table::new(),
+    };
+    for value in values {
+        set_value(&mut table, &value.key, parse_value(value)?)?;
+    }
+    replace_file(path, toml::to_string_pretty(&table)?.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn value(key: &str, value: &str) -> ConfigValue {
+        ConfigValue { key: key.to_string(), value: value.to_string() }
+    }
+
+    #[test]
+    fn set_and_get_round_trip() {
+        let mut config = Config::default();
+        let values = [value("detection.confidence", "0.4"), value("camera.resolution", "\"1280x720\"")];
+        set_values(&mut config, &values).unwrap();
+        assert_eq!(config.detection.confidence, 0.4);
+        assert_eq!(config.camera.resolution.as_deref(), Some("1280x720"));
+
+        let values = get_values(&config, "detection.confidence").unwrap();
+        assert_eq!(values, [value("detection.confidence", "0.4")]);
+        let camera = get_values(&config, "camera.").unwrap();
+        assert!(camera.contains(&value("camera.resolution", "\"1280x720\"")));
+        assert!(get_values(&config, "nothing.here").is_err());
+    }
+
+    #[test]
+    fn rejects_values_that_are_not_tunable() {
+        let mut config = Config::default();
+        // all or nothing, the tunable value with it is not applied either
+        let values = [value("detection.confidence", "0.4"), value("server.bind", "\"0.0.0.0:1\"")];
+        let e = set_values(&mut config, &values).unwrap_err();
+        assert!(e.to_string().contains("server.bind"), "{}", e);
+        assert_eq!(config.detection.confidence, Config::default().detection.confidence);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let mut config = Config::default();
+        for (key, literal) in [
+            ("detection.confidence", "1.5"),
+            ("detection.confidence", "\"high\""),
+            ("camera.fps", "not toml"),
+            ("camera.when_full", "\"sometimes\""),
+        ] {
+            assert!(set_values(&mut config, &[value(key, literal)]).is_err(), "{} = {}", key, literal);
+        }
+        assert_eq!(config.detection.confidence, Config::default().detection.confidence);
+        assert_eq!(config.camera, Config::default().camera);
+    }
+
+    #[test]
+    fn saved_values_load_back() {
+        let dir = TestDir::new("config-values-save");
+        let path = dir.join("ai-trap.toml");
+        fs::write(&path, "# the trap at the pond\n[trap]\nid = \"pond\"\n").unwrap();
+        save_values(&path, &[value("detection.confidence", "0.35"), value("preview.width", "320")]).unwrap();
+
+        let config = Config::load(&path).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.trap.id, "pond");
+        assert_eq!(config.detection.confidence, 0.35);
+        assert_eq!(config.preview.width, 320);
+        assert!(!path.with_extension("tmp").exists());
+    }
+}
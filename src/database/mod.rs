@@ -0,0 +1,36 @@
+pub mod models;
+pub mod saved_state;
+
+use anyhow::Result;
+use native_db::*;
+use once_cell::sync::Lazy;
+
+use crate::database::models::{
+    DetectionModel, DetectionModelV1, DetectionModelV2, SessionModel, SessionModelV1, StillModel,
+};
+
+// ==============================================================================
+// Database
+// ==============================================================================
+
+pub(crate) static MODELS: Lazy<Models> = Lazy::new(|| {
+    let mut models = Models::new();
+    models.define::<SessionModelV1>().unwrap();
+    models.define::<SessionModel>().unwrap();
+    models.define::<DetectionModelV1>().unwrap();
+    models.define::<DetectionModelV2>().unwrap();
+    models.define::<DetectionModel>().unwrap();
+    models.define::<StillModel>().unwrap();
+    models
+});
+
+/// Open the trap database, creating it if it does not exist yet
+pub(crate) fn open_database(location: &str) -> Result<Database<'static>> {
+    let db = Builder::new().create(&MODELS, location)?;
+    // move records written by earlier versions to the current models
+    let rw = db.rw_transaction()?;
+    rw.migrate::<SessionModel>()?;
+    rw.migrate::<DetectionModel>()?;
+    rw.commit()?;
+    Ok(db)
+}
@@ -0,0 +1,294 @@
+use native_db::*;
+use native_model::{native_model, Model};
+use prost::Message as PbMessage;
+use serde::{Deserialize, Serialize};
+
+use crate::generated::sessions::{Detection, SessionDetails};
+use crate::messages::detections::{DetectionExtras, SpeciesScore as SpeciesScoreMsg};
+use crate::messages::identifiers::{DETECTION, STILL};
+use crate::messages::protobuf_msg::ProtobufMsg;
+use crate::messages::stills::Still;
+
+// ==============================================================================
+// Database models
+// ==============================================================================
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[native_model(id = 1, version = 3, from = DetectionModelV2)]
+#[native_db]
+pub(crate) struct DetectionModel {
+    #[primary_key]
+    pub(crate) detection: i32,
+    #[secondary_key]
+    pub(crate) session: String,
+    pub(crate) created: i64,
+    pub(crate) updated: i64,
+    pub(crate) score: f32,
+    pub(crate) clazz: i32,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) image: Vec<u8>,
+    /// Name and version of the model that found the detection, empty for
+    /// detections stored before models were recorded
+    pub(crate) model: String,
+    pub(crate) model_version: String,
+    /// Most likely species first, as named by the classifier when the crop
+    /// was taken. Empty without a classifier.
+    pub(crate) species: Vec<SpeciesScore>,
+    pub(crate) classifier: String,
+    pub(crate) classifier_version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct SpeciesScore {
+    pub(crate) label: String,
+    pub(crate) score: f32,
+}
+
+impl DetectionModel {
+    pub(crate) fn to_event(self) -> ProtobufMsg {
+        let mut payload = Detection {
+            session: self.session,
+            detection: self.detection,
+            created: self.created,
+            updated: self.updated,
+            score: self.score,
+            clazz: self.clazz,
+            width: self.width,
+            height: self.height,
+            image: Some(self.image),
+        }
+        .encode_to_vec();
+        // fields appended to a message are merged into it when it is decoded
+        DetectionExtras {
+            species: self
+                .species
+                .into_iter()
+                .map(|species| SpeciesScoreMsg { label: species.label, score: species.score })
+                .collect(),
+            model: self.model,
+            model_version: self.model_version,
+            classifier: self.classifier,
+            classifier_version: self.classifier_version,
+        }
+        .encode(&mut payload)
+        .expect("Vec grows to fit");
+        ProtobufMsg::new(DETECTION, payload)
+    }
+}
+
+#[native_model(id = 2, version = 2, from = SessionModelV1)]
+#[native_db]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct SessionModel {
+    #[primary_key]
+    pub(crate) session: String,
+    #[secondary_key]
+    pub(crate) active: i32,
+    pub(crate) opened: i64,
+    pub(crate) closed: Option<i64>,
+    /// Every model that found insects in the session, in the order they were
+    /// first used
+    pub(crate) models: Vec<SessionDetector>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct SessionDetector {
+    pub(crate) name: String,
+    pub(crate) version: String,
+}
+
+impl SessionModel {
+    pub(crate) fn to_event(self, event: &str, detections: i32) -> ProtobufMsg {
+        ProtobufMsg::new(
+            event,
+            SessionDetails {
+                session: self.session,
+                active: self.active == 1,
+                opened: self.opened,
+                closed: self.closed,
+                detections,
+            }
+            .encode_to_vec(),
+        )
+    }
+}
+
+/// A full resolution photo of the trap taken on request
+#[native_model(id = 3, version = 1)]
+#[native_db]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct StillModel {
+    #[primary_key]
+    pub(crate) still: i32,
+    /// The session active when the photo was taken, empty if there was none
+    #[secondary_key]
+    pub(crate) session: String,
+    pub(crate) created: i64,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) image: Vec<u8>,
+}
+
+impl StillModel {
+    pub(crate) fn into_event(self, with_image: bool) -> ProtobufMsg {
+        ProtobufMsg::new(
+            STILL,
+            Still {
+                still: self.still,
+                session: self.session,
+                created: self.created,
+                width: self.width,
+                height: self.height,
+                image: with_image.then_some(self.image),
+            }
+            .encode_to_vec(),
+        )
+    }
+}
+
+// ==============================================================================
+// Earlier versions, kept so that older databases can be migrated
+// ==============================================================================
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[native_model(id = 1, version = 1)]
+#[native_db]
+pub(crate) struct DetectionModelV1 {
+    #[primary_key]
+    pub(crate) detection: i32,
+    #[secondary_key]
+    pub(crate) session: String,
+    pub(crate) created: i64,
+    pub(crate) updated: i64,
+    pub(crate) score: f32,
+    pub(crate) clazz: i32,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) image: Vec<u8>,
+}
+
+impl From<DetectionModelV1> for DetectionModelV2 {
+    fn from(v1: DetectionModelV1) -> Self {
+        Self {
+            detection: v1.detection,
+            session: v1.session,
+            created: v1.created,
+            updated: v1.updated,
+            score: v1.score,
+            clazz: v1.clazz,
+            width: v1.width,
+            height: v1.height,
+            image: v1.image,
+            model: String::new(),
+            model_version: String::new(),
+        }
+    }
+}
+
+impl From<DetectionModelV2> for DetectionModelV1 {
+    fn from(v2: DetectionModelV2) -> Self {
+        Self {
+            detection: v2.detection,
+            session: v2.session,
+            created: v2.created,
+            updated: v2.updated,
+            score: v2.score,
+            clazz: v2.clazz,
+            width: v2.width,
+            height: v2.height,
+            image: v2.image,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[native_model(id = 1, version = 2, from = DetectionModelV1)]
+#[native_db]
+pub(crate) struct DetectionModelV2 {
+    #[primary_key]
+    pub(crate) detection: i32,
+    #[secondary_key]
+    pub(crate) session: String,
+    pub(crate) created: i64,
+    pub(crate) updated: i64,
+    pub(crate) score: f32,
+    pub(crate) clazz: i32,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) image: Vec<u8>,
+    pub(crate) model: String,
+    pub(crate) model_version: String,
+}
+
+impl From<DetectionModelV2> for DetectionModel {
+    fn from(v2: DetectionModelV2) -> Self {
+        Self {
+            detection: v2.detection,
+            session: v2.session,
+            created: v2.created,
+            updated: v2.updated,
+            score: v2.score,
+            clazz: v2.clazz,
+            width: v2.width,
+            height: v2.height,
+            image: v2.image,
+            model: v2.model,
+            model_version: v2.model_version,
+            species: Vec::new(),
+            classifier: String::new(),
+            classifier_version: String::new(),
+        }
+    }
+}
+
+impl From<DetectionModel> for DetectionModelV2 {
+    fn from(v3: DetectionModel) -> Self {
+        Self {
+            detection: v3.detection,
+            session: v3.session,
+            created: v3.created,
+            updated: v3.updated,
+            score: v3.score,
+            clazz: v3.clazz,
+            width: v3.width,
+            height: v3.height,
+            image: v3.image,
+            model: v3.model,
+            model_version: v3.model_version,
+        }
+    }
+}
+
+#[native_model(id = 2, version = 1)]
+#[native_db]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct SessionModelV1 {
+    #[primary_key]
+    pub(crate) session: String,
+    #[secondary_key]
+    pub(crate) active: i32,
+    pub(crate) opened: i64,
+    pub(crate) closed: Option<i64>,
+}
+
+impl From<SessionModelV1> for SessionModel {
+    fn from(v1: SessionModelV1) -> Self {
+        Self {
+            session: v1.session,
+            active: v1.active,
+            opened: v1.opened,
+            closed: v1.closed,
+            models: Vec::new(),
+        }
+    }
+}
+
+impl From<SessionModel> for SessionModelV1 {
+    fn from(v2: SessionModel) -> Self {
+        Self {
+            session: v2.session,
+            active: v2.active,
+            opened: v2.opened,
+            closed: v2.closed,
+        }
+    }
+}
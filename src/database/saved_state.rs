@@ -0,0 +1,79 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context as ErrContext, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::state::machine::TrapState;
+
+/// What the trap was doing, kept in a small file next to the database so that a
+/// trap that loses power while capturing picks up where it left off.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedState {
+    pub state: TrapState,
+}
+
+impl SavedState {
+    /// Read the saved state, a missing file means a fresh trap
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self { state: TrapState::Idle });
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("Invalid trap state in {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        replace_file(path, &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// Write through a temporary file so a power cut never leaves half a file.
+/// The file is synced before the rename and the directory after it, or the
+/// rename may reach the disk before the contents do.
+pub fn replace_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp)
+        .with_context(|| format!("Failed to create {}", tmp.display()))?;
+    file.write_all(contents)
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .with_context(|| format!("Failed to sync {}", dir.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn round_trip() {
+        let dir = TestDir::new("saved-state-round-trip");
+        let path = dir.join("state.json");
+        assert_eq!(SavedState::load(&path).unwrap().state, TrapState::Idle);
+
+        for state in [TrapState::Capturing, TrapState::Paused, TrapState::Idle] {
+            SavedState { state }.save(&path).unwrap();
+            assert_eq!(SavedState::load(&path).unwrap(), SavedState { state });
+        }
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn corrupt_file() {
+        let dir = TestDir::new("saved-state-corrupt");
+        let path = dir.join("state.json");
+        for text in ["", "{\"state\": \"Capt", "{\"state\": \"Flying\"}"] {
+            fs::write(&path, text).unwrap();
+            assert!(SavedState::load(&path).is_err(), "loaded {:?}", text);
+        }
+    }
+}
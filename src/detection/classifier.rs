@@ -0,0 +1,156 @@
+use std::cmp::Ordering;
+use std::path::Path;
+
+use anyhow::{ensure, Context as ErrContext, Result};
+use image::RgbImage;
+use log::debug;
+use ort::session::Session;
+use ort::value::{Tensor, ValueType};
+
+use crate::detection::crop::crop_image;
+use crate::detection::labels::Labels;
+use crate::detection::letterbox::Letterbox;
+use crate::detection::yolo::BoundingBox;
+
+/// Used when the model declares a dynamic input size
+const DEFAULT_INPUT_SIZE: u32 = 224;
+
+/// A species the classifier sees in a detection, with its probability
+#[derive(Clone, Debug, PartialEq)]
+pub struct Species {
+    pub label: String,
+    pub score: f32,
+}
+
+/// Second stage model naming the species in the crop of each detection.
+/// Takes `[1, 3, size, size]` RGB scaled to 0..1 and gives a score for each
+/// class, as YOLO classification models do. Class names come from the
+/// model's `.labels`.
+pub struct Classifier {
+    session: Session,
+    input_size: u32,
+    labels: Labels,
+    top_k: usize,
+}
+
+impl Classifier {
+    pub fn load(path: &Path, top_k: usize) -> Result<Self> {
+        let session = Session::builder()?
+            .commit_from_file(path)
+            .with_context(|| format!("Failed to load classifier {}", path.display()))?;
+
+        let input_size = session
+            .inputs
+            .first()
+            .and_then(|input| match &input.input_type {
+                ValueType::Tensor { shape, .. } if shape.len() == 4 && shape[3] > 0 => {
+                    Some(shape[3] as u32)
+                }
+                _ => None,
+            })
+            .unwrap_or(DEFAULT_INPUT_SIZE);
+        let labels = Labels::load(path)?;
+        debug!("Classifier {} loaded, input size {}, {} classes", path.display(), input_size, labels.names().len());
+
+        Ok(Self { session, input_size, labels, top_k })
+    }
+
+    pub fn set_top_k(&mut self, top_k: usize) {
+        self.top_k = top_k;
+    }
+
+    /// The most likely species for a detection, best first
+    pub fn classify(&mut self, image: &RgbImage, bbox: &BoundingBox) -> Result<Vec<Species>> {
+        let crop = crop_image(image, bbox);
+        let (tensor, _) = Letterbox::apply(&crop, self.input_size);
+
+        let size = self.input_size as usize;
+        let input = Tensor::from_array(([1usize, 3, size, size], tensor))?;
+        let outputs = self.session.run(ort::inputs![input])?;
+        let (_, data) = outputs[0].try_extract_tensor::<f32>()?;
+        ensure!(!data.is_empty(), "Classifier gave no scores");
+
+        Ok(rank(data, &self.labels, self.top_k))
+    }
+}
+
+/// The `top_k` most likely classes for the model's scores, best first
+fn rank(scores: &[f32], labels: &Labels, top_k: usize) -> Vec<Species> {
+    let mut ranked: Vec<(usize, f32)> = probabilities(scores).into_iter().enumerate().collect();
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    ranked
+        .into_iter()
+        .take(top_k)
+        .map(|(class, score)| Species { label: labels.name(class as i32), score })
+        .collect()
+}
+
+/// Models exported without their final softmax give raw scores
+fn probabilities(scores: &[f32]) -> Vec<f32> {
+    let sum: f32 = scores.iter().sum();
+    if scores.iter().all(|&score| (0.0..=1.0).contains(&score)) && (sum - 1.0).abs() < 0.01 {
+        return scores.to_vec();
+    }
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exp: Vec<f32> = scores.iter().map(|&score| (score - max).exp()).collect();
+    let total: f32 = exp.iter().sum();
+    exp.into_iter().map(|value| value / total).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn softmax_output_is_kept() {
+        let scores = [0.7, 0.2, 0.1];
+        assert_eq!(probabilities(&scores), scores);
+        // rounding in the model still counts as summing to one
+        let scores = [0.695, 0.2, 0.1];
+        assert_eq!(probabilities(&scores), scores);
+    }
+
+    #[test]
+    fn raw_logits_are_normalised() {
+        let e = std::f32::consts::E;
+        let total = 1.0 + e + e * e;
+        let expected = [1.0 / total, e / total, e * e / total];
+        assert_close(&probabilities(&[0.0, 1.0, 2.0]), &expected);
+        // large logits do not overflow
+        assert_close(&probabilities(&[1000.0, 1001.0, 1002.0]), &expected);
+        // negative scores cannot be probabilities even when they sum to one
+        let total = 1.0 + e.powi(3);
+        assert_close(&probabilities(&[-1.0, 2.0]), &[1.0 / total, e.powi(3) / total]);
+        // nor scores in range that do not sum to one
+        assert_close(&probabilities(&[0.5, 0.5, 0.5]), &[1.0 / 3.0; 3]);
+    }
+
+    #[test]
+    fn ranks_the_top_k() {
+        let dir = TestDir::new("classifier");
+        fs::write(dir.join("moths.labels"), "Noctua pronuba\nOurapteryx sambucaria\nAutographa gamma\n").unwrap();
+        let labels = Labels::load(&dir.join("moths.onnx")).unwrap();
+
+        let scores = [0.1, 0.2, 0.6, 0.1];
+        let species = |label: &str, score: f32| Species { label: label.to_string(), score };
+        assert_eq!(
+            rank(&scores, &labels, 2),
+            [species("Autographa gamma", 0.6), species("Ourapteryx sambucaria", 0.2)]
+        );
+        assert_eq!(rank(&scores, &labels, 1), [species("Autographa gamma", 0.6)]);
+        // a class beyond the labels is still named
+        assert_eq!(rank(&scores, &labels, 10).len(), 4);
+        assert_eq!(rank(&scores, &labels, 10)[3].label, "class 3");
+        assert!(rank(&scores, &labels, 0).is_empty());
+    }
+}
@@ -0,0 +1,41 @@
+use std::io::Cursor;
+
+use anyhow::{Context as ErrContext, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops;
+use image::RgbImage;
+
+use crate::detection::yolo::BoundingBox;
+
+const CROP_QUALITY: u8 = 90;
+
+/// A JPEG encoded cut-out of a detection
+pub struct Crop {
+    pub width: u32,
+    pub height: u32,
+    pub jpeg: Vec<u8>,
+}
+
+/// Cut the area of `bbox` out of the frame image and encode it as a JPEG
+pub fn crop_jpeg(image: &RgbImage, bbox: &BoundingBox) -> Result<Crop> {
+    let crop = crop_image(image, bbox);
+    let jpeg = encode_jpeg(&crop, CROP_QUALITY).context("Failed to encode crop")?;
+    Ok(Crop { width: crop.width(), height: crop.height(), jpeg })
+}
+
+/// Cut the area of `bbox` out of the frame image, at least one pixel
+pub fn crop_image(image: &RgbImage, bbox: &BoundingBox) -> RgbImage {
+    let (frame_width, frame_height) = image.dimensions();
+    let x = (bbox.x1.max(0.0) as u32).min(frame_width.saturating_sub(1));
+    let y = (bbox.y1.max(0.0) as u32).min(frame_height.saturating_sub(1));
+    let width = (bbox.width().ceil() as u32).clamp(1, frame_width - x);
+    let height = (bbox.height().ceil() as u32).clamp(1, frame_height - y);
+    imageops::crop_imm(image, x, y, width, height).to_image()
+}
+
+/// Encode a whole image as a JPEG
+pub fn encode_jpeg(image: &RgbImage, quality: u8) -> Result<Vec<u8>> {
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut Cursor::new(&mut jpeg), quality).encode_image(image)?;
+    Ok(jpeg)
+}
@@ -0,0 +1,150 @@
+use std::path::Path;
+
+use anyhow::{ensure, Context as ErrContext, Result};
+use image::imageops;
+use image::RgbImage;
+use log::debug;
+use ort::session::Session;
+use ort::value::{Tensor, ValueType};
+
+use crate::config::TilingConfig;
+use crate::detection::letterbox::Letterbox;
+use crate::detection::tiling::{merge_tiles, TileLayout, WHOLE_FRAME};
+use crate::detection::yolo::{self, BoundingBox};
+
+/// Used when the model declares a dynamic input size
+const DEFAULT_INPUT_SIZE: u32 = 320;
+
+/// A YOLO model together with the pre- and post-processing needed to turn
+/// camera frames into boxes.
+pub struct Detector {
+    session: Session,
+    input_size: u32,
+    // the model takes any number of images at once
+    dynamic_batch: bool,
+    confidence: f32,
+    iou_threshold: f32,
+    tiling: TilingConfig,
+    // layout of the last frame, while tiling
+    layout: Option<TileLayout>,
+}
+
+impl Detector {
+    pub fn load(path: &Path, confidence: f32, iou_threshold: f32) -> Result<Self> {
+        let session = Session::builder()?
+            .commit_from_file(path)
+            .with_context(|| format!("Failed to load model {}", path.display()))?;
+
+        let shape = session.inputs.first().and_then(|input| match &input.input_type {
+            ValueType::Tensor { shape, .. } if shape.len() == 4 => Some(shape.clone()),
+            _ => None,
+        });
+        let input_size = shape
+            .as_ref()
+            .filter(|shape| shape[3] > 0)
+            .map_or(DEFAULT_INPUT_SIZE, |shape| shape[3] as u32);
+        let dynamic_batch = shape.is_some_and(|shape| shape[0] < 0);
+        debug!("Model {} loaded, input size {}", path.display(), input_size);
+
+        Ok(Self {
+            session,
+            input_size,
+            dynamic_batch,
+            confidence,
+            iou_threshold,
+            tiling: TilingConfig::default(),
+            layout: None,
+        })
+    }
+
+    pub fn input_size(&self) -> u32 {
+        self.input_size
+    }
+
+    /// How the last frame was cut into tiles, `None` when not tiling
+    pub fn layout(&self) -> Option<TileLayout> {
+        self.layout
+    }
+
+    pub fn set_tiling(&mut self, tiling: &TilingConfig) {
+        self.tiling = tiling.clone();
+    }
+
+    pub fn set_thresholds(&mut self, confidence: f32, iou_threshold: f32) {
+        self.confidence = confidence;
+        self.iou_threshold = iou_threshold;
+    }
+
+    /// Run the model over a frame, returning boxes in frame coordinates
+    pub fn detect(&mut self, image: &RgbImage) -> Result<Vec<BoundingBox>> {
+        if self.tiling.enabled {
+            return self.detect_tiled(image);
+        }
+        self.layout = None;
+
+        let (tensor, letterbox) = Letterbox::apply(image, self.input_size);
+        let candidates = self.infer(tensor, 1)?.remove(0);
+        let boxes = yolo::non_max_suppression(candidates, self.iou_threshold)
+            .iter()
+            .map(|bbox| letterbox.map_to_frame(bbox))
+            .collect();
+        Ok(boxes)
+    }
+
+    /// Run the model over overlapping tiles at full resolution, so small
+    /// insects are not shrunk away, and merge what was found across the seams
+    fn detect_tiled(&mut self, image: &RgbImage) -> Result<Vec<BoundingBox>> {
+        let (width, height) = image.dimensions();
+        let layout = TileLayout::new(width, height, self.input_size, self.tiling.overlap);
+        let batch = if self.dynamic_batch { self.tiling.batch.max(1) } else { 1 };
+        let mut candidates = Vec::new();
+
+        // insects larger than a tile are only found whole on the full frame
+        if self.tiling.whole_frame {
+            let (tensor, letterbox) = Letterbox::apply(image, self.input_size);
+            let boxes = self.infer(tensor, 1)?.remove(0);
+            candidates.extend(boxes.iter().map(|bbox| (WHOLE_FRAME, letterbox.map_to_frame(bbox))));
+        }
+
+        for tiles in layout.tiles().chunks(batch) {
+            let mut tensor = Vec::new();
+            let mut letterboxes = Vec::with_capacity(tiles.len());
+            for tile in tiles {
+                let cut = imageops::crop_imm(image, tile.x, tile.y, tile.width, tile.height).to_image();
+                let (tile_tensor, letterbox) = Letterbox::apply(&cut, self.input_size);
+                tensor.extend(tile_tensor);
+                letterboxes.push(letterbox);
+            }
+            let found = self.infer(tensor, tiles.len())?;
+            for ((tile, letterbox), boxes) in tiles.iter().zip(letterboxes).zip(found) {
+                candidates.extend(
+                    boxes.iter().map(|bbox| (tile.index, tile.to_frame(&letterbox.map_to_frame(bbox)))),
+                );
+            }
+        }
+
+        self.layout = Some(layout);
+        Ok(merge_tiles(candidates, self.iou_threshold))
+    }
+
+    /// Run the model over `count` images stacked in `tensor`, returning the
+    /// candidate boxes of each in model input pixels
+    fn infer(&mut self, tensor: Vec<f32>, count: usize) -> Result<Vec<Vec<BoundingBox>>> {
+        let size = self.input_size as usize;
+        let input = Tensor::from_array(([count, 3, size, size], tensor))?;
+        let outputs = self.session.run(ort::inputs![input])?;
+        let (shape, data) = outputs[0].try_extract_tensor::<f32>()?;
+        ensure!(
+            shape.len() == 3 && shape[0] == count as i64 && !data.is_empty(),
+            "Unexpected model output shape {:?}",
+            &shape[..]
+        );
+
+        let item = [1, shape[1], shape[2]];
+        let candidates = data
+            .chunks(data.len() / count)
+            .map(|data| yolo::decode(&item, data, self.confidence))
+            .collect();
+        Ok(candidates)
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as ErrContext, Result};
+use log::warn;
+
+use crate::database::models::DetectionModel;
+use crate::detection::registry::{model_name, ModelRegistry};
+
+/// Class names for a model, one per line in a `.labels` file next to the model,
+/// e.g. `models/insects-320.labels` for `models/insects-320.onnx`. The line
+/// number is the class id, so a blank line leaves its class without a name.
+#[derive(Debug, Clone, Default)]
+pub struct Labels {
+    names: Vec<String>,
+}
+
+impl Labels {
+    pub fn sidecar_path(model_path: &Path) -> PathBuf {
+        model_path.with_extension("labels")
+    }
+
+    pub fn load(model_path: &Path) -> Result<Self> {
+        let path = Self::sidecar_path(model_path);
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read labels {}", path.display()))?;
+        let mut names: Vec<String> = text.lines().map(|line| line.trim().to_string()).collect();
+        while names.last().is_some_and(String::is_empty) {
+            names.pop();
+        }
+        Ok(Self { names })
+    }
+
+    /// Name of each class in id order, empty for a class without one
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Name of a class, if the model has a label for it
+    pub fn get(&self, clazz: i32) -> Option<&str> {
+        usize::try_from(clazz)
+            .ok()
+            .and_then(|index| self.names.get(index))
+            .map(String::as_str)
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Name of a class, or a placeholder when the model has no label for it
+    pub fn name(&self, clazz: i32) -> String {
+        self.get(clazz)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("class {}", clazz))
+    }
+}
+
+/// Class names of the model that found each detection, read from the
+/// registry the first time a model is seen
+pub struct ModelLabels {
+    registry: ModelRegistry,
+    /// Model assumed for detections stored before the model was recorded
+    fallback: String,
+    loaded: HashMap<String, Labels>,
+}
+
+impl ModelLabels {
+    pub fn new(registry: ModelRegistry, fallback: &str) -> Self {
+        Self {
+            registry,
+            fallback: fallback.to_string(),
+            loaded: HashMap::new(),
+        }
+    }
+
+    /// Labels of a model, of the fallback model when the name is empty
+    pub fn labels(&mut self, model: &str) -> &Labels {
+        let model = if model.is_empty() { self.fallback.as_str() } else { model };
+        let registry = &self.registry;
+        self.loaded.entry(model.to_string()).or_insert_with(|| {
+            Labels::load(&registry.model_path(model_name(model))).unwrap_or_else(|e| {
+                warn!("{:#}, the classes of {} have no names", e, model);
+                Labels::default()
+            })
+        })
+    }
+
+    /// Taxon name of the detection's class, empty when the model has no
+    /// label for it rather than a made-up name
+    pub fn name(&mut self, detection: &DetectionModel) -> String {
+        self.labels(&detection.model).get(detection.clazz).unwrap_or_default().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn blank_lines_keep_their_class_ids() {
+        let dir = TestDir::new("labels");
+        let model = dir.join("insects.onnx");
+        fs::write(dir.join("insects.labels"), "Noctuidae\n\n  Geometridae \r\n\n\n").unwrap();
+
+        let labels = Labels::load(&model).unwrap();
+        assert_eq!(labels.names().len(), 3);
+        assert_eq!(labels.get(0), Some("Noctuidae"));
+        assert_eq!(labels.get(1), None);
+        assert_eq!(labels.get(2), Some("Geometridae"));
+        assert_eq!(labels.get(3), None);
+        assert_eq!(labels.get(-1), None);
+        assert_eq!(labels.name(1), "class 1");
+        assert_eq!(labels.name(2), "Geometridae");
+    }
+}
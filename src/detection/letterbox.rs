@@ -0,0 +1,124 @@
+use image::imageops::{self, FilterType};
+use image::{Rgb, RgbImage};
+
+use crate::detection::yolo::BoundingBox;
+
+/// Grey used by the YOLO training pipelines to fill the padding
+const PAD_COLOUR: Rgb<u8> = Rgb([114, 114, 114]);
+
+/// The transform applied when fitting a frame into the square model input, kept so that
+/// boxes found by the model can be mapped back onto the original frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Letterbox {
+    scale: f32,
+    pad_x: f32,
+    pad_y: f32,
+    frame_width: u32,
+    frame_height: u32,
+}
+
+impl Letterbox {
+    /// Resize `image` to fit a `size` x `size` square keeping its aspect ratio, pad the
+    /// remainder and return the pixels as a normalised CHW tensor.
+    pub fn apply(image: &RgbImage, size: u32) -> (Vec<f32>, Self) {
+        let (width, height) = image.dimensions();
+        let scale = (size as f32 / width as f32).min(size as f32 / height as f32);
+        let resized_width = ((width as f32 * scale).round() as u32).clamp(1, size);
+        let resized_height = ((height as f32 * scale).round() as u32).clamp(1, size);
+        let pad_x = (size - resized_width) / 2;
+        let pad_y = (size - resized_height) / 2;
+
+        let resized = imageops::resize(image, resized_width, resized_height, FilterType::Triangle);
+        let mut canvas = RgbImage::from_pixel(size, size, PAD_COLOUR);
+        imageops::replace(&mut canvas, &resized, pad_x as i64, pad_y as i64);
+
+        let plane = (size * size) as usize;
+        let mut tensor = vec![0f32; plane * 3];
+        for (i, pixel) in canvas.pixels().enumerate() {
+            tensor[i] = pixel[0] as f32 / 255.0;
+            tensor[plane + i] = pixel[1] as f32 / 255.0;
+            tensor[2 * plane + i] = pixel[2] as f32 / 255.0;
+        }
+
+        let letterbox = Self {
+            scale,
+            pad_x: pad_x as f32,
+            pad_y: pad_y as f32,
+            frame_width: width,
+            frame_height: height,
+        };
+        (tensor, letterbox)
+    }
+
+    /// Map a box in model input coordinates back onto the original frame
+    pub fn map_to_frame(&self, bbox: &BoundingBox) -> BoundingBox {
+        let max_x = self.frame_width as f32;
+        let max_y = self.frame_height as f32;
+        BoundingBox {
+            x1: ((bbox.x1 - self.pad_x) / self.scale).clamp(0.0, max_x),
+            y1: ((bbox.y1 - self.pad_y) / self.scale).clamp(0.0, max_y),
+            x2: ((bbox.x2 - self.pad_x) / self.scale).clamp(0.0, max_x),
+            y2: ((bbox.y2 - self.pad_y) / self.scale).clamp(0.0, max_y),
+            ..*bbox
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bbox(x1: f32, y1: f32, x2: f32, y2: f32) -> BoundingBox {
+        BoundingBox { x1, y1, x2, y2, score: 0.8, clazz: 3 }
+    }
+
+    #[test]
+    fn wide_frame_is_padded_above_and_below() {
+        let frame = RgbImage::from_pixel(640, 320, Rgb([255, 0, 0]));
+        let (tensor, letterbox) = Letterbox::apply(&frame, 320);
+        assert_eq!(tensor.len(), 3 * 320 * 320);
+        assert_eq!((letterbox.scale, letterbox.pad_x, letterbox.pad_y), (0.5, 0.0, 80.0));
+
+        let plane = 320 * 320;
+        let pixel = |x: usize, y: usize| -> [f32; 3] {
+            let i = y * 320 + x;
+            [tensor[i], tensor[plane + i], tensor[2 * plane + i]]
+        };
+        let grey = 114.0 / 255.0;
+        assert_eq!(pixel(160, 0), [grey; 3]);
+        assert_eq!(pixel(160, 79), [grey; 3]);
+        assert_eq!(pixel(160, 80), [1.0, 0.0, 0.0]);
+        assert_eq!(pixel(160, 239), [1.0, 0.0, 0.0]);
+        assert_eq!(pixel(160, 240), [grey; 3]);
+    }
+
+    #[test]
+    fn boxes_map_back_onto_the_frame() {
+        // 640x320 into 320: half size, 80 pixels of padding above
+        let frame = RgbImage::new(640, 320);
+        let (_, letterbox) = Letterbox::apply(&frame, 320);
+        assert_eq!(
+            letterbox.map_to_frame(&bbox(10.0, 90.0, 50.0, 130.0)),
+            bbox(20.0, 20.0, 100.0, 100.0)
+        );
+
+        // 200x400 into 320: scaled up by 0.8 with 80 pixels of padding at the sides
+        let frame = RgbImage::new(200, 400);
+        let (_, letterbox) = Letterbox::apply(&frame, 320);
+        assert_eq!((letterbox.scale, letterbox.pad_x, letterbox.pad_y), (0.8, 80.0, 0.0));
+        assert_eq!(
+            letterbox.map_to_frame(&bbox(120.0, 40.0, 200.0, 120.0)),
+            bbox(50.0, 50.0, 150.0, 150.0)
+        );
+    }
+
+    #[test]
+    fn boxes_in_the_padding_are_clamped_to_the_frame() {
+        let frame = RgbImage::new(640, 320);
+        let (_, letterbox) = Letterbox::apply(&frame, 320);
+        assert_eq!(
+            letterbox.map_to_frame(&bbox(-5.0, 40.0, 330.0, 300.0)),
+            bbox(0.0, 0.0, 640.0, 320.0)
+        );
+    }
+}
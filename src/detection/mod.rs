@@ -0,0 +1,9 @@
+pub mod letterbox;
+pub mod yolo;
+pub mod detector;
+pub mod crop;
+pub mod labels;
+pub mod registry;
+pub mod tiling;
+pub mod classifier;
+pub mod regions;
@@ -0,0 +1,104 @@
+use crate::config::RegionsConfig;
+use crate::detection::yolo::BoundingBox;
+
+/// Whether a box on a `width` x `height` frame is kept, judged by its centre
+pub fn admits(regions: &RegionsConfig, bbox: &BoundingBox, width: u32, height: u32) -> bool {
+    let x = ((bbox.x1 + bbox.x2) / 2.0) as f64 / width.max(1) as f64;
+    let y = ((bbox.y1 + bbox.y2) / 2.0) as f64 / height.max(1) as f64;
+    let included = regions.include.is_empty()
+        || regions.include.iter().any(|region| contains(&region.points, x, y));
+    included && !regions.exclude.iter().any(|region| contains(&region.points, x, y))
+}
+
+/// Even-odd test of a point against a polygon, both in the same units. A point
+/// on the left or top edge is inside and one on the right or bottom edge is
+/// outside, so a point on an edge shared by two regions is in exactly one.
+pub fn contains(points: &[[f64; 2]], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    let mut previous = match points.last() {
+        Some(&point) => point,
+        None => return false,
+    };
+    for &point in points {
+        let ([x1, y1], [x2, y2]) = (previous, point);
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+            inside = !inside;
+        }
+        previous = point;
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Region;
+
+    /// A U opening upwards, with a notch from x 0.4 to 0.6 down to y 0.6
+    const U: [[f64; 2]; 8] = [
+        [0.2, 0.2], [0.4, 0.2], [0.4, 0.6], [0.6, 0.6],
+        [0.6, 0.2], [0.8, 0.2], [0.8, 0.8], [0.2, 0.8],
+    ];
+    const SQUARE: [[f64; 2]; 4] = [[0.0, 0.0], [0.5, 0.0], [0.5, 0.5], [0.0, 0.5]];
+    const NEXT_SQUARE: [[f64; 2]; 4] = [[0.5, 0.0], [1.0, 0.0], [1.0, 0.5], [0.5, 0.5]];
+
+    fn region(points: &[[f64; 2]]) -> Region {
+        Region { name: "test".to_string(), points: points.to_vec() }
+    }
+
+    /// A 20 pixel box centred on (`x`, `y`) of a 1000 x 500 frame
+    fn centred(x: f32, y: f32) -> BoundingBox {
+        BoundingBox { x1: x - 10.0, y1: y - 10.0, x2: x + 10.0, y2: y + 10.0, score: 0.9, clazz: 0 }
+    }
+
+    #[test]
+    fn concave_polygon() {
+        assert!(contains(&U, 0.3, 0.4));
+        assert!(contains(&U, 0.7, 0.4));
+        assert!(contains(&U, 0.5, 0.7));
+        assert!(!contains(&U, 0.5, 0.4));
+        assert!(!contains(&U, 0.5, 0.1));
+        assert!(!contains(&U, 0.9, 0.5));
+    }
+
+    #[test]
+    fn points_on_edges() {
+        assert!(contains(&SQUARE, 0.0, 0.25));
+        assert!(contains(&SQUARE, 0.25, 0.0));
+        assert!(!contains(&SQUARE, 0.5, 0.25));
+        assert!(!contains(&SQUARE, 0.25, 0.5));
+        for y in [0.1, 0.25, 0.4] {
+            assert!(contains(&SQUARE, 0.5, y) != contains(&NEXT_SQUARE, 0.5, y));
+        }
+    }
+
+    #[test]
+    fn too_few_points_contain_nothing() {
+        assert!(!contains(&[], 0.5, 0.5));
+        assert!(!contains(&[[0.0, 0.0], [1.0, 1.0]], 0.5, 0.5));
+    }
+
+    #[test]
+    fn no_regions_admit_everything() {
+        assert!(admits(&RegionsConfig::default(), &centred(500.0, 250.0), 1000, 500));
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let foot = region(&[[0.2, 0.6], [0.4, 0.6], [0.4, 0.8], [0.2, 0.8]]);
+        let regions = RegionsConfig { include: vec![region(&U)], exclude: vec![foot] };
+        // Centres normalise to (0.3, 0.4), (0.5, 0.4) and (0.3, 0.7)
+        assert!(admits(&regions, &centred(300.0, 200.0), 1000, 500));
+        assert!(!admits(&regions, &centred(500.0, 200.0), 1000, 500));
+        assert!(!admits(&regions, &centred(300.0, 350.0), 1000, 500));
+    }
+
+    #[test]
+    fn judged_by_the_centre() {
+        let regions = RegionsConfig { include: vec![region(&SQUARE)], exclude: Vec::new() };
+        let straddling = BoundingBox { x1: 100.0, y1: 50.0, x2: 700.0, y2: 150.0, score: 0.9, clazz: 0 };
+        assert!(admits(&regions, &straddling, 1000, 500));
+        let mostly_inside = BoundingBox { x1: 0.0, y1: 0.0, x2: 1000.0, y2: 100.0, score: 0.9, clazz: 0 };
+        assert!(!admits(&regions, &mostly_inside, 1000, 500));
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context as ErrContext, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::detection::labels::Labels;
+
+const MODEL_EXTENSION: &str = "onnx";
+const LABELS_EXTENSION: &str = "labels";
+const MANIFEST_EXTENSION: &str = "toml";
+/// Uploads are written here, inside the registry so installing them is a rename
+const STAGING_DIR: &str = ".upload";
+
+/// Tells apart uploads of the same model, so each has its own staged files
+static UPLOADS: AtomicU64 = AtomicU64::new(0);
+
+/// The model that produced a set of detections
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModelId {
+    pub name: String,
+    pub version: String,
+}
+
+impl fmt::Display for ModelId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.version)
+    }
+}
+
+/// Optional `<name>.toml` next to a model
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Manifest {
+    version: String,
+}
+
+/// A model installed in the registry
+#[derive(Clone, Debug)]
+pub struct ModelEntry {
+    pub id: ModelId,
+    pub path: PathBuf,
+    pub size: u64,
+    /// Number of class names in the `.labels` file, 0 without one
+    pub labels: usize,
+}
+
+/// An uploaded model waiting in the staging directory to be installed
+#[derive(Clone, Debug)]
+pub struct StagedModel {
+    pub name: String,
+    /// Written to the manifest, the SHA-256 identifies the model when empty
+    pub version: String,
+    /// Class names, the model has no `.labels` when empty
+    pub labels: Vec<String>,
+    /// Where the model file is written during the upload, unique to this upload
+    pub path: PathBuf,
+}
+
+impl StagedModel {
+    /// Remove what is left of an upload that will not be installed
+    pub fn discard(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A directory of ONNX models the detector can switch between. Each model
+/// is `<name>.onnx` with optional `<name>.labels` class names and a
+/// `<name>.toml` manifest giving its `version`. Models without a version
+/// are identified by the start of their SHA-256.
+#[derive(Clone, Debug)]
+pub struct ModelRegistry {
+    dir: PathBuf,
+    // installs hold it for writing so a model is never read half replaced
+    lock: Arc<RwLock<()>>,
+    // hashes of models without a manifest version, by path, kept while the
+    // file's modification time and size stay the same
+    hashes: Arc<Mutex<HashMap<PathBuf, ModelHash>>>,
+}
+
+/// The version worked out from a model file, with what the file looked like
+#[derive(Debug)]
+struct ModelHash {
+    modified: SystemTime,
+    size: u64,
+    version: String,
+}
+
+/// A file of the installed model replaced by an install, with the staged
+/// file taking its place and where the installed one is kept until the
+/// model file has moved
+struct Sidecar {
+    installed: PathBuf,
+    staged: Option<PathBuf>,
+    backup: PathBuf,
+}
+
+impl ModelRegistry {
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf(), lock: Arc::default(), hashes: Arc::default() }
+    }
+
+    /// Hold while reading a model with its labels and manifest, so an
+    /// install cannot swap them part way through
+    pub fn read(&self) -> RwLockReadGuard<'_, ()> {
+        self.lock.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Path of the model file for `name`, whether or not it is installed
+    pub fn model_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, MODEL_EXTENSION))
+    }
+
+    /// Start an upload of a model, the caller writes the model file to the
+    /// staged `path`
+    pub fn stage(&self, name: &str, version: &str, labels: Vec<String>) -> Result<StagedModel> {
+        check_name(name)?;
+        let dir = self.dir.join(STAGING_DIR);
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let upload = UPLOADS.fetch_add(1, Ordering::Relaxed);
+        Ok(StagedModel {
+            name: name.to_string(),
+            version: version.to_string(),
+            labels,
+            path: dir.join(format!("{}.{}-{}.{}", name, started, upload, MODEL_EXTENSION)),
+        })
+    }
+
+    /// Move a staged model into the registry, replacing a model of the same
+    /// name. The labels and manifest go in first and the model file last, all
+    /// under the write lock. When a step fails the files of the installed
+    /// model are put back.
+    pub fn install(&self, staged: &StagedModel) -> Result<ModelEntry> {
+        let target = self.model_path(&staged.name);
+        let labels = (!staged.labels.is_empty()).then(|| staged.labels.join("\n") + "\n");
+        let manifest = match staged.version.as_str() {
+            "" => None,
+            version => Some(toml::to_string(&Manifest { version: version.to_string() })?),
+        };
+        let mut sidecars = Vec::new();
+        for (extension, contents) in [(LABELS_EXTENSION, labels), (MANIFEST_EXTENSION, manifest)] {
+            let path = staged.path.with_extension(extension);
+            let backup = staged.path.with_extension(format!("{}.old", extension));
+            let staged = match contents {
+                Some(contents) => {
+                    fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+                    Some(path)
+                }
+                None => None,
+            };
+            sidecars.push(Sidecar {
+                installed: target.with_extension(extension),
+                staged,
+                backup,
+            });
+        }
+
+        let _installing = self.lock.write().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = Self::replace(&sidecars, &staged.path, &target) {
+            Self::roll_back(&sidecars);
+            return Err(e);
+        }
+        for sidecar in &sidecars {
+            let _ = fs::remove_file(&sidecar.backup);
+        }
+        self.find(&staged.name)
+    }
+
+    fn replace(sidecars: &[Sidecar], model: &Path, target: &Path) -> Result<()> {
+        for sidecar in sidecars {
+            // an earlier version of the model may have a file the new one does not
+            match fs::rename(&sidecar.installed, &sidecar.backup) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    return Err(e).with_context(|| format!("Failed to replace {}", sidecar.installed.display()))
+                }
+                _ => {}
+            }
+            if let Some(staged) = &sidecar.staged {
+                fs::rename(staged, &sidecar.installed)
+                    .with_context(|| format!("Failed to install {}", sidecar.installed.display()))?;
+            }
+        }
+        fs::rename(model, target).with_context(|| format!("Failed to install {}", target.display()))
+    }
+
+    /// Put back the files of the installed model after a failed install
+    fn roll_back(sidecars: &[Sidecar]) {
+        for sidecar in sidecars.iter().rev() {
+            if sidecar.backup.exists() {
+                let _ = fs::rename(&sidecar.backup, &sidecar.installed);
+            } else if sidecar.staged.as_ref().is_some_and(|staged| !staged.exists()) {
+                let _ = fs::remove_file(&sidecar.installed);
+            }
+            if let Some(staged) = &sidecar.staged {
+                let _ = fs::remove_file(staged);
+            }
+        }
+    }
+
+    /// Every installed model, by name
+    pub fn list(&self) -> Result<Vec<ModelEntry>> {
+        let _reading = self.read();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.dir.display())),
+        };
+
+        let mut models = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == MODEL_EXTENSION) {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                models.push(self.find(&name)?);
+            }
+        }
+        models.sort_by(|a, b| a.id.name.cmp(&b.id.name));
+        Ok(models)
+    }
+
+    /// Look up an installed model. The name may also be given as the path of
+    /// the model file, as older configurations do. Hold `read` while using
+    /// the entry's files.
+    pub fn find(&self, name: &str) -> Result<ModelEntry> {
+        let name = model_name(name);
+        check_name(name)?;
+        let path = self.model_path(name);
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                bail!("No model {} in {}", name, self.dir.display())
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let labels = Labels::load(&path).map(|labels| labels.names().len()).unwrap_or(0);
+
+        Ok(ModelEntry {
+            id: ModelId { name: name.to_string(), version: self.version(&path, &metadata)? },
+            path,
+            size: metadata.len(),
+            labels,
+        })
+    }
+
+    /// Version from the manifest, or the start of the SHA-256 of the model.
+    /// Hashing reads the whole model, so the hash is only worked out again
+    /// once the file changes.
+    fn version(&self, path: &Path, metadata: &fs::Metadata) -> Result<String> {
+        let manifest_path = path.with_extension(MANIFEST_EXTENSION);
+        if manifest_path.exists() {
+            let text = fs::read_to_string(&manifest_path)
+                .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+            let manifest: Manifest = toml::from_str(&text)
+                .with_context(|| format!("Invalid manifest {}", manifest_path.display()))?;
+            if !manifest.version.is_empty() {
+                return Ok(manifest.version);
+            }
+        }
+
+        let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+        let size = metadata.len();
+        let mut hashes = self.hashes.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(hash) = hashes.get(path) {
+            if hash.modified == modified && hash.size == size {
+                return Ok(hash.version.clone());
+            }
+        }
+        let mut file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher).with_context(|| format!("Failed to read {}", path.display()))?;
+        let version = to_hex(&hasher.finalize()[..6]);
+        hashes.insert(path.to_path_buf(), ModelHash { modified, size, version: version.clone() });
+        Ok(version)
+    }
+}
+
+/// The model name for a name or model path, `models/insects-320.onnx` is `insects-320`
+pub fn model_name(name: &str) -> &str {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    name.strip_suffix(".onnx").unwrap_or(name)
+}
+
+/// Model names become file names, so keep them to plain characters
+pub fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        bail!("Invalid model name '{}'", name);
+    }
+    Ok(())
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    /// Install version 1 of `insects` the way an operator would, by copying files in
+    fn installed(dir: &Path, manifest: bool) {
+        fs::write(dir.join("insects.onnx"), "model 1").unwrap();
+        fs::write(dir.join("insects.labels"), "Noctuidae\n").unwrap();
+        if manifest {
+            fs::write(dir.join("insects.toml"), "version = \"1\"\n").unwrap();
+        }
+    }
+
+    fn upload(registry: &ModelRegistry, version: &str, labels: &[&str]) -> StagedModel {
+        let labels = labels.iter().map(|label| label.to_string()).collect();
+        let staged = registry.stage("insects", version, labels).unwrap();
+        fs::write(&staged.path, "model 2").unwrap();
+        staged
+    }
+
+    fn read(dir: &Path, file: &str) -> Option<String> {
+        fs::read_to_string(dir.join(file)).ok()
+    }
+
+    /// Nothing of the upload is left in the staging directory
+    fn assert_staging_empty(dir: &Path) {
+        let left: Vec<_> = fs::read_dir(dir.join(STAGING_DIR)).unwrap().map(|e| e.unwrap().path()).collect();
+        assert!(left.is_empty(), "{:?}", left);
+    }
+
+    #[test]
+    fn install_replaces_a_model() {
+        let dir = TestDir::new("registry-replace");
+        installed(&dir, true);
+        let registry = ModelRegistry::new(&dir);
+        assert_eq!(registry.find("insects").unwrap().id.version, "1");
+
+        let staged = upload(&registry, "2", &["Noctuidae", "Geometridae"]);
+        let entry = registry.install(&staged).unwrap();
+        assert_eq!(entry.id, ModelId { name: "insects".to_string(), version: "2".to_string() });
+        assert_eq!(entry.labels, 2);
+        assert_eq!(entry.size, 7);
+        assert_eq!(read(&dir, "insects.onnx").unwrap(), "model 2");
+        assert_eq!(read(&dir, "insects.labels").unwrap(), "Noctuidae\nGeometridae\n");
+        assert_eq!(read(&dir, "insects.toml").unwrap(), "version = \"2\"\n");
+        assert_staging_empty(&dir);
+        assert_eq!(registry.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn install_removes_files_the_new_model_lacks() {
+        let dir = TestDir::new("registry-stale");
+        installed(&dir, true);
+        let registry = ModelRegistry::new(&dir);
+
+        let entry = registry.install(&upload(&registry, "", &[])).unwrap();
+        assert_eq!(read(&dir, "insects.onnx").unwrap(), "model 2");
+        assert_eq!(read(&dir, "insects.labels"), None);
+        assert_eq!(read(&dir, "insects.toml"), None);
+        assert_eq!(entry.labels, 0);
+        // identified by its hash once the manifest has gone
+        assert_eq!(entry.id.version, to_hex(&Sha256::digest("model 2")[..6]));
+        assert_staging_empty(&dir);
+    }
+
+    #[test]
+    fn failed_install_puts_the_old_model_back() {
+        for manifest in [true, false] {
+            let dir = TestDir::new(&format!("registry-roll-back-{}", manifest));
+            installed(&dir, manifest);
+            let registry = ModelRegistry::new(&dir);
+
+            // the model file is never written, so moving it in fails after
+            // the labels and manifest have been replaced
+            let staged = registry.stage("insects", "2", vec!["Geometridae".to_string()]).unwrap();
+            let error = registry.install(&staged).unwrap_err();
+            assert!(format!("{:#}", error).starts_with("Failed to install"), "{:#}", error);
+
+            assert_eq!(read(&dir, "insects.onnx").unwrap(), "model 1");
+            assert_eq!(read(&dir, "insects.labels").unwrap(), "Noctuidae\n");
+            if manifest {
+                assert_eq!(read(&dir, "insects.toml").unwrap(), "version = \"1\"\n");
+            } else {
+                assert_eq!(read(&dir, "insects.toml"), None);
+            }
+            assert_staging_empty(&dir);
+        }
+    }
+
+    #[test]
+    fn names_cannot_leave_the_registry() {
+        let dir = TestDir::new("registry-names");
+        let registry = ModelRegistry::new(&dir);
+        for name in ["../insects", "..", "a/b", "a\\b", ".upload", "", "insects 320"] {
+            assert!(registry.stage(name, "", Vec::new()).is_err(), "{}", name);
+            assert!(check_name(name).is_err(), "{}", name);
+        }
+        assert!(!dir.join(STAGING_DIR).exists());
+        check_name("insects-320_v1.2").unwrap();
+
+        assert_eq!(model_name("models/insects-320.onnx"), "insects-320");
+        assert_eq!(model_name("C:\\models\\insects.onnx"), "insects");
+        assert_eq!(model_name("insects"), "insects");
+        // a path given as a model name only ever names a file in the registry
+        assert_eq!(model_name("../../etc/passwd"), "passwd");
+        assert!(registry.find("..").is_err());
+    }
+}
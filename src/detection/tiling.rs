@@ -0,0 +1,258 @@
+use std::cmp::Ordering;
+
+use crate::detection::yolo::BoundingBox;
+
+/// Boxes from different tiles are taken for one insect cut by a seam when
+/// this much of the smaller lies inside the larger
+const SEAM_OVERLAP: f32 = 0.6;
+
+/// Tile index given to boxes found on the whole frame
+pub const WHOLE_FRAME: usize = usize::MAX;
+
+/// How a frame is cut into overlapping tiles of the model input size.
+/// Tiles are spread evenly, so neighbours overlap by at least `overlap` pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileLayout {
+    pub frame_width: u32,
+    pub frame_height: u32,
+    pub tile_size: u32,
+    pub columns: u32,
+    pub rows: u32,
+    pub overlap: u32,
+}
+
+/// One tile, in frame pixels. Tiles on a frame smaller than the tile size
+/// are cut short.
+#[derive(Clone, Copy, Debug)]
+pub struct Tile {
+    pub index: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    /// Move a box found in the tile onto the frame
+    pub fn to_frame(self, bbox: &BoundingBox) -> BoundingBox {
+        BoundingBox {
+            x1: bbox.x1 + self.x as f32,
+            y1: bbox.y1 + self.y as f32,
+            x2: bbox.x2 + self.x as f32,
+            y2: bbox.y2 + self.y as f32,
+            ..*bbox
+        }
+    }
+}
+
+impl TileLayout {
+    /// `overlap` is the fraction of the tile shared with each neighbour
+    pub fn new(frame_width: u32, frame_height: u32, tile_size: u32, overlap: f64) -> Self {
+        let overlap = (tile_size as f64 * overlap).round() as u32;
+        Self {
+            frame_width,
+            frame_height,
+            tile_size,
+            columns: count(frame_width, tile_size, overlap),
+            rows: count(frame_height, tile_size, overlap),
+            overlap,
+        }
+    }
+
+    pub fn tiles(&self) -> Vec<Tile> {
+        let xs = starts(self.frame_width, self.tile_size, self.columns);
+        let ys = starts(self.frame_height, self.tile_size, self.rows);
+        let mut tiles = Vec::with_capacity(xs.len() * ys.len());
+        for &y in &ys {
+            for &x in &xs {
+                tiles.push(Tile {
+                    index: tiles.len(),
+                    x,
+                    y,
+                    width: self.tile_size.min(self.frame_width),
+                    height: self.tile_size.min(self.frame_height),
+                });
+            }
+        }
+        tiles
+    }
+}
+
+/// Tiles needed to cover `length` with at least `overlap` between them
+fn count(length: u32, tile_size: u32, overlap: u32) -> u32 {
+    if length <= tile_size {
+        return 1;
+    }
+    let stride = tile_size.saturating_sub(overlap).max(1);
+    (length - overlap).div_ceil(stride).max(2)
+}
+
+fn starts(length: u32, tile_size: u32, count: u32) -> Vec<u32> {
+    if count <= 1 {
+        return vec![0];
+    }
+    let span = (length - tile_size) as f64;
+    (0..count)
+        .map(|i| (span * i as f64 / (count - 1) as f64).round() as u32)
+        .collect()
+}
+
+/// Non-maximum suppression over the boxes of all tiles, highest scores first.
+/// An insect on a seam is found in both tiles and often cut short in one, so
+/// boxes from different tiles are also merged when one lies mostly inside
+/// the other, the kept box growing to cover both.
+pub fn merge_tiles(mut boxes: Vec<(usize, BoundingBox)>, iou_threshold: f32) -> Vec<BoundingBox> {
+    boxes.sort_by(|a, b| b.1.score.partial_cmp(&a.1.score).unwrap_or(Ordering::Equal));
+    let mut kept: Vec<(usize, BoundingBox)> = Vec::new();
+    for (tile, candidate) in boxes {
+        let same = kept.iter_mut().find(|(kept_tile, k)| {
+            k.clazz == candidate.clazz
+                && (k.iou(&candidate) > iou_threshold
+                    || (*kept_tile != tile && inside_smaller(k, &candidate) > SEAM_OVERLAP))
+        });
+        match same {
+            Some((kept_tile, k)) if *kept_tile != tile => *k = union(k, &candidate),
+            Some(_) => {}
+            None => kept.push((tile, candidate)),
+        }
+    }
+    kept.into_iter().map(|(_, bbox)| bbox).collect()
+}
+
+/// Share of the smaller box covered by the larger
+fn inside_smaller(a: &BoundingBox, b: &BoundingBox) -> f32 {
+    let width = (a.x2.min(b.x2) - a.x1.max(b.x1)).max(0.0);
+    let height = (a.y2.min(b.y2) - a.y1.max(b.y1)).max(0.0);
+    let smaller = a.area().min(b.area());
+    if smaller <= 0.0 { 0.0 } else { width * height / smaller }
+}
+
+fn union(kept: &BoundingBox, other: &BoundingBox) -> BoundingBox {
+    BoundingBox {
+        x1: kept.x1.min(other.x1),
+        y1: kept.y1.min(other.y1),
+        x2: kept.x2.max(other.x2),
+        y2: kept.y2.max(other.y2),
+        ..*kept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bbox(x1: f32, y1: f32, x2: f32, y2: f32, score: f32, clazz: i32) -> BoundingBox {
+        BoundingBox { x1, y1, x2, y2, score, clazz }
+    }
+
+    /// Every pixel of the frame is in a tile and neighbours share at least
+    /// the overlap
+    fn assert_covers(layout: &TileLayout) {
+        let tiles = layout.tiles();
+        assert_eq!(tiles.len(), (layout.columns * layout.rows) as usize);
+        assert!(tiles.iter().enumerate().all(|(index, tile)| tile.index == index));
+
+        let across: Vec<_> = tiles.iter().map(|tile| (tile.x, tile.width)).collect();
+        let down: Vec<_> = tiles.iter().map(|tile| (tile.y, tile.height)).collect();
+        for (length, count, mut spans) in [
+            (layout.frame_width, layout.columns, across),
+            (layout.frame_height, layout.rows, down),
+        ] {
+            spans.sort();
+            spans.dedup();
+            assert_eq!(spans.len(), count as usize);
+            assert_eq!(spans[0].0, 0);
+            let (last, size) = spans[spans.len() - 1];
+            assert_eq!(last + size, length);
+            for pair in spans.windows(2) {
+                let ((a, size), (b, _)) = (pair[0], pair[1]);
+                assert!(a + size >= b + layout.overlap.min(size), "gap between tiles at {} and {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_cover_the_frame() {
+        for (width, height, tile_size, overlap) in [
+            (1920, 1080, 320, 0.2),
+            (4056, 3040, 640, 0.25),
+            (641, 640, 640, 0.1),
+            (1000, 330, 320, 0.0),
+            (3840, 2160, 320, 0.5),
+        ] {
+            let layout = TileLayout::new(width, height, tile_size, overlap);
+            assert_covers(&layout);
+        }
+    }
+
+    #[test]
+    fn tile_count() {
+        let layout = TileLayout::new(1920, 1080, 320, 0.2);
+        assert_eq!(layout.overlap, 64);
+        // strides of at most 256 pixels
+        assert_eq!((layout.columns, layout.rows), (8, 4));
+
+        // just too wide for one tile still takes two
+        let layout = TileLayout::new(641, 640, 640, 0.1);
+        assert_eq!((layout.columns, layout.rows), (2, 1));
+    }
+
+    #[test]
+    fn frame_smaller_than_a_tile_is_one_short_tile() {
+        let layout = TileLayout::new(200, 150, 320, 0.2);
+        assert_eq!((layout.columns, layout.rows), (1, 1));
+        let tiles = layout.tiles();
+        assert_eq!(tiles.len(), 1);
+        assert_eq!((tiles[0].x, tiles[0].y, tiles[0].width, tiles[0].height), (0, 0, 200, 150));
+        assert_covers(&layout);
+    }
+
+    #[test]
+    fn boxes_move_onto_the_frame() {
+        let tile = Tile { index: 3, x: 256, y: 128, width: 320, height: 320 };
+        let moved = tile.to_frame(&bbox(10.0, 20.0, 30.0, 40.0, 0.8, 2));
+        assert_eq!(moved, bbox(266.0, 148.0, 286.0, 168.0, 0.8, 2));
+    }
+
+    #[test]
+    fn insect_on_a_seam_is_merged() {
+        // cut short at the right edge of tile 0, whole in tile 1
+        let cut = bbox(300.0, 100.0, 320.0, 130.0, 0.5, 0);
+        let whole = bbox(290.0, 98.0, 340.0, 132.0, 0.9, 0);
+        let merged = merge_tiles(vec![(0, cut), (1, whole)], 0.45);
+        assert_eq!(merged, [bbox(290.0, 98.0, 340.0, 132.0, 0.9, 0)]);
+
+        // the kept box grows when the better one is the cut one
+        let cut = bbox(290.0, 100.0, 320.0, 130.0, 0.9, 0);
+        let rest = bbox(295.0, 101.0, 340.0, 129.0, 0.6, 0);
+        let merged = merge_tiles(vec![(0, cut), (1, rest)], 0.45);
+        assert_eq!(merged, [bbox(290.0, 100.0, 340.0, 130.0, 0.9, 0)]);
+    }
+
+    #[test]
+    fn separate_insects_are_kept() {
+        // a small insect on a larger one in the same tile is not a seam
+        let large = bbox(100.0, 100.0, 200.0, 200.0, 0.9, 0);
+        let small = bbox(120.0, 120.0, 140.0, 140.0, 0.7, 0);
+        assert_eq!(merge_tiles(vec![(0, large), (0, small)], 0.45).len(), 2);
+
+        // across a seam, but of different classes
+        let moth = bbox(300.0, 100.0, 320.0, 130.0, 0.5, 0);
+        let beetle = bbox(290.0, 98.0, 340.0, 132.0, 0.9, 1);
+        assert_eq!(merge_tiles(vec![(0, moth), (1, beetle)], 0.45).len(), 2);
+
+        // far apart in neighbouring tiles
+        let left = bbox(10.0, 10.0, 40.0, 40.0, 0.8, 0);
+        let right = bbox(500.0, 10.0, 530.0, 40.0, 0.8, 0);
+        assert_eq!(merge_tiles(vec![(0, left), (1, right)], 0.45).len(), 2);
+    }
+
+    #[test]
+    fn duplicates_from_the_whole_frame_are_dropped() {
+        let tiled = bbox(100.0, 100.0, 140.0, 140.0, 0.8, 0);
+        let whole = bbox(101.0, 99.0, 141.0, 139.0, 0.6, 0);
+        let merged = merge_tiles(vec![(WHOLE_FRAME, whole), (2, tiled)], 0.45);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].score, 0.8);
+    }
+}
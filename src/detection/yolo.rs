@@ -0,0 +1,202 @@
+use std::cmp::Ordering;
+
+/// A detected object, top-left and bottom-right corners in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+    pub score: f32,
+    pub clazz: i32,
+}
+
+impl BoundingBox {
+    pub fn width(&self) -> f32 {
+        (self.x2 - self.x1).max(0.0)
+    }
+
+    pub fn height(&self) -> f32 {
+        (self.y2 - self.y1).max(0.0)
+    }
+
+    pub fn area(&self) -> f32 {
+        self.width() * self.height()
+    }
+
+    pub fn iou(&self, other: &BoundingBox) -> f32 {
+        let x1 = self.x1.max(other.x1);
+        let y1 = self.y1.max(other.y1);
+        let x2 = self.x2.min(other.x2);
+        let y2 = self.y2.min(other.y2);
+        let intersection = (x2 - x1).max(0.0) * (y2 - y1).max(0.0);
+        let union = self.area() + other.area() - intersection;
+        if union <= 0.0 { 0.0 } else { intersection / union }
+    }
+}
+
+/// Decode a raw YOLO output tensor into candidate boxes above `confidence`.
+///
+/// Two layouts are understood:
+/// * `[1, 4 + classes, anchors]` - YOLOv8 and later, no objectness score
+/// * `[1, anchors, 5 + classes]` - YOLOv5, with an objectness score before the classes
+///
+/// Boxes are centre/size encoded in model input pixels.
+pub fn decode(shape: &[i64], data: &[f32], confidence: f32) -> Vec<BoundingBox> {
+    if shape.len() != 3 {
+        return Vec::new();
+    }
+    let (rows, cols) = (shape[1] as usize, shape[2] as usize);
+    let attributes_first = rows < cols;
+    let (anchors, attributes) = if attributes_first { (cols, rows) } else { (rows, cols) };
+    let has_objectness = !attributes_first;
+    let first_class = if has_objectness { 5 } else { 4 };
+    if attributes <= first_class {
+        return Vec::new();
+    }
+
+    let value = |anchor: usize, attribute: usize| -> f32 {
+        if attributes_first {
+            data[attribute * anchors + anchor]
+        } else {
+            data[anchor * attributes + attribute]
+        }
+    };
+
+    let mut boxes = Vec::new();
+    for anchor in 0..anchors {
+        let objectness = if has_objectness { value(anchor, 4) } else { 1.0 };
+        let (clazz, class_score) = (first_class..attributes)
+            .map(|attribute| (attribute - first_class, value(anchor, attribute)))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+            .unwrap_or((0, 0.0));
+        let score = objectness * class_score;
+        if score < confidence {
+            continue;
+        }
+
+        let (cx, cy) = (value(anchor, 0), value(anchor, 1));
+        let (w, h) = (value(anchor, 2), value(anchor, 3));
+        boxes.push(BoundingBox {
+            x1: cx - w / 2.0,
+            y1: cy - h / 2.0,
+            x2: cx + w / 2.0,
+            y2: cy + h / 2.0,
+            score,
+            clazz: clazz as i32,
+        });
+    }
+    boxes
+}
+
+/// Greedy per-class non-maximum suppression, highest scores first
+pub fn non_max_suppression(mut boxes: Vec<BoundingBox>, iou_threshold: f32) -> Vec<BoundingBox> {
+    boxes.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+    let mut kept: Vec<BoundingBox> = Vec::new();
+    for candidate in boxes {
+        let overlaps = kept
+            .iter()
+            .any(|k| k.clazz == candidate.clazz && k.iou(&candidate) > iou_threshold);
+        if !overlaps {
+            kept.push(candidate);
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANCHORS: usize = 8;
+
+    fn bbox(x1: f32, y1: f32, x2: f32, y2: f32, score: f32, clazz: i32) -> BoundingBox {
+        BoundingBox { x1, y1, x2, y2, score, clazz }
+    }
+
+    /// Attributes of each anchor: centre, size and the two class scores. Anchor 2
+    /// is a class 1 box, anchor 5 a weaker class 0 box and the rest score nothing.
+    fn anchors() -> Vec<Vec<f32>> {
+        let mut anchors = vec![vec![0.0; 6]; ANCHORS];
+        anchors[2] = vec![100.0, 50.0, 40.0, 20.0, 0.1, 0.9];
+        anchors[5] = vec![10.0, 10.0, 4.0, 6.0, 0.6, 0.2];
+        anchors
+    }
+
+    #[test]
+    fn decodes_attributes_first() {
+        // [1, 4 + classes, anchors], stored an attribute at a time
+        let anchors = anchors();
+        let data: Vec<f32> = (0..6).flat_map(|a| anchors.iter().map(move |anchor| anchor[a])).collect();
+        let boxes = decode(&[1, 6, ANCHORS as i64], &data, 0.5);
+        assert_eq!(
+            boxes,
+            [bbox(80.0, 40.0, 120.0, 60.0, 0.9, 1), bbox(8.0, 7.0, 12.0, 13.0, 0.6, 0)]
+        );
+
+        assert_eq!(decode(&[1, 6, ANCHORS as i64], &data, 0.7).len(), 1);
+    }
+
+    #[test]
+    fn decodes_anchors_first_with_objectness() {
+        // [1, anchors, 5 + classes], the objectness scales the class scores
+        let data: Vec<f32> = anchors()
+            .into_iter()
+            .enumerate()
+            .flat_map(|(index, mut anchor)| {
+                anchor.insert(4, if index == 5 { 0.5 } else { 1.0 });
+                anchor
+            })
+            .collect();
+        let boxes = decode(&[1, ANCHORS as i64, 7], &data, 0.25);
+        assert_eq!(
+            boxes,
+            [bbox(80.0, 40.0, 120.0, 60.0, 0.9, 1), bbox(8.0, 7.0, 12.0, 13.0, 0.3, 0)]
+        );
+
+        assert_eq!(decode(&[1, ANCHORS as i64, 7], &data, 0.5).len(), 1);
+    }
+
+    #[test]
+    fn rejects_unknown_shapes() {
+        assert!(decode(&[6, ANCHORS as i64], &[0.0; 6 * ANCHORS], 0.0).is_empty());
+        // no room for any class scores
+        assert!(decode(&[1, ANCHORS as i64, 5], &[1.0; 5 * ANCHORS], 0.0).is_empty());
+    }
+
+    #[test]
+    fn iou() {
+        let a = bbox(0.0, 0.0, 10.0, 10.0, 1.0, 0);
+        assert_eq!(a.iou(&a), 1.0);
+        assert_eq!(a.iou(&bbox(5.0, 0.0, 15.0, 10.0, 1.0, 0)), 50.0 / 150.0);
+        assert_eq!(a.iou(&bbox(10.0, 0.0, 20.0, 10.0, 1.0, 0)), 0.0);
+        assert_eq!(bbox(1.0, 1.0, 1.0, 1.0, 1.0, 0).iou(&bbox(1.0, 1.0, 1.0, 1.0, 1.0, 0)), 0.0);
+    }
+
+    #[test]
+    fn suppression_keeps_the_best_of_overlapping_boxes() {
+        let boxes = vec![
+            bbox(0.0, 0.0, 10.0, 10.0, 0.6, 0),
+            // IoU 0.82 with the first, suppressed by it
+            bbox(1.0, 0.0, 10.0, 10.0, 0.5, 0),
+            bbox(0.0, 0.0, 10.0, 10.0, 0.7, 0),
+            // IoU 1/3 with the others, under the threshold
+            bbox(5.0, 0.0, 15.0, 10.0, 0.4, 0),
+            // same place but another class
+            bbox(0.0, 0.0, 10.0, 10.0, 0.3, 1),
+        ];
+        let kept = non_max_suppression(boxes, 0.45);
+        assert_eq!(
+            kept,
+            [
+                bbox(0.0, 0.0, 10.0, 10.0, 0.7, 0),
+                bbox(5.0, 0.0, 15.0, 10.0, 0.4, 0),
+                bbox(0.0, 0.0, 10.0, 10.0, 0.3, 1),
+            ]
+        );
+
+        // a threshold below the overlap of the neighbour suppresses it too
+        let boxes = vec![bbox(0.0, 0.0, 10.0, 10.0, 0.7, 0), bbox(5.0, 0.0, 15.0, 10.0, 0.4, 0)];
+        assert_eq!(non_max_suppression(boxes, 0.3).len(), 1);
+    }
+}
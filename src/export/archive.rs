@@ -0,0 +1,187 @@
+use std::io::{Seek, Write};
+
+use anyhow::{Context as ErrContext, Result};
+use native_db::Database;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::database::models::DetectionModel;
+use crate::detection::labels::ModelLabels;
+use crate::export::coco::to_coco;
+use crate::export::session_export::SessionExport;
+use crate::export::table::{to_csv, to_json};
+
+pub fn archive_name(session: &str) -> String {
+    format!("session-{}.zip", session)
+}
+
+/// Write a session as a zip archive holding the detection table as CSV and JSON,
+/// a COCO annotation file and the crop images. The crops are read from the
+/// database one at a time as they are written.
+pub fn write_archive<W: Write + Seek>(
+    db: &Database,
+    export: &SessionExport,
+    labels: &mut ModelLabels,
+    writer: W,
+) -> Result<W> {
+    let mut zip = ZipWriter::new(writer);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    // the crops are JPEGs already
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    zip.start_file("detections.csv", deflated)?;
+    zip.write_all(&to_csv(export)?)?;
+    zip.start_file("detections.json", deflated)?;
+    zip.write_all(&to_json(export)?)?;
+    zip.start_file("annotations.coco.json", deflated)?;
+    zip.write_all(&to_coco(export, labels)?)?;
+
+    zip.add_directory("images/", stored)?;
+    let r = db.r_transaction()?;
+    for detection in &export.detections {
+        let crop: DetectionModel = r
+            .get()
+            .primary(detection.detection)?
+            .with_context(|| format!("Detection {} has gone", detection.detection))?;
+        zip.start_file(SessionExport::image_name(detection), stored)?;
+        zip.write_all(&crop.image)?;
+    }
+    Ok(zip.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Cursor, Read};
+
+    use native_db::Builder;
+    use serde_json::Value;
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::database::models::SessionModel;
+    use crate::database::MODELS;
+    use crate::detection::registry::ModelRegistry;
+    use crate::test_dir::TestDir;
+
+    const SESSION: &str = "20260101220000";
+
+    fn models_dir() -> TestDir {
+        let dir = TestDir::new("archive-models");
+        fs::write(dir.join("insects.labels"), "Noctuidae\nGeometridae\n").unwrap();
+        dir
+    }
+
+    fn detection(detection: i32, session: &str, clazz: i32, model: &str) -> DetectionModel {
+        DetectionModel {
+            detection,
+            session: session.to_string(),
+            created: 1_767_304_800_000 + detection as i64,
+            updated: 1_767_304_800_000 + detection as i64,
+            score: 0.5,
+            clazz,
+            width: 20 + detection,
+            height: 10,
+            image: vec![detection as u8; 4],
+            model: model.to_string(),
+            model_version: if model.is_empty() { String::new() } else { "1".to_string() },
+            species: Vec::new(),
+            classifier: String::new(),
+            classifier_version: String::new(),
+        }
+    }
+
+    /// Read every file of an archive, by name in the order they were written
+    fn unzip(archive: Vec<u8>) -> Vec<(String, Vec<u8>)> {
+        let mut zip = ZipArchive::new(Cursor::new(archive)).unwrap();
+        (0..zip.len())
+            .map(|index| {
+                let mut file = zip.by_index(index).unwrap();
+                let mut data = Vec::new();
+                file.read_to_end(&mut data).unwrap();
+                (file.name().to_string(), data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn archive_layout() {
+        let db = Builder::new().create_in_memory(&MODELS).unwrap();
+        let rw = db.rw_transaction().unwrap();
+        for session in [SESSION, "20260102220000"] {
+            rw.insert(SessionModel {
+                session: session.to_string(),
+                active: 0,
+                opened: 1_767_304_800_000,
+                closed: Some(1_767_333_600_000),
+                models: Vec::new(),
+            })
+            .unwrap();
+        }
+        rw.insert(detection(1, SESSION, 1, "insects")).unwrap();
+        // stored before models were recorded, named by the configured model
+        rw.insert(detection(2, SESSION, 0, "")).unwrap();
+        rw.insert(detection(3, SESSION, 7, "insects")).unwrap();
+        rw.insert(detection(4, "20260102220000", 0, "insects")).unwrap();
+        rw.commit().unwrap();
+
+        let export = SessionExport::load(&db, SESSION).unwrap();
+        let models = models_dir();
+        let mut labels = ModelLabels::new(ModelRegistry::new(&models), "insects");
+        let archive = write_archive(&db, &export, &mut labels, Cursor::new(Vec::new()))
+            .unwrap()
+            .into_inner();
+        let files = unzip(archive);
+
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "detections.csv",
+                "detections.json",
+                "annotations.coco.json",
+                "images/",
+                "images/000001.jpg",
+                "images/000002.jpg",
+                "images/000003.jpg",
+            ]
+        );
+        assert_eq!(files[5].1, vec![2u8; 4], "crop of detection 2");
+
+        let csv = String::from_utf8(files[0].1.clone()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "detection,session,created,updated,score,class,width,height,image,model,model_version,\
+             species,classifier,classifier_version"
+        );
+        assert_eq!(lines.len(), 4);
+        let start = format!("1,{},2026-01-01T22:00:00.001+00:00,", SESSION);
+        assert!(lines[1].starts_with(&start), "{}", lines[1]);
+        assert!(lines[1].ends_with(",0.5,1,21,10,images/000001.jpg,insects,1,,,"), "{}", lines[1]);
+        assert!(lines[2].ends_with(",0.5,0,22,10,images/000002.jpg,,,,,"), "{}", lines[2]);
+
+        let json: Value = serde_json::from_slice(&files[1].1).unwrap();
+        assert_eq!(json["session"], SESSION);
+        assert_eq!(json["detections"].as_array().unwrap().len(), 3);
+
+        let coco: Value = serde_json::from_slice(&files[2].1).unwrap();
+        let ids = |key: &str, field: &str| -> Vec<i64> {
+            coco[key].as_array().unwrap().iter().map(|item| item[field].as_i64().unwrap()).collect()
+        };
+        assert_eq!(ids("images", "id"), [1, 2, 3]);
+        assert_eq!(ids("annotations", "id"), [1, 2, 3]);
+        assert_eq!(ids("annotations", "image_id"), [1, 2, 3]);
+        assert_eq!(ids("annotations", "category_id"), [1, 0, 7]);
+        assert_eq!(ids("categories", "id"), [0, 1, 7]);
+        let names: Vec<&str> = coco["categories"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|category| category["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["Noctuidae", "Geometridae", "class 7"]);
+        assert_eq!(coco["images"][0]["file_name"], "images/000001.jpg");
+        assert_eq!(coco["annotations"][0]["bbox"], serde_json::json!([0, 0, 21, 10]));
+    }
+}
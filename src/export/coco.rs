@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::detection::labels::ModelLabels;
+use crate::export::session_export::{iso_time, SessionExport};
+
+// COCO object detection format, see https://cocodataset.org/#format-data.
+// Every crop is an image of its own with a single annotation covering it.
+// Categories are the detector's classes, named by the model that found them.
+
+#[derive(Serialize)]
+struct CocoInfo {
+    description: String,
+    date_created: String,
+}
+
+#[derive(Serialize)]
+struct CocoImage {
+    id: i32,
+    file_name: String,
+    width: i32,
+    height: i32,
+    date_captured: String,
+}
+
+#[derive(Serialize)]
+struct CocoAnnotation {
+    id: i32,
+    image_id: i32,
+    category_id: i32,
+    bbox: [i32; 4],
+    area: i32,
+    iscrowd: u8,
+    score: f32,
+}
+
+#[derive(Serialize)]
+struct CocoCategory {
+    id: i32,
+    name: String,
+    supercategory: String,
+}
+
+#[derive(Serialize)]
+struct CocoDataset {
+    info: CocoInfo,
+    images: Vec<CocoImage>,
+    annotations: Vec<CocoAnnotation>,
+    categories: Vec<CocoCategory>,
+}
+
+pub fn to_coco(export: &SessionExport, labels: &mut ModelLabels) -> Result<Vec<u8>> {
+    let images = export
+        .detections
+        .iter()
+        .map(|d| CocoImage {
+            id: d.detection,
+            file_name: SessionExport::image_name(d),
+            width: d.width,
+            height: d.height,
+            date_captured: iso_time(d.created),
+        })
+        .collect();
+
+    let annotations = export
+        .detections
+        .iter()
+        .map(|d| CocoAnnotation {
+            id: d.detection,
+            image_id: d.detection,
+            category_id: d.clazz,
+            bbox: [0, 0, d.width, d.height],
+            area: d.width * d.height,
+            iscrowd: 0,
+            score: d.score,
+        })
+        .collect();
+
+    // each class is named after the first detection of it
+    let mut classes = BTreeMap::new();
+    for detection in &export.detections {
+        classes.entry(detection.clazz).or_insert(detection.model.as_str());
+    }
+    let categories = classes
+        .into_iter()
+        .map(|(clazz, model)| CocoCategory {
+            id: clazz,
+            name: labels.labels(model).name(clazz),
+            supercategory: "insect".to_string(),
+        })
+        .collect();
+
+    let dataset = CocoDataset {
+        info: CocoInfo {
+            description: format!("Session {}", export.session.session),
+            date_created: iso_time(export.session.opened),
+        },
+        images,
+        annotations,
+        categories,
+    };
+    Ok(serde_json::to_vec_pretty(&dataset)?)
+}
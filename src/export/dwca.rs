@@ -0,0 +1,423 @@
+use std::io::{Seek, Write};
+
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use native_db::Database;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::database::models::{DetectionModel, SessionModel};
+use crate::detection::labels::ModelLabels;
+use crate::export::session_export::{iso_time, SessionExport};
+use crate::export::table::species_list;
+
+// Darwin Core Archive with an Event core (one event per session) and an
+// Occurrence extension (one occurrence per detection), see
+// https://dwc.tdwg.org/text/
+
+const DWC: &str = "http://rs.tdwg.org/dwc/terms/";
+
+const EVENT_TERMS: [&str; 7] = [
+    "eventID",
+    "eventDate",
+    "samplingProtocol",
+    "locality",
+    "decimalLatitude",
+    "decimalLongitude",
+    "geodeticDatum",
+];
+
+const OCCURRENCE_TERMS: [&str; 8] = [
+    "eventID",
+    "occurrenceID",
+    "basisOfRecord",
+    "eventDate",
+    "scientificName",
+    "individualCount",
+    "occurrenceStatus",
+    "identificationRemarks",
+];
+
+const SAMPLING_PROTOCOL: &str = "automated light trap with camera and object detection";
+
+/// Describes the trap and the dataset in the archive
+#[derive(Debug, Clone)]
+pub struct DwcMetadata {
+    pub trap_id: String,
+    pub locality: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub dataset_title: String,
+    pub publisher: String,
+}
+
+/// Load all sessions opened between `from` and `to`, both days included
+pub fn load_sessions(
+    db: &Database,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<Vec<SessionExport>> {
+    let start = from.map(day_start).unwrap_or(i64::MIN);
+    let end = to
+        .and_then(|day| day.succ_opt())
+        .map(day_start)
+        .unwrap_or(i64::MAX);
+
+    let r = db.r_transaction()?;
+    let sessions: Vec<SessionModel> = r
+        .scan()
+        .primary::<SessionModel>()?
+        .all()?
+        .collect::<Result<_, _>>()?;
+    sessions
+        .iter()
+        .filter(|session| session.opened >= start && session.opened < end)
+        .map(|session| SessionExport::load(db, &session.session))
+        .collect()
+}
+
+fn day_start(day: NaiveDate) -> i64 {
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|time| time.timestamp_millis())
+        .unwrap_or_else(|| midnight.and_utc().timestamp_millis())
+}
+
+fn event_id(metadata: &DwcMetadata, session: &SessionModel) -> String {
+    format!("{}:{}", metadata.trap_id, session.session)
+}
+
+fn event_date(session: &SessionModel) -> String {
+    match session.closed {
+        Some(closed) => format!("{}/{}", iso_time(session.opened), iso_time(closed)),
+        None => iso_time(session.opened),
+    }
+}
+
+/// A tab separated writer matching meta.xml, which declares no quoting
+fn tsv_writer() -> csv::Writer<Vec<u8>> {
+    csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .quote_style(csv::QuoteStyle::Never)
+        .from_writer(Vec::new())
+}
+
+/// Write a row with any tabs or line breaks in the fields turned into
+/// spaces, as without quoting they would split the field or the record
+fn write_row<const N: usize>(writer: &mut csv::Writer<Vec<u8>>, fields: [String; N]) -> Result<()> {
+    let fields = fields.map(|field| field.replace(['\t', '\r', '\n'], " "));
+    Ok(writer.write_record(fields)?)
+}
+
+fn events(exports: &[SessionExport], metadata: &DwcMetadata) -> Result<Vec<u8>> {
+    let mut writer = tsv_writer();
+    writer.write_record(EVENT_TERMS)?;
+    for export in exports {
+        write_row(&mut writer, [
+            event_id(metadata, &export.session),
+            event_date(&export.session),
+            SAMPLING_PROTOCOL.to_string(),
+            metadata.locality.clone(),
+            metadata.latitude.to_string(),
+            metadata.longitude.to_string(),
+            "WGS84".to_string(),
+        ])?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+fn occurrences(
+    exports: &[SessionExport],
+    metadata: &DwcMetadata,
+    labels: &mut ModelLabels,
+) -> Result<Vec<u8>> {
+    let mut writer = tsv_writer();
+    writer.write_record(OCCURRENCE_TERMS)?;
+    for export in exports {
+        let event = event_id(metadata, &export.session);
+        for detection in &export.detections {
+            write_row(&mut writer, [
+                event.clone(),
+                format!("{}:{}", event, detection.detection),
+                "MachineObservation".to_string(),
+                iso_time(detection.created),
+                labels.name(detection),
+                "1".to_string(),
+                "present".to_string(),
+                identification_remarks(detection),
+            ])?;
+        }
+    }
+    Ok(writer.into_inner()?)
+}
+
+fn identification_remarks(detection: &DetectionModel) -> String {
+    // the class number is kept here for detections scientificName cannot name
+    let mut remarks = match detection.model.as_str() {
+        "" => format!("detector class {} confidence {:.3}", detection.clazz, detection.score),
+        model => format!(
+            "detector class {} confidence {:.3}, model {} {}",
+            detection.clazz, detection.score, model, detection.model_version
+        ),
+    };
+    if !detection.species.is_empty() {
+        remarks += &format!(
+            ", classifier {} {}: {}",
+            detection.classifier,
+            detection.classifier_version,
+            species_list(detection)
+        );
+    }
+    remarks
+}
+
+fn meta_fields(terms: &[&str]) -> String {
+    terms
+        .iter()
+        .enumerate()
+        .map(|(index, term)| format!("    <field index=\"{}\" term=\"{}{}\"/>\n", index, DWC, term))
+        .collect()
+}
+
+fn meta_xml() -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<archive xmlns="http://rs.tdwg.org/dwc/text/" metadata="eml.xml">
+  <core encoding="UTF-8" fieldsTerminatedBy="\t" linesTerminatedBy="\n" fieldsEnclosedBy="" ignoreHeaderLines="1" rowType="{dwc}Event">
+    <files><location>event.txt</location></files>
+    <id index="0"/>
+{event_fields}  </core>
+  <extension encoding="UTF-8" fieldsTerminatedBy="\t" linesTerminatedBy="\n" fieldsEnclosedBy="" ignoreHeaderLines="1" rowType="{dwc}Occurrence">
+    <files><location>occurrence.txt</location></files>
+    <coreid index="0"/>
+{occurrence_fields}  </extension>
+</archive>
+"#,
+        dwc = DWC,
+        event_fields = meta_fields(&EVENT_TERMS),
+        occurrence_fields = meta_fields(&OCCURRENCE_TERMS),
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn eml_xml(exports: &[SessionExport], metadata: &DwcMetadata) -> String {
+    let date = |millis: i64| {
+        DateTime::from_timestamp_millis(millis)
+            .map(|time| time.format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    };
+    let begin = exports.iter().map(|e| e.session.opened).min().unwrap_or_default();
+    let end = exports
+        .iter()
+        .map(|e| e.session.closed.unwrap_or(e.session.opened))
+        .max()
+        .unwrap_or_default();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<eml:eml xmlns:eml="eml://ecoinformatics.org/eml-2.1.1" packageId="{trap}" system="ai-trap-rs" scope="system" xml:lang="en">
+  <dataset>
+    <title>{title}</title>
+    <creator><organizationName>{publisher}</organizationName></creator>
+    <pubDate>{published}</pubDate>
+    <abstract><para>Insects recorded by automated light trap {trap}, {sessions} sampling events.</para></abstract>
+    <coverage>
+      <geographicCoverage>
+        <geographicDescription>{locality}</geographicDescription>
+        <boundingCoordinates>
+          <westBoundingCoordinate>{longitude}</westBoundingCoordinate>
+          <eastBoundingCoordinate>{longitude}</eastBoundingCoordinate>
+          <northBoundingCoordinate>{latitude}</northBoundingCoordinate>
+          <southBoundingCoordinate>{latitude}</southBoundingCoordinate>
+        </boundingCoordinates>
+      </geographicCoverage>
+      <temporalCoverage>
+        <rangeOfDates>
+          <beginDate><calendarDate>{begin}</calendarDate></beginDate>
+          <endDate><calendarDate>{end}</calendarDate></endDate>
+        </rangeOfDates>
+      </temporalCoverage>
+    </coverage>
+    <contact><organizationName>{publisher}</organizationName></contact>
+  </dataset>
+</eml:eml>
+"#,
+        trap = xml_escape(&metadata.trap_id),
+        title = xml_escape(&metadata.dataset_title),
+        publisher = xml_escape(&metadata.publisher),
+        published = Local::now().format("%Y-%m-%d"),
+        sessions = exports.len(),
+        locality = xml_escape(&metadata.locality),
+        latitude = metadata.latitude,
+        longitude = metadata.longitude,
+        begin = date(begin),
+        end = date(end),
+    )
+}
+
+/// Write the sessions as a Darwin Core Archive
+pub fn write_dwca<W: Write + Seek>(
+    exports: &[SessionExport],
+    metadata: &DwcMetadata,
+    labels: &mut ModelLabels,
+    writer: W,
+) -> Result<W> {
+    let mut zip = ZipWriter::new(writer);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("event.txt", options)?;
+    zip.write_all(&events(exports, metadata)?)?;
+    zip.start_file("occurrence.txt", options)?;
+    zip.write_all(&occurrences(exports, metadata, labels)?)?;
+    zip.start_file("meta.xml", options)?;
+    zip.write_all(meta_xml().as_bytes())?;
+    zip.start_file("eml.xml", options)?;
+    zip.write_all(eml_xml(exports, metadata).as_bytes())?;
+    Ok(zip.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::{Cursor, Read};
+
+    use native_db::Builder;
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::database::MODELS;
+    use crate::detection::registry::ModelRegistry;
+    use crate::test_dir::TestDir;
+
+    const SESSION: &str = "20260101220000";
+
+    fn metadata() -> DwcMetadata {
+        DwcMetadata {
+            trap_id: "trap-1".to_string(),
+            locality: "Old \"Mill\"\tmeadow\r\nnorth side".to_string(),
+            latitude: 52.5,
+            longitude: -1.25,
+            dataset_title: "Moths & more".to_string(),
+            publisher: "Field station".to_string(),
+        }
+    }
+
+    fn models_dir() -> TestDir {
+        let dir = TestDir::new("dwca-models");
+        fs::write(dir.join("insects.labels"), "Noctuidae\n\nGeometridae\n").unwrap();
+        dir
+    }
+
+    fn detection(detection: i32, clazz: i32) -> DetectionModel {
+        DetectionModel {
+            detection,
+            session: SESSION.to_string(),
+            created: 1_767_304_800_000 + detection as i64,
+            updated: 1_767_304_800_000 + detection as i64,
+            score: 0.5,
+            clazz,
+            width: 20,
+            height: 10,
+            image: Vec::new(),
+            model: "insects".to_string(),
+            model_version: "1".to_string(),
+            species: Vec::new(),
+            classifier: String::new(),
+            classifier_version: String::new(),
+        }
+    }
+
+    fn exports() -> Vec<SessionExport> {
+        let db = Builder::new().create_in_memory(&MODELS).unwrap();
+        let rw = db.rw_transaction().unwrap();
+        rw.insert(SessionModel {
+            session: SESSION.to_string(),
+            active: 0,
+            opened: 1_767_304_800_000,
+            closed: Some(1_767_333_600_000),
+            models: Vec::new(),
+        })
+        .unwrap();
+        for (id, clazz) in [(1, 0), (2, 1), (3, 2)] {
+            rw.insert(detection(id, clazz)).unwrap();
+        }
+        rw.commit().unwrap();
+        load_sessions(&db, None, None).unwrap()
+    }
+
+    fn unzip(archive: Vec<u8>) -> HashMap<String, String> {
+        let mut zip = ZipArchive::new(Cursor::new(archive)).unwrap();
+        (0..zip.len())
+            .map(|index| {
+                let mut file = zip.by_index(index).unwrap();
+                let mut text = String::new();
+                file.read_to_string(&mut text).unwrap();
+                (file.name().to_string(), text)
+            })
+            .collect()
+    }
+
+    /// The terms meta.xml declares for a section, in column order
+    fn declared_terms(section: &str) -> Vec<String> {
+        let mut terms = Vec::new();
+        for (index, line) in section.lines().filter(|line| line.contains("<field ")).enumerate() {
+            let field = format!("<field index=\"{}\" term=\"{}", index, DWC);
+            let term = line.trim().strip_prefix(&field).expect(line).strip_suffix("\"/>").unwrap();
+            terms.push(term.to_string());
+        }
+        terms
+    }
+
+    fn rows(text: &str) -> Vec<Vec<&str>> {
+        text.lines().map(|line| line.split('\t').collect()).collect()
+    }
+
+    #[test]
+    fn meta_xml_describes_the_columns_written() {
+        let models = models_dir();
+        let mut labels = ModelLabels::new(ModelRegistry::new(&models), "insects");
+        let archive = write_dwca(&exports(), &metadata(), &mut labels, Cursor::new(Vec::new()))
+            .unwrap()
+            .into_inner();
+        let files = unzip(archive);
+        let mut names: Vec<&str> = files.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["eml.xml", "event.txt", "meta.xml", "occurrence.txt"]);
+
+        let meta = &files["meta.xml"];
+        let (core, extension) = meta.split_once("<extension").unwrap();
+        assert!(core.contains("<location>event.txt</location>"));
+        assert!(extension.contains("<location>occurrence.txt</location>"));
+        for (section, file) in [(core, "event.txt"), (extension, "occurrence.txt")] {
+            let rows = rows(&files[file]);
+            let header: Vec<String> = rows[0].iter().map(|term| term.to_string()).collect();
+            assert_eq!(declared_terms(section), header, "{}", file);
+            assert!(rows.iter().all(|row| row.len() == header.len()), "{}", file);
+        }
+
+        let events = rows(&files["event.txt"]);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1][0], format!("trap-1:{}", SESSION));
+        // no quoting, tabs and line breaks become spaces
+        assert_eq!(events[1][3], "Old \"Mill\" meadow  north side");
+        assert_eq!(&events[1][4..], ["52.5", "-1.25", "WGS84"]);
+
+        let occurrences = rows(&files["occurrence.txt"]);
+        assert_eq!(occurrences.len(), 4);
+        let names: Vec<&str> = occurrences[1..].iter().map(|row| row[4]).collect();
+        assert_eq!(names, ["Noctuidae", "", "Geometridae"]);
+        assert_eq!(occurrences[2][1], format!("trap-1:{}:2", SESSION));
+        assert_eq!(occurrences[2][7], "detector class 1 confidence 0.500, model insects 1");
+
+        assert!(files["eml.xml"].contains("<title>Moths &amp; more</title>"));
+    }
+}
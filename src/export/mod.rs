@@ -0,0 +1,5 @@
+pub mod session_export;
+pub mod table;
+pub mod coco;
+pub mod archive;
+pub mod dwca;
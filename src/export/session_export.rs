@@ -0,0 +1,47 @@
+use anyhow::{Context as ErrContext, Result};
+use chrono::{DateTime, Utc};
+use native_db::Database;
+
+use crate::database::models::{DetectionModel, DetectionModelKey, SessionModel};
+
+/// A session and all of its detections, read in a single transaction. The
+/// crops are left out so a long session fits in memory, `write_archive`
+/// reads them one at a time.
+pub struct SessionExport {
+    pub session: SessionModel,
+    pub detections: Vec<DetectionModel>,
+}
+
+impl SessionExport {
+    pub fn load(db: &Database, session: &str) -> Result<Self> {
+        let r = db.r_transaction()?;
+        let session: SessionModel = r
+            .get()
+            .primary(session.to_string())?
+            .with_context(|| format!("Unknown session '{}'", session))?;
+        let mut detections = Vec::new();
+        for res in r
+            .scan()
+            .secondary::<DetectionModel>(DetectionModelKey::session)?
+            .start_with(session.session.clone())? {
+            let mut detection: DetectionModel = res?;
+            if detection.session == session.session {
+                detection.image = Vec::new();
+                detections.push(detection);
+            }
+        }
+        Ok(Self { session, detections })
+    }
+
+    /// Path of a detection's crop inside the archive
+    pub fn image_name(detection: &DetectionModel) -> String {
+        format!("images/{:06}.jpg", detection.detection)
+    }
+}
+
+/// Millisecond timestamp as an ISO 8601 string
+pub fn iso_time(millis: i64) -> String {
+    DateTime::<Utc>::from_timestamp_millis(millis)
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default()
+}
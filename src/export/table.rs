@@ -0,0 +1,84 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::database::models::{DetectionModel, SessionDetector};
+use crate::export::session_export::{iso_time, SessionExport};
+
+/// One row of the detection table, shared by the CSV and JSON exports
+#[derive(Serialize)]
+struct DetectionRecord {
+    detection: i32,
+    session: String,
+    created: String,
+    updated: String,
+    score: f32,
+    class: i32,
+    width: i32,
+    height: i32,
+    image: String,
+    model: String,
+    model_version: String,
+    /// Species from the classifier with their scores, most likely first
+    species: String,
+    classifier: String,
+    classifier_version: String,
+}
+
+impl DetectionRecord {
+    fn new(detection: &DetectionModel) -> Self {
+        Self {
+            detection: detection.detection,
+            session: detection.session.clone(),
+            created: iso_time(detection.created),
+            updated: iso_time(detection.updated),
+            score: detection.score,
+            class: detection.clazz,
+            width: detection.width,
+            height: detection.height,
+            image: SessionExport::image_name(detection),
+            model: detection.model.clone(),
+            model_version: detection.model_version.clone(),
+            species: species_list(detection),
+            classifier: detection.classifier.clone(),
+            classifier_version: detection.classifier_version.clone(),
+        }
+    }
+}
+
+/// `label score` pairs joined with `; `, which keeps the CSV to one column
+pub fn species_list(detection: &DetectionModel) -> String {
+    detection
+        .species
+        .iter()
+        .map(|species| format!("{} {:.3}", species.label, species.score))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[derive(Serialize)]
+struct SessionRecord {
+    session: String,
+    opened: String,
+    closed: Option<String>,
+    models: Vec<SessionDetector>,
+    detections: Vec<DetectionRecord>,
+}
+
+pub fn to_csv(export: &SessionExport) -> Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for detection in &export.detections {
+        writer.serialize(DetectionRecord::new(detection))?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+pub fn to_json(export: &SessionExport) -> Result<Vec<u8>> {
+    let record = SessionRecord {
+        session: export.session.session.clone(),
+        opened: iso_time(export.session.opened),
+        closed: export.session.closed.map(iso_time),
+        models: export.session.models.clone(),
+        detections: export.detections.iter().map(DetectionRecord::new).collect(),
+    };
+    Ok(serde_json::to_vec_pretty(&record)?)
+}
@@ -1,23 +1,24 @@
+use std::io;
 use std::thread;
 use std::thread::JoinHandle;
 use tokio::runtime::Runtime;
 
 pub trait Actor where Self: 'static {
-    async fn start(self) -> JoinHandle<()> where Self: Sized, Self: Send {
-        let handle = thread::spawn(move || {
-            let rt = Runtime::new().unwrap();
-            rt.block_on(async {
-                self.on_started().await;
-            });
-        });
-        handle
+    /// Run the actor on its own thread with its own runtime. The thread
+    /// finishes when `on_started` returns, or with an error if it panics.
+    /// Actors return once they have seen `app.shutdown` and finished their work.
+    fn spawn(self, name: &str) -> io::Result<JoinHandle<()>> where Self: Sized, Self: Send {
+        thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let rt = Runtime::new().unwrap();
+                rt.block_on(async {
+                    self.on_started().await;
+                });
+            })
     }
-    
-    
-    fn _stop() {
 
-    }
-    
+
     //async fn on_started(state: Arc<Mutex<Self>>) where Self: Sized;
     async fn on_started(self);
 
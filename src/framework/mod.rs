@@ -1,2 +1,4 @@
 pub mod actor;
-pub mod streams;
\ No newline at end of file
+pub mod streams;
+pub mod supervisor;
+pub mod shutdown;
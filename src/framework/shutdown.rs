@@ -0,0 +1,70 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+
+use crate::framework::streams::BroadcastStream;
+use crate::messages::identifiers::APP_SHUTDOWN;
+use crate::messages::protobuf_msg::ProtobufMsg;
+
+struct Signals {
+    interrupt: Signal,
+    terminate: Signal,
+}
+
+impl Signals {
+    fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+
+    /// Wait for SIGINT or SIGTERM
+    async fn recv(&mut self) {
+        tokio::select! {
+            _ = self.interrupt.recv() => info!("Interrupted"),
+            _ = self.terminate.recv() => info!("Terminated"),
+        }
+    }
+}
+
+/// On the first signal ask every actor to stop. Actors that are still running
+/// when the deadline passes, or when a second signal arrives, are abandoned.
+pub(crate) fn handle_signals(
+    protobuf_subs: BroadcastStream<ProtobufMsg>,
+    stopping: Arc<AtomicBool>,
+    deadline: Duration,
+) {
+    let protobuf_subs_tx = protobuf_subs.broadcast_sender();
+    let mut signals = match Signals::new() {
+        Ok(signals) => signals,
+        Err(e) => {
+            warn!("Failed to install signal handlers {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        signals.recv().await;
+        info!("Shutting down");
+        stopping.store(true, Ordering::Relaxed);
+
+        // the deadline runs from the signal, a full message stream must not hold it back
+        let deadline_passed = tokio::time::sleep(deadline);
+        let shutdown = async {
+            let _ = protobuf_subs_tx.broadcast(ProtobufMsg::new(APP_SHUTDOWN, Vec::new())).await;
+            deadline_passed.await;
+        };
+        tokio::select! {
+            _ = shutdown => {
+                error!("Actors still running after {:?}, exiting", deadline);
+            }
+            _ = signals.recv() => {
+                error!("Second signal received, exiting");
+            }
+        }
+        std::process::exit(1);
+    });
+}
@@ -1,17 +1,18 @@
-use async_broadcast::{ Receiver as BroadcastReceiver, Sender as BroadcastSender };
+use async_broadcast::{ InactiveReceiver, Receiver as BroadcastReceiver, Sender as BroadcastSender };
 use async_channel::{Receiver as ChannelReceiver, Sender as ChannelSender};
 
 #[derive(Debug, Clone)]
 pub struct BroadcastStream<T> {
     size : usize,
     stream_sender : BroadcastSender<T>,
-    stream_receiver : BroadcastReceiver<T>
+    // Kept inactive so that holding the stream does not hold up senders
+    stream_receiver : InactiveReceiver<T>
 }
 
 impl<T> BroadcastStream<T> {
     pub(crate) fn new(size : usize) -> Self {
         let (stream_sender, stream_receiver) = async_broadcast::broadcast::<T>(size);
-        Self {size, stream_sender, stream_receiver }
+        Self {size, stream_sender, stream_receiver: stream_receiver.deactivate() }
     }
     
     pub fn broadcast_sender(&self) -> BroadcastSender<T> {
@@ -19,7 +20,7 @@ impl<T> BroadcastStream<T> {
     }
 
     pub fn broadcast_receiver(&self) -> BroadcastReceiver<T> {
-        self.stream_receiver.clone()
+        self.stream_receiver.activate_cloned()
     }
 }
 
@@ -0,0 +1,381 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context as ErrContext, Result};
+use async_broadcast::Sender as BroadcastSender;
+use log::{error, info, warn};
+use prost::Message as PbMessage;
+use serde::{Deserialize, Serialize};
+
+use crate::framework::actor::Actor;
+use crate::framework::streams::BroadcastStream;
+use crate::messages::actor_events::ActorEvent;
+use crate::messages::identifiers::{ACTOR_FAILED, ACTOR_RESTARTED};
+use crate::messages::protobuf_msg::ProtobufMsg;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Restart only the actor that failed, the others keep running
+    OneForOne,
+    /// Leave a failed actor stopped
+    Never,
+}
+
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    pub strategy: Strategy,
+    /// Failures tolerated within `window` before the supervisor gives up
+    pub max_restarts: usize,
+    pub window: Duration,
+    /// Delay before the first restart, doubled for each further failure in the window
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            strategy: Strategy::OneForOne,
+            max_restarts: 5,
+            window: Duration::from_secs(60),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RestartPolicy {
+    fn backoff(&self, failures: usize) -> Duration {
+        let doublings = failures.saturating_sub(1).min(16) as u32;
+        self.initial_backoff
+            .saturating_mul(1 << doublings)
+            .min(self.max_backoff)
+    }
+}
+
+type Factory = Box<dyn Fn(&str) -> Result<JoinHandle<()>>>;
+
+struct Child {
+    name: String,
+    factory: Factory,
+    handle: Option<JoinHandle<()>>,
+    failures: VecDeque<Instant>,
+    restarts: u32,
+    restart_at: Option<Instant>,
+}
+
+/// Watches the actor threads and restarts the ones that panic. Actors are
+/// built from a factory so a restarted actor starts from a fresh state on the
+/// same streams. A factory that fails counts as a failure of its actor.
+/// Failures and restarts are published for the clients.
+pub struct Supervisor {
+    policy: RestartPolicy,
+    stopping: Arc<AtomicBool>,
+    protobuf_pub_tx: BroadcastSender<ProtobufMsg>,
+    children: Vec<Child>,
+}
+
+impl Supervisor {
+    pub(crate) fn new(policy: RestartPolicy, protobuf_pub: BroadcastStream<ProtobufMsg>) -> Self {
+        Self {
+            policy,
+            stopping: Arc::new(AtomicBool::new(false)),
+            protobuf_pub_tx: protobuf_pub.broadcast_sender(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Set when the trap is shutting down, actors that fail from then on stay down
+    pub(crate) fn stopping(&self) -> Arc<AtomicBool> {
+        self.stopping.clone()
+    }
+
+    pub(crate) fn supervise<A, F>(&mut self, name: &str, factory: F)
+    where
+        A: Actor + Send,
+        F: Fn() -> Result<A> + 'static,
+    {
+        self.children.push(Child {
+            name: name.to_string(),
+            factory: Box::new(move |name| {
+                let actor = factory()?;
+                actor.spawn(name).context("Failed to start thread")
+            }),
+            handle: None,
+            failures: VecDeque::new(),
+            restarts: 0,
+            restart_at: None,
+        });
+    }
+
+    /// Start every actor and supervise them until they have all stopped. Returns an
+    /// error when an actor fails more often than the policy allows, leaving it to
+    /// whatever started the trap to restart the whole process.
+    pub(crate) fn run(mut self) -> Result<()> {
+        let now = Instant::now();
+        for index in 0..self.children.len() {
+            let child = &mut self.children[index];
+            match (child.factory)(&child.name) {
+                Ok(handle) => child.handle = Some(handle),
+                Err(e) => self.failed(index, now, format!("Failed to start: {:#}", e))?,
+            }
+        }
+
+        loop {
+            let now = Instant::now();
+            for index in 0..self.children.len() {
+                self.check_child(index, now)?;
+            }
+            if self.children.iter().all(|child| child.handle.is_none() && child.restart_at.is_none()) {
+                info!("All actors stopped");
+                return Ok(());
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn check_child(&mut self, index: usize, now: Instant) -> Result<()> {
+        let stopping = self.stopping.load(Ordering::Relaxed);
+        let child = &mut self.children[index];
+
+        if stopping && child.restart_at.take().is_some() {
+            info!("{} not restarted, shutting down", child.name);
+        }
+        if let Some(restart_at) = child.restart_at {
+            if now >= restart_at {
+                child.restart_at = None;
+                // a factory that fails is another failure, not a restart
+                match (child.factory)(&child.name) {
+                    Ok(handle) => child.handle = Some(handle),
+                    Err(e) => return self.failed(index, now, format!("Failed to restart: {:#}", e)),
+                }
+                child.restarts += 1;
+                info!("Restarted {} ({} restarts)", child.name, child.restarts);
+                let event = ActorEvent {
+                    actor: child.name.clone(),
+                    error: String::new(),
+                    restarts: child.restarts,
+                    backoff_ms: 0,
+                };
+                self.publish(ACTOR_RESTARTED, event);
+            }
+            return Ok(());
+        }
+
+        let finished = matches!(&child.handle, Some(handle) if handle.is_finished());
+        if !finished {
+            return Ok(());
+        }
+        let handle = child.handle.take().unwrap();
+        match handle.join() {
+            Ok(()) => {
+                info!("{} stopped", child.name);
+                Ok(())
+            }
+            Err(panic) => self.failed(index, now, panic_message(panic)),
+        }
+    }
+
+    /// Count a failure of the actor, then schedule its restart or give up on it
+    fn failed(&mut self, index: usize, now: Instant, error: String) -> Result<()> {
+        let stopping = self.stopping.load(Ordering::Relaxed);
+        let child = &mut self.children[index];
+        error!("{} failed: {}", child.name, error);
+
+        while child.failures.front().is_some_and(|at| now.duration_since(*at) > self.policy.window) {
+            child.failures.pop_front();
+        }
+        child.failures.push_back(now);
+
+        let restart = self.policy.strategy == Strategy::OneForOne
+            && child.failures.len() <= self.policy.max_restarts
+            && !stopping;
+        let backoff = if restart {
+            self.policy.backoff(child.failures.len())
+        } else {
+            Duration::ZERO
+        };
+        if restart {
+            child.restart_at = Some(now + backoff);
+        }
+
+        let name = child.name.clone();
+        let event = ActorEvent {
+            actor: name.clone(),
+            error,
+            restarts: child.restarts,
+            backoff_ms: backoff.as_millis() as u64,
+        };
+        let failures = child.failures.len();
+        self.publish(ACTOR_FAILED, event);
+
+        match self.policy.strategy {
+            _ if stopping => Ok(()),
+            Strategy::Never => Ok(()),
+            Strategy::OneForOne if restart => {
+                warn!("Restarting {} in {:?}", name, backoff);
+                Ok(())
+            }
+            Strategy::OneForOne => bail!(
+                "{} failed {} times within {:?}, giving up",
+                name, failures, self.policy.window
+            ),
+        }
+    }
+
+    fn publish(&self, identifier: &str, event: ActorEvent) {
+        let msg = ProtobufMsg::new(identifier, event.encode_to_vec());
+        if let Err(e) = self.protobuf_pub_tx.try_broadcast(msg) {
+            warn!("Failed to publish {}: {}", identifier, e);
+        }
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use anyhow::anyhow;
+    use async_broadcast::Receiver as BroadcastReceiver;
+
+    use super::*;
+
+    /// Stops at once, panicking if told to
+    struct TestActor {
+        panics: bool,
+    }
+
+    impl Actor for TestActor {
+        async fn on_started(self) {
+            if self.panics {
+                panic!("test actor failed");
+            }
+        }
+    }
+
+    fn policy(strategy: Strategy, max_restarts: usize) -> RestartPolicy {
+        RestartPolicy {
+            strategy,
+            max_restarts,
+            window: Duration::from_secs(60),
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+        }
+    }
+
+    /// A supervisor of one actor whose first `failures` starts fail, by
+    /// panicking or by the factory itself failing, with a count of the starts
+    fn supervised(
+        policy: RestartPolicy,
+        failures: usize,
+        factory_fails: bool,
+    ) -> (Supervisor, Arc<AtomicUsize>, BroadcastReceiver<ProtobufMsg>) {
+        let protobuf_pub = BroadcastStream::new(32);
+        let events = protobuf_pub.broadcast_receiver();
+        let mut supervisor = Supervisor::new(policy, protobuf_pub);
+        let starts = Arc::new(AtomicUsize::new(0));
+        let counted = starts.clone();
+        supervisor.supervise("test", move || {
+            let failing = counted.fetch_add(1, Ordering::Relaxed) < failures;
+            if failing && factory_fails {
+                return Err(anyhow!("no device"));
+            }
+            Ok(TestActor { panics: failing })
+        });
+        (supervisor, starts, events)
+    }
+
+    fn identifiers(events: &mut BroadcastReceiver<ProtobufMsg>) -> Vec<String> {
+        std::iter::from_fn(|| events.try_recv().ok()).map(|msg| msg.identifier).collect()
+    }
+
+    #[test]
+    fn one_for_one_restarts_a_failed_actor() {
+        let (supervisor, starts, mut events) = supervised(policy(Strategy::OneForOne, 5), 2, false);
+        supervisor.run().unwrap();
+        assert_eq!(starts.load(Ordering::Relaxed), 3);
+        assert_eq!(
+            identifiers(&mut events),
+            [ACTOR_FAILED, ACTOR_RESTARTED, ACTOR_FAILED, ACTOR_RESTARTED]
+        );
+    }
+
+    #[test]
+    fn gives_up_after_max_restarts_within_the_window() {
+        let (supervisor, starts, mut events) = supervised(policy(Strategy::OneForOne, 2), usize::MAX, false);
+        let error = supervisor.run().unwrap_err();
+        assert!(error.to_string().contains("failed 3 times"), "{:#}", error);
+        assert_eq!(starts.load(Ordering::Relaxed), 3);
+        assert_eq!(identifiers(&mut events).iter().filter(|id| *id == ACTOR_FAILED).count(), 3);
+    }
+
+    #[test]
+    fn failures_outside_the_window_are_forgotten() {
+        let mut policy = policy(Strategy::OneForOne, 1);
+        policy.window = Duration::ZERO;
+        let (supervisor, starts, _events) = supervised(policy, 3, false);
+        supervisor.run().unwrap();
+        assert_eq!(starts.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn never_leaves_a_failed_actor_stopped() {
+        let (supervisor, starts, mut events) = supervised(policy(Strategy::Never, 5), 1, false);
+        supervisor.run().unwrap();
+        assert_eq!(starts.load(Ordering::Relaxed), 1);
+        assert_eq!(identifiers(&mut events), [ACTOR_FAILED]);
+    }
+
+    #[test]
+    fn a_failing_factory_counts_as_a_failure() {
+        let (supervisor, starts, mut events) = supervised(policy(Strategy::OneForOne, 5), 2, true);
+        supervisor.run().unwrap();
+        assert_eq!(starts.load(Ordering::Relaxed), 3);
+        let events: Vec<(String, ActorEvent)> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|msg| (msg.identifier, ActorEvent::decode(msg.payload.as_slice()).unwrap()))
+            .collect();
+        let summary: Vec<(&str, &str, u32)> = events
+            .iter()
+            .map(|(identifier, event)| (identifier.as_str(), event.error.as_str(), event.restarts))
+            .collect();
+        // only the start that worked counts as a restart
+        assert_eq!(
+            summary,
+            [
+                (ACTOR_FAILED, "Failed to start: no device", 0),
+                (ACTOR_FAILED, "Failed to restart: no device", 0),
+                (ACTOR_RESTARTED, "", 1),
+            ]
+        );
+
+        let (supervisor, starts, _events) = supervised(policy(Strategy::OneForOne, 1), usize::MAX, true);
+        assert!(supervisor.run().is_err());
+        assert_eq!(starts.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn no_restarts_while_stopping() {
+        let (supervisor, starts, _events) = supervised(policy(Strategy::OneForOne, 5), usize::MAX, false);
+        supervisor.stopping().store(true, Ordering::Relaxed);
+        supervisor.run().unwrap();
+        assert_eq!(starts.load(Ordering::Relaxed), 1);
+    }
+}
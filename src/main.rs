@@ -2,67 +2,304 @@ mod actors;
 mod generated;
 mod messages;
 mod framework;
+mod sources;
+mod detection;
+mod tracking;
+mod database;
+mod export;
+mod cli;
+mod config;
+mod state;
+mod schedule;
+mod preview;
+#[cfg(test)]
+mod test_dir;
 
-use std::thread::JoinHandle;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use anyhow::{Context as ErrContext, Result};
+use clap::Parser;
+use chrono::NaiveDate;
+use log::{error, info};
 use simplelog::*;
+use tokio::runtime::Runtime;
 
 use crate::actors::camera_actor::CameraActor;
 use crate::actors::detection_actor::DetectionActor;
 use crate::actors::sessions_actor::SessionsActor;
 use crate::actors::state_actor::StateActor;
+use crate::actors::tracking_actor::TrackingActor;
 use crate::actors::websocket_actor::WebsocketActor;
-use crate::framework::actor::Actor;
+use crate::actors::config_actor::ConfigActor;
+use crate::actors::scheduler_actor::SchedulerActor;
+use crate::actors::preview_actor::PreviewActor;
+use crate::actors::model_upload_actor::ModelUploadActor;
+use crate::cli::{Cli, Command, RunArgs};
+use crate::config::{Config as TrapConfig, LiveConfig};
+use crate::database::open_database;
+use crate::detection::labels::ModelLabels;
+use crate::detection::registry::ModelRegistry;
+use crate::export::archive::{archive_name, write_archive};
+use crate::export::dwca::{load_sessions, write_dwca, DwcMetadata};
+use crate::export::session_export::SessionExport;
 use crate::framework::streams::{BroadcastStream, ChannelStream};
+use crate::framework::shutdown::handle_signals;
+use crate::framework::supervisor::Supervisor;
 use crate::messages::camera_frame::CameraFrame;
+use crate::messages::detection_result::DetectionResult;
+use crate::messages::still_capture::StillCapture;
+use crate::messages::config::ConfigValues;
+use crate::messages::model_install::ModelInstall;
+use crate::messages::track_update::TrackUpdate;
 use crate::messages::protobuf_msg::ProtobufMsg;
+use crate::sources::frame_source::create_frame_source;
+use crate::state::machine::LiveState;
 
-#[tokio::main]
-async fn main() {
+fn main() {
     CombinedLogger::init(
         vec![
             TermLogger::new(LevelFilter::Debug, Config::default(), TerminalMode::Mixed, ColorChoice::Auto),
         ]
     ).unwrap();
 
-    let protobuf_pub: BroadcastStream<ProtobufMsg> = BroadcastStream::new(10);
-    let protobuf_subs: BroadcastStream<ProtobufMsg> = BroadcastStream::new(10);
-    let camera_frame: ChannelStream<CameraFrame> = ChannelStream::new(10);
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Run(cli.run)) {
+        Command::Run(args) => {
+            if let Err(e) = run(args) {
+                error!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Export { session, output, config, database } => {
+            let result = export_config(&config, database)
+                .and_then(|config| export(&session, output, &config));
+            if let Err(e) = result {
+                error!("Export failed: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Dwca {
+            session, from, to, output, config, database,
+            trap_id, locality, latitude, longitude, title, publisher,
+        } => {
+            let result = export_config(&config, database).and_then(|config| {
+                let site = &config.trap;
+                let metadata = DwcMetadata {
+                    trap_id: trap_id.unwrap_or_else(|| site.id.clone()),
+                    locality: locality.unwrap_or_else(|| site.locality.clone()),
+                    latitude: latitude
+                        .or(site.latitude)
+                        .context("No trap latitude, set trap.latitude or give --latitude")?,
+                    longitude: longitude
+                        .or(site.longitude)
+                        .context("No trap longitude, set trap.longitude or give --longitude")?,
+                    dataset_title: title,
+                    publisher,
+                };
+                export_dwca(session, from, to, &output, &config, &metadata)
+            });
+            if let Err(e) = result {
+                error!("Export failed: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
 
-    let session_actor = SessionsActor::new(
-        protobuf_pub.clone(),
-        protobuf_subs.clone(),
-        "location".to_string()
+/// Configuration for the export commands, with the command line overrides
+fn export_config(path: &Path, database: Option<String>) -> Result<TrapConfig> {
+    let mut config = TrapConfig::load(path)?;
+    if let Some(database) = database {
+        config.database.path = database;
+    }
+    Ok(config)
+}
+
+fn export_dwca(
+    session: Option<String>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    output: &Path,
+    config: &TrapConfig,
+    metadata: &DwcMetadata,
+) -> Result<()> {
+    let database = &config.database.path;
+    let db = open_database(database)
+        .with_context(|| format!("Failed to open database '{}'", database))?;
+    let exports = match session {
+        Some(session) => vec![SessionExport::load(&db, &session)?],
+        None => load_sessions(&db, from, to)?,
+    };
+    let mut labels = ModelLabels::new(
+        ModelRegistry::new(&config.detection.models),
+        &config.detection.model,
     );
-    let state_actor = StateActor::new(
-        protobuf_pub.clone(),
-        protobuf_subs.clone()
+    let file = File::create(output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    write_dwca(&exports, metadata, &mut labels, file)?;
+    info!("Exported {} sessions to {}", exports.len(), output.display());
+    Ok(())
+}
+
+fn export(session: &str, output: Option<PathBuf>, config: &TrapConfig) -> Result<()> {
+    let database = &config.database.path;
+    let db = open_database(database)
+        .with_context(|| format!("Failed to open database '{}'", database))?;
+    let export = SessionExport::load(&db, session)?;
+    let mut labels = ModelLabels::new(
+        ModelRegistry::new(&config.detection.models),
+        &config.detection.model,
     );
-    let camera_actor = CameraActor::new(
-        camera_frame.clone(),
+    let output = output.unwrap_or_else(|| PathBuf::from(archive_name(session)));
+    let file = File::create(&output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    write_archive(&db, &export, &mut labels, file)?;
+    info!("Exported {} detections to {}", export.detections.len(), output.display());
+    Ok(())
+}
+
+fn run(args: RunArgs) -> Result<()> {
+    let mut config = TrapConfig::load(&args.config)?;
+    args.apply(&mut config);
+    config.validate()?;
+
+    let streams = &config.streams;
+    let protobuf_pub: BroadcastStream<ProtobufMsg> = BroadcastStream::new(streams.messages);
+    let protobuf_subs: BroadcastStream<ProtobufMsg> = BroadcastStream::new(streams.messages);
+    let camera_frame: ChannelStream<CameraFrame> = ChannelStream::new(streams.frames);
+    let detections: ChannelStream<DetectionResult> = ChannelStream::new(streams.detections);
+    let tracks: ChannelStream<TrackUpdate> = ChannelStream::new(streams.tracks);
+    let preview: ChannelStream<DetectionResult> = ChannelStream::new(streams.preview);
+    // stills are only taken on request, one at a time
+    let stills: ChannelStream<StillCapture> = ChannelStream::new(1);
+    // uploaded models waiting to be tried, one at a time
+    let installs: ChannelStream<ModelInstall> = ChannelStream::new(1);
+    // settings changed by the actors, for ConfigActor to save
+    let config_saves: ChannelStream<ConfigValues> = ChannelStream::new(streams.messages);
+
+    let mut supervisor = Supervisor::new(config.supervisor.restart_policy(), protobuf_pub.clone());
+    // actors read their section when they are (re)started, so that a restart
+    // keeps the changes made while the trap was running
+    let live = LiveConfig::new(config.clone());
+    let trap_state = LiveState::default();
+
+    let (l, c, p, s) = (live.clone(), config_saves.clone(), protobuf_pub.clone(), protobuf_subs.clone());
+    let path = args.config.clone();
+    supervisor.supervise("config", move || Ok(ConfigActor::new(
+        l.clone(),
+        path.clone(),
+        c.clone(),
+        p.clone(),
+        s.clone()
+    )));
+    let (l, t, v, p, s) = (
+        live.clone(),
+        tracks.clone(),
+        stills.clone(),
         protobuf_pub.clone(),
-        protobuf_subs.clone()
+        protobuf_subs.clone(),
     );
-    let detection_actor = DetectionActor::new(
+    supervisor.supervise("sessions", move || SessionsActor::new(
+        t.clone(),
+        v.clone(),
+        p.clone(),
+        s.clone(),
+        &l.get().database,
+        &l.get().detection
+    ));
+    let (l, f, v, c, p, s) = (
+        live.clone(),
         camera_frame.clone(),
+        stills.clone(),
+        config_saves.clone(),
         protobuf_pub.clone(),
-        protobuf_subs.clone()
+        protobuf_subs.clone(),
     );
-
-    let websocket_actor = WebsocketActor::new(
+    supervisor.supervise("camera", move || {
+        let camera = l.get().camera;
+        let source = create_frame_source(&camera.source)?;
+        Ok(CameraActor::new(
+            camera,
+            source,
+            f.clone(),
+            v.clone(),
+            c.clone(),
+            p.clone(),
+            s.clone()
+        ))
+    });
+    let (l, f, d, v, i, c, p, s) = (
+        live.clone(),
+        camera_frame.clone(),
+        detections.clone(),
+        preview.clone(),
+        installs.clone(),
+        config_saves.clone(),
         protobuf_pub.clone(),
         protobuf_subs.clone(),
     );
+    supervisor.supervise("detection", move || Ok(DetectionActor::new(
+        l.get().detection,
+        f.clone(),
+        d.clone(),
+        v.clone(),
+        i.clone(),
+        c.clone(),
+        p.clone(),
+        s.clone()
+    )));
+    let (l, i, p, s) = (live.clone(), installs.clone(), protobuf_pub.clone(), protobuf_subs.clone());
+    supervisor.supervise("model-upload", move || Ok(ModelUploadActor::new(
+        &l.get().detection,
+        i.clone(),
+        p.clone(),
+        s.clone()
+    )));
+    let (d, t) = (detections.clone(), tracks.clone());
+    supervisor.supervise("tracking", move || Ok(TrackingActor::new(
+        d.clone(),
+        t.clone(),
+    )));
+    let (l, v, p, s) = (live.clone(), preview.clone(), protobuf_pub.clone(), protobuf_subs.clone());
+    supervisor.supervise("preview", move || {
+        let c = l.get();
+        Ok(PreviewActor::new(
+            c.preview,
+            c.detection.regions,
+            v.clone(),
+            p.clone(),
+            s.clone()
+        ))
+    });
+    let (l, p, s) = (live.clone(), protobuf_pub.clone(), protobuf_subs.clone());
+    supervisor.supervise("websocket", move || WebsocketActor::new(
+        &l.get().server,
+        p.clone(),
+        s.clone(),
+    ));
 
-    let threads : Vec<JoinHandle<()>> = vec![
-        session_actor.start().await,
-        state_actor.start().await,
-        camera_actor.start().await,
-        detection_actor.start().await,
-        websocket_actor.start().await
-    ];
+    // StateActor resumes capture as it starts, so it comes after the actors it commands
+    let (l, t, p, s) = (live.clone(), trap_state.clone(), protobuf_pub.clone(), protobuf_subs.clone());
+    supervisor.supervise("state", move || Ok(StateActor::new(
+        &l.get().database,
+        t.clone(),
+        p.clone(),
+        s.clone()
+    )));
 
-    for thread in threads {
-        thread.join().unwrap();
-    }
-}
+    // after StateActor, which resumes first and may then be told otherwise
+    let (l, t, p, s) = (live.clone(), trap_state.clone(), protobuf_pub.clone(), protobuf_subs.clone());
+    supervisor.supervise("scheduler", move || Ok(SchedulerActor::new(
+        &l.get(),
+        t.clone(),
+        p.clone(),
+        s.clone()
+    )));
 
+    // the actors bring their own runtimes, this one only waits for signals
+    // while the supervisor blocks this thread
+    let signals = Runtime::new().context("Failed to start the signal handler")?;
+    let _entered = signals.enter();
+    handle_signals(protobuf_subs.clone(), supervisor.stopping(), config.supervisor.shutdown_deadline());
+    supervisor.run()
+}
@@ -0,0 +1,16 @@
+// Supervisor events (hand written prost messages)
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ActorEvent {
+    #[prost(string, tag = "1")]
+    pub actor: String,
+    /// Why the actor failed, empty on restart
+    #[prost(string, tag = "2")]
+    pub error: String,
+    /// Times the actor has been restarted so far
+    #[prost(uint32, tag = "3")]
+    pub restarts: u32,
+    /// Delay before the next restart, 0 when the actor will stay down
+    #[prost(uint64, tag = "4")]
+    pub backoff_ms: u64,
+}
@@ -0,0 +1,74 @@
+// Camera controls (hand written prost messages)
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CameraControlValue {
+    /// exposure, gain, white_balance or focus
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(int64, tag = "2")]
+    pub value: i64,
+}
+
+/// A control the camera offers, `min` and `max` are 0 when it gives no range
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CameraControlInfo {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(int64, tag = "2")]
+    pub min: i64,
+    #[prost(int64, tag = "3")]
+    pub max: i64,
+    #[prost(int64, tag = "4")]
+    pub step: i64,
+    #[prost(int64, tag = "5")]
+    pub default: i64,
+    #[prost(int64, tag = "6")]
+    pub value: i64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CameraFormatInfo {
+    #[prost(uint32, tag = "1")]
+    pub width: u32,
+    #[prost(uint32, tag = "2")]
+    pub height: u32,
+    /// 0 when the source does not say
+    #[prost(uint32, tag = "3")]
+    pub fps: u32,
+}
+
+/// `camera`, the reply to `camera.get`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CameraInfo {
+    #[prost(string, tag = "1")]
+    pub source: String,
+    #[prost(bool, tag = "2")]
+    pub capturing: bool,
+    /// Current frame size, 0 when the source does not say
+    #[prost(uint32, tag = "3")]
+    pub width: u32,
+    #[prost(uint32, tag = "4")]
+    pub height: u32,
+    #[prost(message, repeated, tag = "5")]
+    pub controls: Vec<CameraControlInfo>,
+    #[prost(message, repeated, tag = "6")]
+    pub formats: Vec<CameraFormatInfo>,
+    /// Frames read since the trap started, and those dropped because
+    /// detection fell behind
+    #[prost(uint64, tag = "7")]
+    pub frames_captured: u64,
+    #[prost(uint64, tag = "8")]
+    pub frames_dropped: u64,
+}
+
+/// `camera.set`, the controls to change and the frame size, width and height
+/// left at 0 keep the current size
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CameraSettings {
+    #[prost(message, repeated, tag = "1")]
+    pub controls: Vec<CameraControlValue>,
+    #[prost(uint32, tag = "2")]
+    pub width: u32,
+    #[prost(uint32, tag = "3")]
+    pub height: u32,
+}
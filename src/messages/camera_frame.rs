@@ -15,4 +15,12 @@ impl CameraFrame {
             buffer: Arc::new(buffer)
         }
     }
-}
\ No newline at end of file
+
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+}
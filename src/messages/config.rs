@@ -0,0 +1,24 @@
+// Configuration requests and replies (hand written prost messages)
+
+/// `config.get`, an empty prefix asks for every value
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ConfigQuery {
+    #[prost(string, tag = "1")]
+    pub prefix: String,
+}
+
+/// A dotted key such as `detection.confidence` and its value as a TOML literal
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ConfigValue {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+/// Reply to `config.get`, payload of `config.set` and of `config.changed`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ConfigValues {
+    #[prost(message, repeated, tag = "1")]
+    pub values: Vec<ConfigValue>,
+}
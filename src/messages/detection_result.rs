@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use crate::detection::registry::ModelId;
+use crate::detection::yolo::BoundingBox;
+use crate::messages::camera_frame::CameraFrame;
+
+/// The boxes found in a frame, passed on with the frame itself so that
+/// downstream actors can crop the detected insects out of it
+#[derive(Clone)]
+pub struct DetectionResult {
+    frame : CameraFrame,
+    boxes : Vec<BoundingBox>,
+    model : Arc<ModelId>
+}
+
+impl DetectionResult {
+
+    pub(crate) fn new(frame : CameraFrame, boxes : Vec<BoundingBox>, model : Arc<ModelId>) -> Self {
+        Self { frame, boxes, model }
+    }
+
+    pub fn frame(&self) -> &CameraFrame {
+        &self.frame
+    }
+
+    pub fn boxes(&self) -> &[BoundingBox] {
+        &self.boxes
+    }
+
+    /// The model that found the boxes
+    pub fn model(&self) -> &Arc<ModelId> {
+        &self.model
+    }
+}
@@ -0,0 +1,60 @@
+// Detection results published by DetectionActor (hand written prost messages)
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DetectionBox {
+    #[prost(float, tag = "1")]
+    pub x1: f32,
+    #[prost(float, tag = "2")]
+    pub y1: f32,
+    #[prost(float, tag = "3")]
+    pub x2: f32,
+    #[prost(float, tag = "4")]
+    pub y2: f32,
+    #[prost(float, tag = "5")]
+    pub score: f32,
+    #[prost(int32, tag = "6")]
+    pub clazz: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FrameDetections {
+    #[prost(int64, tag = "1")]
+    pub timestamp: i64,
+    #[prost(int32, tag = "2")]
+    pub width: i32,
+    #[prost(int32, tag = "3")]
+    pub height: i32,
+    #[prost(message, repeated, tag = "4")]
+    pub boxes: Vec<DetectionBox>,
+    /// Name and version of the model that found the boxes
+    #[prost(string, tag = "5")]
+    pub model: String,
+    #[prost(string, tag = "6")]
+    pub model_version: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SpeciesScore {
+    #[prost(string, tag = "1")]
+    pub label: String,
+    #[prost(float, tag = "2")]
+    pub score: f32,
+}
+
+/// Fields of a stored `Detection` that sessions.proto does not have yet.
+/// They are encoded after the `Detection` in the same payload, with tags
+/// clear of its own, so clients built before them still decode the event.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DetectionExtras {
+    /// Species named by the classifier, most likely first
+    #[prost(message, repeated, tag = "10")]
+    pub species: Vec<SpeciesScore>,
+    #[prost(string, tag = "11")]
+    pub model: String,
+    #[prost(string, tag = "12")]
+    pub model_version: String,
+    #[prost(string, tag = "13")]
+    pub classifier: String,
+    #[prost(string, tag = "14")]
+    pub classifier_version: String,
+}
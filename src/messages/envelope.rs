@@ -0,0 +1,29 @@
+// Wire format of every message exchanged with the clients (hand written prost messages).
+// Fields 1 and 2 are those of `protocol.ProtobufMessage`, so clients that only know
+// the original message still read identifier and payload.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum EnvelopeStatus {
+    /// A request, an event or one reply in a stream of replies
+    Message = 0,
+    /// No more replies will follow for the correlation id
+    End = 1,
+    /// The request failed, `error` says why. No more replies will follow.
+    Error = 2,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Envelope {
+    #[prost(string, tag = "1")]
+    pub identifier: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub protobuf: Vec<u8>,
+    /// Chosen by the client on a request and echoed on every reply to it
+    #[prost(uint64, optional, tag = "3")]
+    pub correlation: Option<u64>,
+    #[prost(enumeration = "EnvelopeStatus", tag = "4")]
+    pub status: i32,
+    #[prost(string, tag = "5")]
+    pub error: String,
+}
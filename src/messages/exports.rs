@@ -0,0 +1,31 @@
+// Session export requests and replies (hand written prost messages)
+
+/// Reply to `session.export` and to each `session.export.read`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SessionArchive {
+    #[prost(string, tag = "1")]
+    pub session: String,
+    #[prost(string, tag = "2")]
+    pub filename: String,
+    /// The piece of the archive asked for with `session.export.read`, empty
+    /// in the reply to `session.export`
+    #[prost(bytes = "vec", tag = "3")]
+    pub data: Vec<u8>,
+    /// Where `data` starts in the archive
+    #[prost(uint64, tag = "4")]
+    pub offset: u64,
+    /// Size of the whole archive
+    #[prost(uint64, tag = "5")]
+    pub size: u64,
+}
+
+/// Ask for the piece of a built archive starting at `offset`. Clients read
+/// the pieces one after the other, asking for the next once the last has
+/// arrived, and the archive is removed when its final piece has been read.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SessionArchiveRead {
+    #[prost(string, tag = "1")]
+    pub session: String,
+    #[prost(uint64, tag = "2")]
+    pub offset: u64,
+}
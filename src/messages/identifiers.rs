@@ -0,0 +1,133 @@
+// Identifiers of the messages carried on the protobuf streams. Use these
+// rather than string literals so a typo fails to compile instead of being
+// silently ignored by every actor.
+
+// Sent on the subscription stream when the trap is shutting down, every
+// actor finishes its in-flight work and returns
+pub const APP_SHUTDOWN: &str = "app.shutdown";
+
+// Requests handled by StateActor
+pub const STATE_GET: &str = "state.get";
+pub const STATE_PAUSE_SET: &str = "state.pause.set";
+pub const STATE_CAPTURE_GET: &str = "state.capture.get";
+pub const STATE_CAPTURE_SET: &str = "state.capture.set";
+pub const STATE_STREAMING_GET: &str = "state.streaming.get";
+pub const STATE_STREAMING_SET: &str = "state.streaming.set";
+
+// Events published by StateActor
+pub const STATE: &str = "state";
+pub const STATE_CHANGED: &str = "state.changed";
+pub const STATE_CAPTURE: &str = "state.capture";
+pub const STATE_STREAMING: &str = "state.streaming";
+
+// Requests handled by CameraActor
+pub const CAMERA_GET: &str = "camera.get";
+pub const CAMERA_SET: &str = "camera.set";
+pub const CAMERA_STILL: &str = "camera.still";
+
+// Sent by StateActor to CameraActor as the trap changes state, not taken
+// from clients so the camera cannot get out of step with the state machine
+pub const CAMERA_STATE_SET: &str = "camera.state.set";
+
+// Published by CameraActor
+pub const CAMERA: &str = "camera";
+
+// Sent by CameraActor to StateActor
+pub const CAMERA_OPENED: &str = "camera.opened";
+pub const CAMERA_FAILED: &str = "camera.failed";
+
+// Sent by StateActor to DetectionActor, `detection.frame` is only published while set
+pub const DETECTION_STREAM_SET: &str = "detection.stream.set";
+
+// Handled by SessionsActor but not taken from clients, sessions open and
+// close as StateActor moves through the capture states
+pub const SESSION_OPEN: &str = "session.open";
+pub const SESSION_CLOSE: &str = "session.close";
+pub const SESSION_STATE_SET: &str = "session.state.set";
+pub const SESSION_RESUME: &str = "session.resume";
+
+// Requests handled by SessionsActor
+pub const SESSION_ALL: &str = "session.all";
+pub const SESSION_EXPORT: &str = "session.export";
+pub const SESSION_EXPORT_READ: &str = "session.export.read";
+pub const SESSION_DETECTIONS: &str = "session.detections";
+pub const SESSION_STILLS: &str = "session.stills";
+
+// Events published by SessionsActor
+pub const SESSION_OPENED: &str = "session.opened";
+pub const SESSION_CLOSED: &str = "session.closed";
+pub const SESSION_DETAILS: &str = "session.details";
+// Reply to `session.export` and `session.export.read`
+pub const SESSION_EXPORTED: &str = "session.exported";
+pub const DETECTION: &str = "detection";
+// Reply to `camera.still` and `session.stills`
+pub const STILL: &str = "still";
+
+// Requests handled by DetectionActor
+pub const MODEL_LIST: &str = "model.list";
+pub const MODEL_SELECT: &str = "model.select";
+pub const REGIONS_GET: &str = "regions.get";
+pub const REGIONS_SET: &str = "regions.set";
+
+// Requests handled by ModelUploadActor, the commit is answered by DetectionActor
+pub const MODEL_UPLOAD_BEGIN: &str = "model.upload.begin";
+pub const MODEL_UPLOAD_CHUNK: &str = "model.upload.chunk";
+pub const MODEL_UPLOAD_COMMIT: &str = "model.upload.commit";
+
+// Events published by DetectionActor
+pub const DETECTION_FRAME: &str = "detection.frame";
+pub const MODELS: &str = "models";
+pub const MODEL_CHANGED: &str = "model.changed";
+// Reply to `model.upload.commit`
+pub const MODEL_INSTALLED: &str = "model.installed";
+// Reply to `regions.get`
+pub const REGIONS: &str = "regions";
+
+// Published by PreviewActor while streaming
+pub const STREAM_FRAME: &str = "stream.frame";
+
+// Requests handled by ConfigActor
+pub const CONFIG_GET: &str = "config.get";
+pub const CONFIG_SET: &str = "config.set";
+
+// Published by ConfigActor, `config.changed` also goes to the actors
+pub const CONFIG: &str = "config";
+pub const CONFIG_CHANGED: &str = "config.changed";
+
+// Requests handled by SchedulerActor
+pub const SCHEDULE_GET: &str = "schedule.get";
+
+// Published by SchedulerActor
+pub const SCHEDULE: &str = "schedule";
+
+// Events published by the supervisor
+pub const ACTOR_FAILED: &str = "actor.failed";
+pub const ACTOR_RESTARTED: &str = "actor.restarted";
+
+/// Every request a client may send
+pub const REQUESTS: &[&str] = &[
+    STATE_GET,
+    STATE_PAUSE_SET,
+    STATE_CAPTURE_GET,
+    STATE_CAPTURE_SET,
+    STATE_STREAMING_GET,
+    STATE_STREAMING_SET,
+    CAMERA_GET,
+    CAMERA_SET,
+    CAMERA_STILL,
+    SESSION_ALL,
+    SESSION_EXPORT,
+    SESSION_EXPORT_READ,
+    SESSION_DETECTIONS,
+    SESSION_STILLS,
+    MODEL_LIST,
+    MODEL_SELECT,
+    REGIONS_GET,
+    REGIONS_SET,
+    MODEL_UPLOAD_BEGIN,
+    MODEL_UPLOAD_CHUNK,
+    MODEL_UPLOAD_COMMIT,
+    CONFIG_GET,
+    CONFIG_SET,
+    SCHEDULE_GET,
+];
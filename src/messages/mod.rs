@@ -1,3 +1,21 @@
 pub mod protobuf_msg;
+pub mod envelope;
 pub mod raw_message;
 pub mod camera_frame;
+pub mod detections;
+pub mod detection_result;
+pub mod track_update;
+pub mod exports;
+pub mod identifiers;
+pub mod registry;
+pub mod actor_events;
+pub mod config;
+pub mod state;
+pub mod schedule;
+pub mod preview;
+pub mod still_capture;
+pub mod stills;
+pub mod camera;
+pub mod models;
+pub mod model_install;
+pub mod regions;
@@ -0,0 +1,32 @@
+use crate::detection::registry::StagedModel;
+use crate::messages::protobuf_msg::ProtobufMsg;
+
+/// An uploaded model on its way from ModelUploadActor to DetectionActor to
+/// be tried before it is installed, with the commit request so the reply
+/// goes back to whoever sent the model
+#[derive(Clone)]
+pub struct ModelInstall {
+    request : ProtobufMsg,
+    staged : StagedModel,
+    select : bool
+}
+
+impl ModelInstall {
+
+    pub(crate) fn new(request : ProtobufMsg, staged : StagedModel, select : bool) -> Self {
+        Self { request, staged, select }
+    }
+
+    pub fn request(&self) -> &ProtobufMsg {
+        &self.request
+    }
+
+    pub fn staged(&self) -> &StagedModel {
+        &self.staged
+    }
+
+    /// Detect with the model once it is installed
+    pub fn select(&self) -> bool {
+        self.select
+    }
+}
@@ -0,0 +1,89 @@
+// Detection models (hand written prost messages)
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModelInfo {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub version: String,
+    /// Size of the model file in bytes
+    #[prost(uint64, tag = "3")]
+    pub size: u64,
+    /// Number of class names, 0 when the model has no labels
+    #[prost(uint32, tag = "4")]
+    pub labels: u32,
+    /// The model DetectionActor is running
+    #[prost(bool, tag = "5")]
+    pub active: bool,
+    /// Width and height of the model input, only known for the active model
+    #[prost(uint32, tag = "6")]
+    pub input_size: u32,
+    /// How frames are cut into tiles, when the active model is tiling
+    #[prost(message, optional, tag = "7")]
+    pub tiles: Option<TileLayoutInfo>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TileLayoutInfo {
+    #[prost(uint32, tag = "1")]
+    pub frame_width: u32,
+    #[prost(uint32, tag = "2")]
+    pub frame_height: u32,
+    #[prost(uint32, tag = "3")]
+    pub tile_size: u32,
+    #[prost(uint32, tag = "4")]
+    pub columns: u32,
+    #[prost(uint32, tag = "5")]
+    pub rows: u32,
+    /// Least overlap between neighbouring tiles in pixels
+    #[prost(uint32, tag = "6")]
+    pub overlap: u32,
+}
+
+/// `models`, the reply to `model.list`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModelList {
+    #[prost(message, repeated, tag = "1")]
+    pub models: Vec<ModelInfo>,
+}
+
+/// `model.select`, switch detection to an installed model
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModelSelect {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+/// `model.upload.begin`, start sending a model to install in the registry.
+/// The model follows in `model.upload.chunk` messages and is installed by
+/// `model.upload.commit` once it has loaded successfully.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModelUploadBegin {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    /// Size of the model file in bytes
+    #[prost(uint64, tag = "2")]
+    pub size: u64,
+    /// SHA-256 of the model file in hex
+    #[prost(string, tag = "3")]
+    pub sha256: String,
+    /// Version for the manifest, when empty the model is known by its SHA-256
+    #[prost(string, tag = "4")]
+    pub version: String,
+    /// Class names in class order
+    #[prost(string, repeated, tag = "5")]
+    pub labels: Vec<String>,
+    /// Detect with the model once it is installed
+    #[prost(bool, tag = "6")]
+    pub select: bool,
+}
+
+/// `model.upload.chunk`, the next part of the model file
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModelUploadChunk {
+    /// Position of `data` in the file, chunks are sent in order
+    #[prost(uint64, tag = "1")]
+    pub offset: u64,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data: Vec<u8>,
+}
@@ -0,0 +1,15 @@
+// Live preview frames (hand written prost messages)
+
+/// `stream.frame`, a downscaled camera frame published while streaming
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PreviewFrame {
+    /// Capture time in milliseconds since the epoch
+    #[prost(int64, tag = "1")]
+    pub timestamp: i64,
+    #[prost(uint32, tag = "2")]
+    pub width: u32,
+    #[prost(uint32, tag = "3")]
+    pub height: u32,
+    #[prost(bytes = "vec", tag = "4")]
+    pub jpeg: Vec<u8>,
+}
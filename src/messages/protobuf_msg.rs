@@ -2,45 +2,92 @@ use prost::Message as PbMessage;
 use anyhow::{Context as ErrContext, Result};
 use tungstenite::Bytes;
 
-use crate::generated::protocol::ProtobufMessage;
+use crate::messages::envelope::{Envelope, EnvelopeStatus};
 use crate::messages::raw_message::RawMessage;
 
 #[derive(Clone, Hash, Debug, Eq, PartialEq)]
 pub struct ProtobufMsg {
     pub(crate) identifier: String,
     pub(crate) payload: Vec<u8>,
+    /// Set by clients on requests they want to match replies to
+    pub(crate) correlation: Option<u64>,
+    pub(crate) status: EnvelopeStatus,
+    pub(crate) error: Option<String>,
 }
 
 impl ProtobufMsg {
+    pub(crate) fn new(identifier: &str, payload: Vec<u8>) -> Self {
+        Self {
+            identifier: identifier.to_string(),
+            payload,
+            correlation: None,
+            status: EnvelopeStatus::Message,
+            error: None,
+        }
+    }
+
+    /// A reply to this message, carrying its correlation id
+    pub(crate) fn reply(&self, identifier: &str, payload: Vec<u8>) -> Self {
+        Self {
+            correlation: self.correlation,
+            ..Self::new(identifier, payload)
+        }
+    }
+
+    /// Mark a message built elsewhere as a reply to this request
+    pub(crate) fn in_reply_to(mut self, request: &ProtobufMsg) -> Self {
+        self.correlation = request.correlation;
+        self
+    }
+
+    /// The terminal message for a request: end of the replies when the request
+    /// succeeded, or the error when it failed. Only requests that carry a
+    /// correlation id get one, nobody would be waiting for the others.
+    pub(crate) fn completion(&self, result: &Result<()>) -> Option<Self> {
+        self.correlation?;
+        let mut done = self.reply(&self.identifier, Vec::new());
+        match result {
+            Ok(()) => done.status = EnvelopeStatus::End,
+            Err(e) => {
+                done.status = EnvelopeStatus::Error;
+                done.error = Some(format!("{:#}", e));
+            }
+        }
+        Some(done)
+    }
+
     pub(crate) fn to_raw_message(&self) -> Result<RawMessage> {
-        let pm = ProtobufMessage {
+        let pm = Envelope {
             identifier: self.identifier.clone(),
             protobuf : self.payload.clone(),
+            correlation: self.correlation,
+            status: self.status as i32,
+            error: self.error.clone().unwrap_or_default(),
         };
         let mut buf = Vec::new();
         pm.encode(&mut buf).context("Failed to encode protobuf message")?;
         Ok(RawMessage {message: buf})
     }
 
-    pub fn from_raw_message(raw: RawMessage) -> Result<Self> {
-        let pm = ProtobufMessage::decode(&raw.message[..])
-            .context("failed to decode protobuf message")?; // Deserializes from the buffer
-        let msg = ProtobufMsg {
+    fn from_envelope(pm: Envelope) -> Self {
+        ProtobufMsg {
             identifier: pm.identifier.trim().to_string(),
             payload: pm.protobuf,
-        };
-        Ok(msg)
+            correlation: pm.correlation,
+            status: EnvelopeStatus::try_from(pm.status).unwrap_or(EnvelopeStatus::Message),
+            error: Some(pm.error).filter(|error| !error.is_empty()),
+        }
+    }
+
+    pub fn from_raw_message(raw: RawMessage) -> Result<Self> {
+        let pm = Envelope::decode(&raw.message[..])
+            .context("failed to decode protobuf message")?; // Deserializes from the buffer
+        Ok(Self::from_envelope(pm))
     }
 
     pub fn _from_bytes(bytes: Bytes) -> Result<Self> {
-        let pm = ProtobufMessage::decode(bytes)
+        let pm = Envelope::decode(bytes)
             .context("failed to decode protobuf message")?; // Deserializes from the buffer
-        let msg = ProtobufMsg {
-            identifier: pm.identifier,
-            payload: pm.protobuf,
-        };
-        Ok(msg)
+        Ok(Self::from_envelope(pm))
     }
 }
-
-
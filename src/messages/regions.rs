@@ -0,0 +1,30 @@
+// Regions of interest and exclusion masks (hand written prost messages)
+
+/// A corner of a region, as fractions of the frame width and height
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RegionPoint {
+    #[prost(double, tag = "1")]
+    pub x: f64,
+    #[prost(double, tag = "2")]
+    pub y: f64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RegionInfo {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, repeated, tag = "2")]
+    pub points: Vec<RegionPoint>,
+}
+
+/// `regions`, the reply to `regions.get`, and the payload of `regions.set`
+/// which replaces every region
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Regions {
+    /// Detections are only kept inside these, anywhere when there are none
+    #[prost(message, repeated, tag = "1")]
+    pub include: Vec<RegionInfo>,
+    /// Detections inside these are dropped
+    #[prost(message, repeated, tag = "2")]
+    pub exclude: Vec<RegionInfo>,
+}
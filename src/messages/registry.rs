@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context as ErrContext, Result};
+use prost::Message as PbMessage;
+
+use crate::messages::envelope::EnvelopeStatus;
+use crate::messages::identifiers::REQUESTS;
+use crate::messages::protobuf_msg::ProtobufMsg;
+
+type Decoder<C> = Arc<dyn Fn(&[u8]) -> Result<C> + Send + Sync>;
+
+/// The requests an actor handles. Each identifier is registered with the prost
+/// type of its payload and a constructor for the actor's own command type, so
+/// payloads are decoded in one place and handlers only ever see typed values.
+/// Requests without a payload register `()`.
+pub(crate) struct Routes<C> {
+    decoders: HashMap<&'static str, Decoder<C>>,
+}
+
+impl<C> Routes<C> {
+    pub(crate) fn new() -> Self {
+        Self { decoders: HashMap::new() }
+    }
+
+    pub(crate) fn on<M, F>(mut self, identifier: &'static str, command: F) -> Self
+    where
+        M: PbMessage + Default,
+        F: Fn(M) -> C + Send + Sync + 'static,
+    {
+        let decoder = move |payload: &[u8]| {
+            let message = M::decode(payload)
+                .with_context(|| format!("Failed to decode payload of {}", identifier))?;
+            Ok(command(message))
+        };
+        self.decoders.insert(identifier, Arc::new(decoder));
+        self
+    }
+
+    /// Decode a request into a command. None when the request is not routed
+    /// here, the streams are shared and most messages are for other actors.
+    pub(crate) fn decode(&self, msg: &ProtobufMsg) -> Option<Result<C>> {
+        if msg.status != EnvelopeStatus::Message {
+            return None;
+        }
+        let decoder = self.decoders.get(msg.identifier.as_str())?;
+        Some(decoder(&msg.payload))
+    }
+}
+
+impl<C> Clone for Routes<C> {
+    fn clone(&self) -> Self {
+        Self { decoders: self.decoders.clone() }
+    }
+}
+
+impl<C> fmt::Debug for Routes<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.decoders.keys()).finish()
+    }
+}
+
+/// Reject requests from clients that no actor handles
+pub(crate) fn check_request(msg: &ProtobufMsg) -> Result<()> {
+    if REQUESTS.contains(&msg.identifier.as_str()) {
+        Ok(())
+    } else {
+        Err(anyhow!("Unknown request {}", msg.identifier))
+    }
+}
@@ -0,0 +1,13 @@
+// Capture schedule replies (hand written prost messages)
+
+/// Reply to `schedule.get`, times in milliseconds since the epoch
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScheduleInfo {
+    #[prost(bool, tag = "1")]
+    pub enabled: bool,
+    /// The window capture is in or the next one, 0 when there is none
+    #[prost(int64, tag = "2")]
+    pub window_start: i64,
+    #[prost(int64, tag = "3")]
+    pub window_end: i64,
+}
@@ -0,0 +1,20 @@
+// Trap state events (hand written prost messages)
+
+/// `state.changed`, also the reply to `state.get` with the latest transition
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StateChanged {
+    #[prost(enumeration = "crate::state::machine::TrapState", tag = "1")]
+    pub previous: i32,
+    #[prost(enumeration = "crate::state::machine::TrapState", tag = "2")]
+    pub current: i32,
+    /// The request or event that caused the change, or the camera error
+    #[prost(string, tag = "3")]
+    pub reason: String,
+}
+
+/// `camera.failed`, the camera could not be opened or stopped delivering frames
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CameraFailure {
+    #[prost(string, tag = "1")]
+    pub error: String,
+}
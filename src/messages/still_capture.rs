@@ -0,0 +1,25 @@
+use crate::messages::camera_frame::CameraFrame;
+use crate::messages::protobuf_msg::ProtobufMsg;
+
+/// A full resolution still on its way from CameraActor to SessionsActor,
+/// with the request so the reply goes back to whoever asked for it
+#[derive(Clone)]
+pub struct StillCapture {
+    request : ProtobufMsg,
+    frame : CameraFrame
+}
+
+impl StillCapture {
+
+    pub(crate) fn new(request : ProtobufMsg, frame : CameraFrame) -> Self {
+        Self { request, frame }
+    }
+
+    pub fn request(&self) -> &ProtobufMsg {
+        &self.request
+    }
+
+    pub fn frame(&self) -> &CameraFrame {
+        &self.frame
+    }
+}
@@ -0,0 +1,20 @@
+// Still photos (hand written prost messages)
+
+/// `still`, the reply to `camera.still` and to `session.stills`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Still {
+    #[prost(int32, tag = "1")]
+    pub still: i32,
+    /// Empty when no session was active
+    #[prost(string, tag = "2")]
+    pub session: String,
+    #[prost(int64, tag = "3")]
+    pub created: i64,
+    #[prost(int32, tag = "4")]
+    pub width: i32,
+    #[prost(int32, tag = "5")]
+    pub height: i32,
+    /// Left out of the reply to `camera.still`, fetch it with `session.stills`
+    #[prost(bytes = "vec", optional, tag = "6")]
+    pub image: Option<Vec<u8>>,
+}
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use crate::detection::registry::ModelId;
+use crate::messages::camera_frame::CameraFrame;
+use crate::tracking::sort::TrackingResult;
+
+/// The tracks seen in a frame, passed on with the frame so that the
+/// insects can be cropped out of it
+#[derive(Clone)]
+pub struct TrackUpdate {
+    frame : CameraFrame,
+    tracking : TrackingResult,
+    model : Arc<ModelId>
+}
+
+impl TrackUpdate {
+
+    pub(crate) fn new(frame : CameraFrame, tracking : TrackingResult, model : Arc<ModelId>) -> Self {
+        Self { frame, tracking, model }
+    }
+
+    pub fn frame(&self) -> &CameraFrame {
+        &self.frame
+    }
+
+    pub fn tracking(&self) -> &TrackingResult {
+        &self.tracking
+    }
+
+    /// The model that produced the detections being tracked
+    pub fn model(&self) -> &ModelId {
+        &self.model
+    }
+}
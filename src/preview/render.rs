@@ -0,0 +1,137 @@
+use anyhow::{ensure, Context as ErrContext, Result};
+use nokhwa::pixel_format::RgbFormat;
+use photon_rs::transform::{resize, SamplingFilter};
+use photon_rs::PhotonImage;
+
+use crate::config::RegionsConfig;
+use crate::detection::regions::contains;
+use crate::detection::yolo::BoundingBox;
+use crate::messages::camera_frame::CameraFrame;
+
+const BOX_COLOUR: [u8; 4] = [255, 64, 0, 255];
+const BOX_THICKNESS: u32 = 2;
+const INCLUDE_COLOUR: [u8; 4] = [0, 200, 80, 255];
+const EXCLUDE_COLOUR: [u8; 4] = [200, 0, 200, 255];
+
+/// A downscaled, JPEG encoded frame for the live preview
+pub struct Preview {
+    pub width: u32,
+    pub height: u32,
+    pub jpeg: Vec<u8>,
+}
+
+/// Scale a frame down to at most `max_width` pixels across, draw the regions
+/// and boxes on it when there are any, and encode it as a JPEG
+pub fn render_preview(
+    frame: &CameraFrame,
+    boxes: &[BoundingBox],
+    regions: Option<&RegionsConfig>,
+    max_width: u32,
+    quality: u8,
+) -> Result<Preview> {
+    let image = frame
+        .buffer()
+        .decode_image::<RgbFormat>()
+        .context("Failed to decode frame")?;
+    let (frame_width, frame_height) = image.dimensions();
+    ensure!(frame_width > 0 && frame_height > 0, "Empty frame");
+
+    // photon works on RGBA
+    let rgba = image
+        .pixels()
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+        .collect();
+    let mut preview = PhotonImage::new(rgba, frame_width, frame_height);
+
+    let scale = (max_width as f32 / frame_width as f32).min(1.0);
+    if scale < 1.0 {
+        let width = max_width.max(1);
+        let height = ((frame_height as f32 * scale).round() as u32).max(1);
+        preview = resize(&preview, width, height, SamplingFilter::Triangle);
+    }
+
+    let regions = regions.filter(|regions| !regions.include.is_empty() || !regions.exclude.is_empty());
+    if !boxes.is_empty() || regions.is_some() {
+        let (width, height) = (preview.get_width(), preview.get_height());
+        let mut pixels = preview.get_raw_pixels();
+        if let Some(regions) = regions {
+            draw_regions(&mut pixels, width, height, regions);
+        }
+        for bbox in boxes {
+            draw_box(&mut pixels, width, height, bbox, scale);
+        }
+        preview = PhotonImage::new(pixels, width, height);
+    }
+
+    Ok(Preview {
+        width: preview.get_width(),
+        height: preview.get_height(),
+        jpeg: preview.get_bytes_jpeg(quality),
+    })
+}
+
+/// Outline a box, given in frame coordinates, on RGBA pixels scaled by `scale`
+fn draw_box(pixels: &mut [u8], width: u32, height: u32, bbox: &BoundingBox, scale: f32) {
+    let clamp = |value: f32, max: u32| ((value * scale).max(0.0) as u32).min(max - 1);
+    let (x1, x2) = (clamp(bbox.x1, width), clamp(bbox.x2, width));
+    let (y1, y2) = (clamp(bbox.y1, height), clamp(bbox.y2, height));
+
+    let mut set = |x: u32, y: u32| {
+        let offset = ((y * width + x) * 4) as usize;
+        pixels[offset..offset + 4].copy_from_slice(&BOX_COLOUR);
+    };
+    for t in 0..BOX_THICKNESS {
+        for x in x1..=x2 {
+            set(x, (y1 + t).min(y2));
+            set(x, y2.saturating_sub(t).max(y1));
+        }
+        for y in y1..=y2 {
+            set((x1 + t).min(x2), y);
+            set(x2.saturating_sub(t).max(x1), y);
+        }
+    }
+}
+
+/// Shade the excluded regions and outline them all, on RGBA pixels
+fn draw_regions(pixels: &mut [u8], width: u32, height: u32, regions: &RegionsConfig) {
+    for y in 0..height {
+        let fy = (y as f64 + 0.5) / height as f64;
+        for x in 0..width {
+            let fx = (x as f64 + 0.5) / width as f64;
+            if regions.exclude.iter().any(|region| contains(&region.points, fx, fy)) {
+                let offset = ((y * width + x) * 4) as usize;
+                for channel in &mut pixels[offset..offset + 3] {
+                    *channel /= 2;
+                }
+            }
+        }
+    }
+    for (colour, list) in [(INCLUDE_COLOUR, &regions.include), (EXCLUDE_COLOUR, &regions.exclude)] {
+        for region in list {
+            let corners: Vec<(f64, f64)> = region
+                .points
+                .iter()
+                .map(|&[x, y]| (x * (width - 1) as f64, y * (height - 1) as f64))
+                .collect();
+            for (i, &start) in corners.iter().enumerate() {
+                let end = corners[(i + 1) % corners.len()];
+                draw_line(pixels, width, height, start, end, colour);
+            }
+        }
+    }
+}
+
+/// A line two pixels wide between points in preview coordinates
+fn draw_line(pixels: &mut [u8], width: u32, height: u32, start: (f64, f64), end: (f64, f64), colour: [u8; 4]) {
+    let steps = (end.0 - start.0).abs().max((end.1 - start.1).abs()).ceil().max(1.0) as u32;
+    for step in 0..=steps {
+        let t = step as f64 / steps as f64;
+        let x = (start.0 + (end.0 - start.0) * t).round() as u32;
+        let y = (start.1 + (end.1 - start.1) * t).round() as u32;
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1)] {
+            let (x, y) = ((x + dx).min(width - 1), (y + dy).min(height - 1));
+            let offset = ((y * width + x) * 4) as usize;
+            pixels[offset..offset + 4].copy_from_slice(&colour);
+        }
+    }
+}
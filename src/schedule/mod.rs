@@ -0,0 +1,228 @@
+pub mod sun;
+
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context as ErrContext, Result};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime};
+
+use crate::config::{ScheduleConfig, SiteConfig};
+use crate::schedule::sun::{sun_times, SunTimes};
+
+/// When a capture window starts or ends
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeSpec {
+    Clock(NaiveTime),
+    Sunset,
+    Sunrise,
+}
+
+impl FromStr for TimeSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        match spec {
+            "sunset" => Ok(TimeSpec::Sunset),
+            "sunrise" => Ok(TimeSpec::Sunrise),
+            clock => NaiveTime::parse_from_str(clock, "%H:%M")
+                .map(TimeSpec::Clock)
+                .with_context(|| format!("Invalid time '{}', expected HH:MM, sunset or sunrise", clock)),
+        }
+    }
+}
+
+impl TimeSpec {
+    fn is_sun(self) -> bool {
+        matches!(self, TimeSpec::Sunset | TimeSpec::Sunrise)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Window {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+}
+
+/// What the schedule wants the trap to be doing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Desired {
+    Off,
+    On,
+    /// Inside the window but in the off part of the duty cycle
+    Paused,
+}
+
+/// One capture window a night, so each night gets a single session. Within the
+/// window an optional duty cycle pauses capture without closing the session.
+pub struct Schedule {
+    start: TimeSpec,
+    start_offset: Duration,
+    end: TimeSpec,
+    end_offset: Duration,
+    /// Latitude and longitude of the trap, needed for sunset and sunrise
+    position: Option<(f64, f64)>,
+    duty: Option<(Duration, Duration)>,
+}
+
+impl Schedule {
+    pub fn from_config(config: &ScheduleConfig, site: &SiteConfig) -> Result<Self> {
+        let start: TimeSpec = config.start.parse()?;
+        let end: TimeSpec = config.end.parse()?;
+        let position = site.latitude.zip(site.longitude);
+        ensure!(
+            position.is_some() || !start.is_sun() && !end.is_sun(),
+            "A schedule from or to sunset or sunrise needs trap.latitude and trap.longitude"
+        );
+        let duty = match (config.duty_on_min, config.duty_off_min) {
+            (_, 0) => None,
+            (0, _) => bail!("schedule.duty_on_min must be set when schedule.duty_off_min is"),
+            (on, off) => Some((Duration::minutes(on as i64), Duration::minutes(off as i64))),
+        };
+        Ok(Self {
+            start,
+            start_offset: Duration::minutes(config.start_offset_min),
+            end,
+            end_offset: Duration::minutes(config.end_offset_min),
+            position,
+            duty,
+        })
+    }
+
+    fn time_on(&self, spec: TimeSpec, date: NaiveDate) -> Option<DateTime<Local>> {
+        match spec {
+            TimeSpec::Clock(time) => date.and_time(time).and_local_timezone(Local).earliest(),
+            TimeSpec::Sunset | TimeSpec::Sunrise => {
+                let (latitude, longitude) = self.position?;
+                match sun_times(date, latitude, longitude) {
+                    SunTimes::RiseSet { sunrise, sunset } => {
+                        let time = if spec == TimeSpec::Sunset { sunset } else { sunrise };
+                        Some(time.with_timezone(&Local))
+                    }
+                    // no sunset or sunrise near the poles in summer or winter
+                    SunTimes::AlwaysUp | SunTimes::AlwaysDown => None,
+                }
+            }
+        }
+    }
+
+    /// The window of the night that starts on `date`
+    pub fn window(&self, date: NaiveDate) -> Option<Window> {
+        let start = self.time_on(self.start, date)? + self.start_offset;
+        let mut end = self.time_on(self.end, date)? + self.end_offset;
+        if end <= start {
+            end = self.time_on(self.end, date.succ_opt()?)? + self.end_offset;
+        }
+        (end > start).then_some(Window { start, end })
+    }
+
+    /// The window `now` falls in, or the next one to start
+    pub fn current_or_next(&self, now: DateTime<Local>) -> Option<Window> {
+        let today = now.date_naive();
+        let yesterday = today.pred_opt()?;
+        [yesterday, today, today.succ_opt()?]
+            .into_iter()
+            .filter_map(|date| self.window(date))
+            .find(|window| now < window.end)
+    }
+
+    pub fn desired(&self, now: DateTime<Local>) -> Desired {
+        let Some(window) = self.current_or_next(now).filter(|window| window.start <= now) else {
+            return Desired::Off;
+        };
+        match self.duty {
+            None => Desired::On,
+            Some((on, off)) => {
+                let cycle = (on + off).num_seconds();
+                let phase = (now - window.start).num_seconds() % cycle;
+                if phase < on.num_seconds() { Desired::On } else { Desired::Paused }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn schedule(start: &str, end: &str, duty_on_min: u64, duty_off_min: u64) -> Schedule {
+        let config = ScheduleConfig {
+            start: start.to_string(),
+            end: end.to_string(),
+            duty_on_min,
+            duty_off_min,
+            ..Default::default()
+        };
+        Schedule::from_config(&config, &SiteConfig::default()).unwrap()
+    }
+
+    /// A January night, clear of any daylight saving change
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn window_crosses_midnight() {
+        let schedule = schedule("22:00", "02:00", 0, 0);
+        let window = schedule.window(at(15, 0, 0).date_naive()).unwrap();
+        assert_eq!(window, Window { start: at(15, 22, 0), end: at(16, 2, 0) });
+
+        assert_eq!(schedule.desired(at(15, 21, 59)), Desired::Off);
+        assert_eq!(schedule.desired(at(15, 22, 0)), Desired::On);
+        assert_eq!(schedule.desired(at(15, 23, 30)), Desired::On);
+        assert_eq!(schedule.desired(at(16, 1, 30)), Desired::On);
+        assert_eq!(schedule.desired(at(16, 2, 0)), Desired::Off);
+        assert_eq!(schedule.desired(at(16, 12, 0)), Desired::Off);
+    }
+
+    #[test]
+    fn duty_cycle_pauses_inside_the_window() {
+        // 30 minutes on, 15 off from 20:00, so cycles start every 45 minutes
+        let schedule = schedule("20:00", "04:00", 30, 15);
+        assert_eq!(schedule.desired(at(15, 19, 50)), Desired::Off);
+        assert_eq!(schedule.desired(at(15, 20, 10)), Desired::On);
+        assert_eq!(schedule.desired(at(15, 20, 40)), Desired::Paused);
+        assert_eq!(schedule.desired(at(15, 20, 45)), Desired::On);
+        // 245 and 260 minutes into the window, across midnight
+        assert_eq!(schedule.desired(at(16, 0, 5)), Desired::On);
+        assert_eq!(schedule.desired(at(16, 0, 20)), Desired::Paused);
+        assert_eq!(schedule.desired(at(16, 4, 0)), Desired::Off);
+    }
+
+    #[test]
+    fn sunset_window_runs_to_the_next_sunrise() {
+        let config = ScheduleConfig { end_offset_min: 30, ..Default::default() };
+        let site = SiteConfig { latitude: Some(51.5), longitude: Some(-0.13), ..Default::default() };
+        let schedule = Schedule::from_config(&config, &site).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let (SunTimes::RiseSet { sunset, .. }, SunTimes::RiseSet { sunrise, .. }) =
+            (sun_times(date, 51.5, -0.13), sun_times(date.succ_opt().unwrap(), 51.5, -0.13))
+        else {
+            panic!("London has a sunrise and sunset in June");
+        };
+        let window = schedule.window(date).unwrap();
+        assert_eq!(window.start.with_timezone(&Utc), sunset);
+        assert_eq!(window.end.with_timezone(&Utc), sunrise + Duration::minutes(30));
+    }
+
+    #[test]
+    fn rejects_bad_config() {
+        let site = SiteConfig { latitude: Some(51.5), longitude: Some(-0.13), ..Default::default() };
+        let config = ScheduleConfig { duty_off_min: 10, ..Default::default() };
+        assert!(Schedule::from_config(&config, &site).is_err());
+        let config = ScheduleConfig { start: "25:00".to_string(), ..Default::default() };
+        assert!(Schedule::from_config(&config, &site).is_err());
+    }
+
+    #[test]
+    fn sun_times_need_the_trap_position() {
+        let clock = ScheduleConfig { start: "22:00".to_string(), end: "04:00".to_string(), ..Default::default() };
+        assert!(Schedule::from_config(&clock, &SiteConfig::default()).is_ok());
+        for (start, end) in [("sunset", "04:00"), ("22:00", "sunrise")] {
+            let config = ScheduleConfig { start: start.to_string(), end: end.to_string(), ..Default::default() };
+            assert!(Schedule::from_config(&config, &SiteConfig::default()).is_err(), "{} to {}", start, end);
+            // half a position is no position
+            let site = SiteConfig { latitude: Some(51.5), ..Default::default() };
+            assert!(Schedule::from_config(&config, &site).is_err(), "{} to {}", start, end);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use chrono::{DateTime, NaiveDate, Utc};
+
+// Sunrise and sunset from the sunrise equation, accurate to a minute or two
+// which is plenty for switching a light trap.
+
+const J2000: f64 = 2451545.0;
+const UNIX_EPOCH_JD: f64 = 2440587.5;
+const OBLIQUITY: f64 = 23.4397;
+/// Sun centre this far below the horizon at sunrise and sunset, for refraction
+/// and the radius of the disc
+const HORIZON: f64 = -0.833;
+
+pub enum SunTimes {
+    RiseSet { sunrise: DateTime<Utc>, sunset: DateTime<Utc> },
+    /// The sun stays above the horizon all day
+    AlwaysUp,
+    /// The sun stays below the horizon all day
+    AlwaysDown,
+}
+
+fn to_utc(julian_date: f64) -> DateTime<Utc> {
+    let millis = ((julian_date - UNIX_EPOCH_JD) * 86_400_000.0).round() as i64;
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+}
+
+/// Sunrise and sunset on `date` at a place, longitude positive east
+pub fn sun_times(date: NaiveDate, latitude: f64, longitude: f64) -> SunTimes {
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+    let days = (date - epoch).num_days() as f64;
+
+    let mean_solar_noon = days - longitude / 360.0;
+    let anomaly = (357.5291 + 0.98560028 * mean_solar_noon).rem_euclid(360.0).to_radians();
+    let centre = 1.9148 * anomaly.sin() + 0.0200 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic_longitude = (anomaly.to_degrees() + centre + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit = J2000 + mean_solar_noon + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let declination = (ecliptic_longitude.sin() * OBLIQUITY.to_radians().sin()).asin();
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = (HORIZON.to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+
+    if cos_hour_angle > 1.0 {
+        return SunTimes::AlwaysDown;
+    }
+    if cos_hour_angle < -1.0 {
+        return SunTimes::AlwaysUp;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    SunTimes::RiseSet {
+        sunrise: to_utc(transit - hour_angle / 360.0),
+        sunset: to_utc(transit + hour_angle / 360.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn rise_set(date: NaiveDate, latitude: f64, longitude: f64) -> (DateTime<Utc>, DateTime<Utc>) {
+        match sun_times(date, latitude, longitude) {
+            SunTimes::RiseSet { sunrise, sunset } => (sunrise, sunset),
+            SunTimes::AlwaysUp | SunTimes::AlwaysDown => panic!("expected a sunrise and sunset"),
+        }
+    }
+
+    fn assert_near(actual: DateTime<Utc>, expected: DateTime<Utc>) {
+        let error = (actual - expected).num_seconds().abs();
+        assert!(error <= 180, "{} is {}s from {}", actual, error, expected);
+    }
+
+    #[test]
+    fn london_midsummer() {
+        // Published times 04:43 and 21:21 BST
+        let (sunrise, sunset) = rise_set(NaiveDate::from_ymd_opt(2024, 6, 21).unwrap(), 51.5074, -0.1278);
+        assert_near(sunrise, Utc.with_ymd_and_hms(2024, 6, 21, 3, 43, 0).unwrap());
+        assert_near(sunset, Utc.with_ymd_and_hms(2024, 6, 21, 20, 21, 0).unwrap());
+    }
+
+    #[test]
+    fn sydney_midsummer() {
+        // Published times 05:41 and 20:05 AEDT, sunrise falls on the previous UTC day
+        let (sunrise, sunset) = rise_set(NaiveDate::from_ymd_opt(2024, 12, 21).unwrap(), -33.8688, 151.2093);
+        assert_near(sunrise, Utc.with_ymd_and_hms(2024, 12, 20, 18, 41, 0).unwrap());
+        assert_near(sunset, Utc.with_ymd_and_hms(2024, 12, 21, 9, 5, 0).unwrap());
+    }
+
+    #[test]
+    fn polar_day_and_night() {
+        let (latitude, longitude) = (69.65, 18.96);
+        assert!(matches!(
+            sun_times(NaiveDate::from_ymd_opt(2024, 6, 21).unwrap(), latitude, longitude),
+            SunTimes::AlwaysUp
+        ));
+        assert!(matches!(
+            sun_times(NaiveDate::from_ymd_opt(2024, 12, 21).unwrap(), latitude, longitude),
+            SunTimes::AlwaysDown
+        ));
+    }
+}
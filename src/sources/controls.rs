@@ -0,0 +1,81 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Context as ErrContext, Result};
+
+/// The camera settings that can be changed from the app
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Control {
+    Exposure,
+    Gain,
+    WhiteBalance,
+    Focus,
+}
+
+impl Control {
+    pub const ALL: [Control; 4] = [Control::Exposure, Control::Gain, Control::WhiteBalance, Control::Focus];
+
+    /// Name used in messages and in the `camera.controls` configuration
+    pub fn name(self) -> &'static str {
+        match self {
+            Control::Exposure => "exposure",
+            Control::Gain => "gain",
+            Control::WhiteBalance => "white_balance",
+            Control::Focus => "focus",
+        }
+    }
+}
+
+impl fmt::Display for Control {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Control {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match Control::ALL.into_iter().find(|control| control.name() == name) {
+            Some(control) => Ok(control),
+            None => bail!("Unknown camera control '{}'", name),
+        }
+    }
+}
+
+/// A control offered by a source, with its range and current value. Sources
+/// that give no range report `min` and `max` as 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ControlInfo {
+    pub control: Control,
+    pub min: i64,
+    pub max: i64,
+    pub step: i64,
+    pub default: i64,
+    pub value: i64,
+}
+
+impl ControlInfo {
+    pub fn check(&self, value: i64) -> Result<()> {
+        if self.min != self.max && !(self.min..=self.max).contains(&value) {
+            bail!("{} must be between {} and {}", self.control, self.min, self.max);
+        }
+        Ok(())
+    }
+}
+
+/// A frame size a source can deliver, `fps` is 0 when the source does not say
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceFormat {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+}
+
+/// Parse a frame size written as `<width>x<height>`
+pub fn parse_size(size: &str) -> Result<(u32, u32)> {
+    size.split_once('x')
+        .and_then(|(w, h)| Some((w.parse::<u32>().ok()?, h.parse::<u32>().ok()?)))
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .with_context(|| format!("Invalid frame size '{}'", size))
+}
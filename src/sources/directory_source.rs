@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use anyhow::{Context as ErrContext, Result};
+use chrono::Local;
+use log::{debug, warn};
+use nokhwa::utils::{FrameFormat, Resolution};
+use nokhwa::Buffer;
+
+use crate::messages::camera_frame::CameraFrame;
+use crate::sources::frame_source::FrameSource;
+
+const IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "bmp", "tif"];
+
+/// Frames read from the image files in a directory, in file name order
+pub struct DirectorySource {
+    directory: PathBuf,
+    files: VecDeque<PathBuf>,
+}
+
+impl DirectorySource {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory, files: VecDeque::new() }
+    }
+}
+
+impl FrameSource for DirectorySource {
+    fn name(&self) -> String {
+        format!("dir:{}", self.directory.display())
+    }
+
+    fn open(&mut self) -> Result<()> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&self.directory)
+            .with_context(|| format!("Failed to read directory {}", self.directory.display()))? {
+            let path = entry?.path();
+            let is_image = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+                .unwrap_or(false);
+            if is_image {
+                files.push(path);
+            }
+        }
+        files.sort();
+        debug!("Found {} images in {}", files.len(), self.directory.display());
+        self.files = files.into();
+        Ok(())
+    }
+
+    fn next_frame(&mut self) -> Result<Option<CameraFrame>> {
+        while let Some(path) = self.files.pop_front() {
+            match image::open(&path) {
+                Ok(img) => {
+                    let rgb = img.to_rgb8();
+                    let buffer = Buffer::new(
+                        Resolution::new(rgb.width(), rgb.height()),
+                        rgb.as_raw(),
+                        FrameFormat::RAWRGB,
+                    );
+                    return Ok(Some(CameraFrame::new(Local::now().timestamp_millis(), buffer)));
+                }
+                Err(e) => warn!("Skipping {}: {}", path.display(), e),
+            }
+        }
+        Ok(None)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.files.clear();
+        Ok(())
+    }
+}
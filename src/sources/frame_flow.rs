@@ -0,0 +1,218 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use async_channel::{Sender as ChannelSender, TrySendError};
+use serde::{Deserialize, Serialize};
+
+use crate::messages::camera_frame::CameraFrame;
+
+/// How often a blocked send looks for room, and for capture being stopped
+const BLOCK_POLL: Duration = Duration::from_millis(10);
+
+/// What the capture loop does with a frame when DetectionActor has fallen
+/// behind and the frame channel is full
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FullPolicy {
+    /// Make room by dropping the oldest frame waiting, detection sees the latest
+    #[default]
+    DropOldest,
+    /// Drop the new frame, detection works through the ones waiting
+    DropNewest,
+    /// Wait for room, the camera is read no faster than detection keeps up
+    Block,
+}
+
+/// Frames read from the source and frames dropped on the way to detection,
+/// since CameraActor started
+#[derive(Debug, Default)]
+pub struct FrameStats {
+    captured: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl FrameStats {
+    pub fn captured(&self) -> u64 {
+        self.captured.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Paces the capture loop to the target frame rate and hands frames to the
+/// frame channel according to the full policy
+pub struct FrameFlow {
+    frame_tx: ChannelSender<CameraFrame>,
+    // the capture loop's stop flag, a blocked send gives up once it is cleared
+    running: Arc<AtomicBool>,
+    policy: FullPolicy,
+    interval: Option<Duration>,
+    next: Instant,
+    stats: Arc<FrameStats>,
+}
+
+impl FrameFlow {
+    /// `fps` of 0 reads frames as fast as the source delivers them
+    pub fn new(
+        frame_tx: ChannelSender<CameraFrame>,
+        running: Arc<AtomicBool>,
+        fps: f64,
+        policy: FullPolicy,
+        stats: Arc<FrameStats>,
+    ) -> Self {
+        Self {
+            frame_tx,
+            running,
+            policy,
+            interval: (fps > 0.0).then(|| Duration::from_secs_f64(1.0 / fps)),
+            next: Instant::now(),
+            stats,
+        }
+    }
+
+    /// Sleep until the next frame is due
+    pub fn wait(&mut self) {
+        let Some(interval) = self.interval else { return };
+        let now = Instant::now();
+        if self.next > now {
+            thread::sleep(self.next - now);
+        }
+        // a slow source resets the pace rather than catching up in a burst
+        self.next = self.next.max(now) + interval;
+    }
+
+    /// Pass a frame on. Returns false once the frame channel is closed.
+    pub fn send(&mut self, frame: CameraFrame) -> bool {
+        self.stats.captured.fetch_add(1, Ordering::Relaxed);
+        match self.policy {
+            FullPolicy::DropOldest => match self.frame_tx.force_send(frame) {
+                Ok(Some(_oldest)) => {
+                    self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Ok(None) => true,
+                Err(_) => false,
+            },
+            FullPolicy::DropNewest => match self.frame_tx.try_send(frame) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(TrySendError::Closed(_)) => false,
+            },
+            FullPolicy::Block => self.send_blocking(frame),
+        }
+    }
+
+    /// Wait for room, but never past a stop. Receivers of the frame channel
+    /// outlive a DetectionActor that stops reading, so the channel may stay
+    /// full and open for good.
+    fn send_blocking(&mut self, mut frame: CameraFrame) -> bool {
+        loop {
+            match self.frame_tx.try_send(frame) {
+                Ok(()) => return true,
+                Err(TrySendError::Closed(_)) => return false,
+                Err(TrySendError::Full(rejected)) => {
+                    if !self.running.load(Ordering::Relaxed) {
+                        self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                        return true;
+                    }
+                    frame = rejected;
+                    thread::sleep(BLOCK_POLL);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nokhwa::utils::{FrameFormat, Resolution};
+    use nokhwa::Buffer;
+
+    use super::*;
+
+    fn frame(timestamp: i64) -> CameraFrame {
+        CameraFrame::new(timestamp, Buffer::new(Resolution::new(1, 1), &[], FrameFormat::MJPEG))
+    }
+
+    fn flow(frame_tx: ChannelSender<CameraFrame>, policy: FullPolicy) -> (FrameFlow, Arc<AtomicBool>) {
+        let running = Arc::new(AtomicBool::new(true));
+        let flow = FrameFlow::new(frame_tx, running.clone(), 0.0, policy, Arc::new(FrameStats::default()));
+        (flow, running)
+    }
+
+    fn timestamps(frame_rx: &async_channel::Receiver<CameraFrame>) -> Vec<i64> {
+        std::iter::from_fn(|| frame_rx.try_recv().ok()).map(|frame| frame.timestamp()).collect()
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_latest_frames() {
+        let (frame_tx, frame_rx) = async_channel::bounded(2);
+        let (mut flow, _running) = flow(frame_tx, FullPolicy::DropOldest);
+        for timestamp in 1..=4 {
+            assert!(flow.send(frame(timestamp)));
+        }
+        assert_eq!(timestamps(&frame_rx), [3, 4]);
+        assert_eq!((flow.stats.captured(), flow.stats.dropped()), (4, 2));
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_waiting_frames() {
+        let (frame_tx, frame_rx) = async_channel::bounded(2);
+        let (mut flow, _running) = flow(frame_tx, FullPolicy::DropNewest);
+        for timestamp in 1..=4 {
+            assert!(flow.send(frame(timestamp)));
+        }
+        assert_eq!(timestamps(&frame_rx), [1, 2]);
+        assert_eq!((flow.stats.captured(), flow.stats.dropped()), (4, 2));
+    }
+
+    #[test]
+    fn block_waits_for_room() {
+        let (frame_tx, frame_rx) = async_channel::bounded(1);
+        let (mut flow, _running) = flow(frame_tx, FullPolicy::Block);
+        assert!(flow.send(frame(1)));
+        let reader = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let first = frame_rx.recv_blocking().unwrap().timestamp();
+            let second = frame_rx.recv_blocking().unwrap().timestamp();
+            (first, second)
+        });
+        assert!(flow.send(frame(2)));
+        assert_eq!(reader.join().unwrap(), (1, 2));
+        assert_eq!(flow.stats.dropped(), 0);
+    }
+
+    #[test]
+    fn block_gives_up_when_capture_stops() {
+        // the receiver is kept but nobody reads it, as when DetectionActor has stopped
+        let (frame_tx, _frame_rx) = async_channel::bounded(1);
+        let (mut flow, running) = flow(frame_tx, FullPolicy::Block);
+        assert!(flow.send(frame(1)));
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            running.store(false, Ordering::Relaxed);
+        });
+        let started = Instant::now();
+        assert!(flow.send(frame(2)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(flow.stats.dropped(), 1);
+        stopper.join().unwrap();
+    }
+
+    #[test]
+    fn closed_channel_ends_capture() {
+        for policy in [FullPolicy::DropOldest, FullPolicy::DropNewest, FullPolicy::Block] {
+            let (frame_tx, frame_rx) = async_channel::bounded(1);
+            drop(frame_rx);
+            let (mut flow, _running) = flow(frame_tx, policy);
+            assert!(!flow.send(frame(1)), "{:?}", policy);
+        }
+    }
+}
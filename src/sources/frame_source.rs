@@ -0,0 +1,88 @@
+use anyhow::{bail, Context as ErrContext, Result};
+
+use crate::messages::camera_frame::CameraFrame;
+use crate::sources::controls::{parse_size, Control, ControlInfo, SourceFormat};
+use crate::sources::directory_source::DirectorySource;
+use crate::sources::nokhwa_source::NokhwaSource;
+use crate::sources::synthetic_source::SyntheticSource;
+use crate::sources::video_source::VideoSource;
+
+/// A producer of camera frames. CameraActor owns one source and drives it from a
+/// blocking task, so implementations are free to block while waiting for a frame.
+pub trait FrameSource: Send {
+    /// Short description used in log messages
+    fn name(&self) -> String;
+
+    /// Prepare the source for reading, e.g. open the device or scan the directory
+    fn open(&mut self) -> Result<()>;
+
+    /// Read the next frame. `Ok(None)` means the source is exhausted.
+    fn next_frame(&mut self) -> Result<Option<CameraFrame>>;
+
+    /// Capture a reference photo at the full resolution of the source. The
+    /// source is open when this is called. By default this is just the next
+    /// frame, sources that can stream below their highest resolution switch
+    /// up for the still and back afterwards.
+    fn still(&mut self) -> Result<CameraFrame> {
+        self.next_frame()?.context("Frame source is exhausted")
+    }
+
+    /// The controls the source offers, with their current values. Sources
+    /// without any offer none.
+    fn controls(&mut self) -> Result<Vec<ControlInfo>> {
+        Ok(Vec::new())
+    }
+
+    fn set_control(&mut self, control: Control, _value: i64) -> Result<()> {
+        bail!("{} has no {} control", self.name(), control)
+    }
+
+    /// The frame sizes the source can deliver
+    fn formats(&mut self) -> Result<Vec<SourceFormat>> {
+        Ok(Vec::new())
+    }
+
+    /// The frame size currently delivered, if the source knows it
+    fn format(&mut self) -> Result<Option<SourceFormat>> {
+        Ok(None)
+    }
+
+    /// Deliver frames of this size from now on, or from the next `open`
+    fn set_resolution(&mut self, width: u32, height: u32) -> Result<()> {
+        bail!("{} cannot deliver {}x{} frames", self.name(), width, height)
+    }
+
+    /// Release the underlying device or files
+    fn close(&mut self) -> Result<()>;
+}
+
+/// Create a frame source from a specification string:
+///
+/// * `camera:<index>` - a webcam through nokhwa
+/// * `dir:<path>` - every image in a directory, in file name order
+/// * `video:<path>` - a motion JPEG file
+/// * `synthetic[:<width>x<height>]` - generated frames, no hardware needed
+pub fn create_frame_source(spec: &str) -> Result<Box<dyn FrameSource>> {
+    let (kind, arg) = match spec.split_once(':') {
+        Some((kind, arg)) => (kind, Some(arg)),
+        None => (spec, None),
+    };
+    let source: Box<dyn FrameSource> = match (kind, arg) {
+        ("camera", None) => Box::new(NokhwaSource::new(0)),
+        ("camera", Some(index)) => {
+            let index = index
+                .parse::<u32>()
+                .with_context(|| format!("Invalid camera index '{}'", index))?;
+            Box::new(NokhwaSource::new(index))
+        }
+        ("dir", Some(path)) => Box::new(DirectorySource::new(path.into())),
+        ("video", Some(path)) => Box::new(VideoSource::new(path.into())),
+        ("synthetic", None) => Box::new(SyntheticSource::new(640, 480)),
+        ("synthetic", Some(size)) => {
+            let (width, height) = parse_size(size)?;
+            Box::new(SyntheticSource::new(width, height))
+        }
+        _ => bail!("Unknown frame source '{}'", spec),
+    };
+    Ok(source)
+}
@@ -0,0 +1,7 @@
+pub mod frame_source;
+pub mod controls;
+pub mod frame_flow;
+pub mod nokhwa_source;
+pub mod directory_source;
+pub mod video_source;
+pub mod synthetic_source;
@@ -0,0 +1,163 @@
+use anyhow::{Context as ErrContext, Result};
+use chrono::Local;
+use log::debug;
+use nokhwa::pixel_format::RgbFormat;
+use nokhwa::utils::{
+    CameraFormat, CameraIndex, ControlValueDescription, ControlValueSetter, KnownCameraControl,
+    RequestedFormat, RequestedFormatType, Resolution,
+};
+use nokhwa::CallbackCamera;
+
+use crate::messages::camera_frame::CameraFrame;
+use crate::sources::controls::{Control, ControlInfo, SourceFormat};
+use crate::sources::frame_source::FrameSource;
+
+/// Frames from a locally attached camera
+pub struct NokhwaSource {
+    index: u32,
+    // highest resolution the camera offers unless set
+    resolution: Option<Resolution>,
+    camera: Option<CallbackCamera>,
+}
+
+impl NokhwaSource {
+    pub fn new(index: u32) -> Self {
+        Self { index, resolution: None, camera: None }
+    }
+
+    fn camera(&mut self) -> Result<&mut CallbackCamera> {
+        self.camera.as_mut().context("Camera is not open")
+    }
+
+    fn requested_format(&self) -> RequestedFormat<'static> {
+        let format = match self.resolution {
+            Some(resolution) => RequestedFormatType::HighestResolution(resolution),
+            None => RequestedFormatType::AbsoluteHighestResolution,
+        };
+        RequestedFormat::new::<RgbFormat>(format)
+    }
+}
+
+fn known_control(control: Control) -> KnownCameraControl {
+    match control {
+        Control::Exposure => KnownCameraControl::Exposure,
+        Control::Gain => KnownCameraControl::Gain,
+        Control::WhiteBalance => KnownCameraControl::WhiteBalance,
+        Control::Focus => KnownCameraControl::Focus,
+    }
+}
+
+fn source_format(format: CameraFormat) -> SourceFormat {
+    SourceFormat { width: format.width(), height: format.height(), fps: format.frame_rate() }
+}
+
+impl FrameSource for NokhwaSource {
+    fn name(&self) -> String {
+        format!("camera:{}", self.index)
+    }
+
+    fn open(&mut self) -> Result<()> {
+        let mut camera = CallbackCamera::new(CameraIndex::Index(self.index), self.requested_format(), |_| {})
+            .with_context(|| format!("Failed to open camera {}", self.index))?;
+
+        let cam_format = camera.camera_format()?;
+        debug!("{:?}", cam_format);
+
+        camera.open_stream().context("Failed to open camera stream")?;
+        self.camera = Some(camera);
+        Ok(())
+    }
+
+    fn next_frame(&mut self) -> Result<Option<CameraFrame>> {
+        match self.camera.as_mut() {
+            Some(camera) => {
+                let buffer = camera.poll_frame().context("Failed to read camera frame")?;
+                Ok(Some(CameraFrame::new(Local::now().timestamp_millis(), buffer)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Capture at the highest resolution the camera offers, then go back to
+    /// the frame size used for detection
+    fn still(&mut self) -> Result<CameraFrame> {
+        if self.resolution.is_none() {
+            // already streaming at the highest resolution
+            return self.next_frame()?.context("Camera is not open");
+        }
+        let streaming = self.requested_format();
+        let camera = self.camera()?;
+        let highest = RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestResolution);
+        let format = camera
+            .set_camera_requset(highest)
+            .context("Failed to switch to the highest resolution")?;
+        debug!("Still at {:?}", format);
+        let still = camera.poll_frame().context("Failed to read still");
+        let restored = camera
+            .set_camera_requset(streaming)
+            .context("Failed to switch back to the capture resolution");
+        let buffer = still?;
+        restored?;
+        Ok(CameraFrame::new(Local::now().timestamp_millis(), buffer))
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Some(mut camera) = self.camera.take() {
+            camera.stop_stream().context("Failed to stop camera stream")?;
+        }
+        Ok(())
+    }
+
+    fn controls(&mut self) -> Result<Vec<ControlInfo>> {
+        let mut controls = Vec::new();
+        for camera_control in self.camera()?.camera_controls()? {
+            let Some(control) = Control::ALL
+                .into_iter()
+                .find(|control| known_control(*control) == camera_control.control())
+            else {
+                continue;
+            };
+            let info = match *camera_control.description() {
+                ControlValueDescription::IntegerRange { min, max, value, step, default } => {
+                    ControlInfo { control, min, max, step, default, value }
+                }
+                ControlValueDescription::Integer { value, default, step } => {
+                    ControlInfo { control, min: 0, max: 0, step, default, value }
+                }
+                ref other => {
+                    debug!("Ignoring {} control {:?}", control, other);
+                    continue;
+                }
+            };
+            controls.push(info);
+        }
+        Ok(controls)
+    }
+
+    fn set_control(&mut self, control: Control, value: i64) -> Result<()> {
+        self.camera()?
+            .set_camera_control(known_control(control), ControlValueSetter::Integer(value))
+            .with_context(|| format!("Failed to set {} to {}", control, value))
+    }
+
+    fn formats(&mut self) -> Result<Vec<SourceFormat>> {
+        let formats = self.camera()?.compatible_camera_formats()?;
+        Ok(formats.into_iter().map(source_format).collect())
+    }
+
+    fn format(&mut self) -> Result<Option<SourceFormat>> {
+        Ok(Some(source_format(self.camera()?.camera_format()?)))
+    }
+
+    fn set_resolution(&mut self, width: u32, height: u32) -> Result<()> {
+        self.resolution = Some(Resolution::new(width, height));
+        let request = self.requested_format();
+        if let Some(camera) = self.camera.as_mut() {
+            let format = camera
+                .set_camera_requset(request)
+                .with_context(|| format!("Failed to set resolution {}x{}", width, height))?;
+            debug!("{:?}", format);
+        }
+        Ok(())
+    }
+}
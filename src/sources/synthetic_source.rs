@@ -0,0 +1,152 @@
+use anyhow::{ensure, Context as ErrContext, Result};
+use chrono::Local;
+use nokhwa::utils::{FrameFormat, Resolution};
+use nokhwa::Buffer;
+
+use crate::messages::camera_frame::CameraFrame;
+use crate::sources::controls::{Control, ControlInfo, SourceFormat};
+use crate::sources::frame_source::FrameSource;
+
+const INSECTS: u32 = 3;
+const INSECT_SIZE: u32 = 24;
+const MAX_SIZE: u32 = 4096;
+
+/// Sizes offered to the app, any other size up to MAX_SIZE works as well
+const FORMATS: [(u32, u32); 4] = [(640, 480), (1280, 960), (1920, 1080), (3840, 2160)];
+
+/// The same controls a camera offers. Exposure and gain brighten the sheet,
+/// white balance tints it and focus is only remembered.
+const CONTROLS: [ControlInfo; 4] = [
+    ControlInfo { control: Control::Exposure, min: 1, max: 1000, step: 1, default: 100, value: 100 },
+    ControlInfo { control: Control::Gain, min: 0, max: 100, step: 1, default: 0, value: 0 },
+    ControlInfo { control: Control::WhiteBalance, min: 2800, max: 6500, step: 10, default: 4600, value: 4600 },
+    ControlInfo { control: Control::Focus, min: 0, max: 255, step: 1, default: 0, value: 0 },
+];
+
+/// Generated frames: a light sheet with a few dark blobs wandering across it.
+/// Good enough to exercise the whole pipeline without a camera.
+pub struct SyntheticSource {
+    width: u32,
+    height: u32,
+    frame: u64,
+    controls: [ControlInfo; 4],
+}
+
+impl SyntheticSource {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, frame: 0, controls: CONTROLS }
+    }
+
+    fn control(&self, control: Control) -> i64 {
+        self.controls.iter().find(|info| info.control == control).map_or(0, |info| info.value)
+    }
+
+    fn render(&self) -> Vec<u8> {
+        let (width, height) = (self.width, self.height);
+        let brightness = self.control(Control::Exposure) as f32 / 100.0
+            * (1.0 + self.control(Control::Gain) as f32 / 100.0);
+        // warmer light below the default, cooler above
+        let tint = ((self.control(Control::WhiteBalance) - 4600) / 100) as f32;
+        let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                // faint vignette so the background is not perfectly flat
+                let shade = (230 - (x * 20 / width.max(1)) - (y * 20 / height.max(1))) as f32 * brightness;
+                let red = (shade - tint).clamp(0.0, 255.0) as u8;
+                let blue = (shade + tint - 10.0).clamp(0.0, 255.0) as u8;
+                pixels.extend_from_slice(&[red, shade.min(255.0) as u8, blue]);
+            }
+        }
+
+        let span_x = width.saturating_sub(INSECT_SIZE).max(1) as u64;
+        let span_y = height.saturating_sub(INSECT_SIZE).max(1) as u64;
+        for insect in 0..INSECTS as u64 {
+            let left = ((self.frame * (insect + 1) + insect * 97) % span_x) as u32;
+            let top = ((self.frame / (insect + 2) + insect * 131) % span_y) as u32;
+            for y in top..(top + INSECT_SIZE).min(height) {
+                for x in left..(left + INSECT_SIZE).min(width) {
+                    let offset = ((y * width + x) * 3) as usize;
+                    pixels[offset..offset + 3].copy_from_slice(&[60, 45, 30]);
+                }
+            }
+        }
+        pixels
+    }
+}
+
+impl FrameSource for SyntheticSource {
+    fn name(&self) -> String {
+        format!("synthetic:{}x{}", self.width, self.height)
+    }
+
+    fn open(&mut self) -> Result<()> {
+        self.frame = 0;
+        Ok(())
+    }
+
+    fn next_frame(&mut self) -> Result<Option<CameraFrame>> {
+        let pixels = self.render();
+        self.frame += 1;
+        let buffer = Buffer::new(Resolution::new(self.width, self.height), &pixels, FrameFormat::RAWRGB);
+        Ok(Some(CameraFrame::new(Local::now().timestamp_millis(), buffer)))
+    }
+
+    fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn controls(&mut self) -> Result<Vec<ControlInfo>> {
+        Ok(self.controls.to_vec())
+    }
+
+    fn set_control(&mut self, control: Control, value: i64) -> Result<()> {
+        let info = self
+            .controls
+            .iter_mut()
+            .find(|info| info.control == control)
+            .context("Unknown control")?;
+        info.check(value)?;
+        info.value = value;
+        Ok(())
+    }
+
+    fn formats(&mut self) -> Result<Vec<SourceFormat>> {
+        Ok(FORMATS
+            .into_iter()
+            .map(|(width, height)| SourceFormat { width, height, fps: 0 })
+            .collect())
+    }
+
+    fn format(&mut self) -> Result<Option<SourceFormat>> {
+        Ok(Some(SourceFormat { width: self.width, height: self.height, fps: 0 }))
+    }
+
+    fn set_resolution(&mut self, width: u32, height: u32) -> Result<()> {
+        ensure!(width > 0 && height > 0, "Invalid frame size '{}x{}'", width, height);
+        ensure!(
+            width <= MAX_SIZE && height <= MAX_SIZE,
+            "Synthetic frames are at most {}x{}",
+            MAX_SIZE,
+            MAX_SIZE
+        );
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_resolution_rejects_empty_frames() {
+        let mut source = SyntheticSource::new(640, 480);
+        for (width, height) in [(0, 0), (0, 480), (640, 0), (MAX_SIZE + 1, 480)] {
+            assert!(source.set_resolution(width, height).is_err(), "{}x{}", width, height);
+        }
+        assert_eq!((source.width, source.height), (640, 480));
+        source.set_resolution(320, 240).unwrap();
+        assert_eq!((source.width, source.height), (320, 240));
+    }
+}
@@ -0,0 +1,177 @@
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read};
+use std::path::PathBuf;
+
+use anyhow::{Context as ErrContext, Result};
+use chrono::Local;
+use image::{ImageFormat, ImageReader};
+use nokhwa::utils::{FrameFormat, Resolution};
+use nokhwa::Buffer;
+
+use crate::messages::camera_frame::CameraFrame;
+use crate::sources::frame_source::FrameSource;
+
+const READ_CHUNK: usize = 64 * 1024;
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+const JPEG_EOI: [u8; 2] = [0xFF, 0xD9];
+
+/// Frames read from a motion JPEG file, i.e. a plain concatenation of JPEG images.
+/// The frames are passed on still encoded so the detector decodes them exactly as it
+/// would decode a frame from an MJPEG webcam.
+pub struct VideoSource {
+    path: PathBuf,
+    reader: Option<BufReader<File>>,
+    pending: Vec<u8>,
+}
+
+impl VideoSource {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, reader: None, pending: Vec::new() }
+    }
+
+    /// Remove the next complete JPEG image from the pending bytes, if there is one
+    fn take_jpeg(&mut self) -> Option<Vec<u8>> {
+        let start = find(&self.pending, &JPEG_SOI, 0)?;
+        let end = jpeg_end(&self.pending, start)?;
+        let jpeg = self.pending[start..end].to_vec();
+        self.pending.drain(..end);
+        Some(jpeg)
+    }
+}
+
+/// Where the JPEG image starting at `start` ends, just past its EOI. The marker
+/// segments are stepped over by their lengths, an EXIF thumbnail in APP1 has an
+/// EOI of its own, and only the data from the start of the scan is searched.
+fn jpeg_end(data: &[u8], start: usize) -> Option<usize> {
+    let mut pos = start + JPEG_SOI.len();
+    loop {
+        let marker = data.get(pos..pos + 2)?;
+        if marker[0] != 0xFF {
+            // not a marker where one should be, search the rest as it is
+            break;
+        }
+        match marker[1] {
+            // fill byte before a marker
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            0xD9 => return Some(pos + JPEG_EOI.len()),
+            // markers without a length
+            0x01 | 0xD0..=0xD7 => {
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+        let length = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        pos += 2 + length;
+        // SOS, the entropy coded data follows its header
+        if marker[1] == 0xDA {
+            break;
+        }
+    }
+    find(data, &JPEG_EOI, pos).map(|end| end + JPEG_EOI.len())
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from >= haystack.len() {
+        return None;
+    }
+    haystack[from..]
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|pos| pos + from)
+}
+
+impl FrameSource for VideoSource {
+    fn name(&self) -> String {
+        format!("video:{}", self.path.display())
+    }
+
+    fn open(&mut self) -> Result<()> {
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to open video {}", self.path.display()))?;
+        self.reader = Some(BufReader::new(file));
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn next_frame(&mut self) -> Result<Option<CameraFrame>> {
+        loop {
+            if let Some(jpeg) = self.take_jpeg() {
+                let (width, height) = ImageReader::with_format(Cursor::new(&jpeg), ImageFormat::Jpeg)
+                    .into_dimensions()
+                    .context("Failed to read JPEG frame header")?;
+                let buffer = Buffer::new(Resolution::new(width, height), &jpeg, FrameFormat::MJPEG);
+                return Ok(Some(CameraFrame::new(Local::now().timestamp_millis(), buffer)));
+            }
+
+            let reader = match self.reader.as_mut() {
+                Some(reader) => reader,
+                None => return Ok(None),
+            };
+            let mut chunk = vec![0u8; READ_CHUNK];
+            let read = reader.read(&mut chunk)?;
+            if read == 0 {
+                self.reader = None;
+                return Ok(None);
+            }
+            self.pending.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.reader = None;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::codecs::jpeg::JpegEncoder;
+    use image::RgbImage;
+
+    use super::*;
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        let image = RgbImage::from_pixel(width, height, image::Rgb([200, 120, 40]));
+        JpegEncoder::new(&mut jpeg).encode_image(&image).unwrap();
+        jpeg
+    }
+
+    /// `frame` with an APP1 segment carrying `thumbnail` inserted after its SOI
+    fn with_thumbnail(frame: &[u8], thumbnail: &[u8]) -> Vec<u8> {
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(thumbnail);
+        let mut jpeg = JPEG_SOI.to_vec();
+        jpeg.extend_from_slice(&[0xFF, 0xE1]);
+        jpeg.extend_from_slice(&(app1.len() as u16 + 2).to_be_bytes());
+        jpeg.extend_from_slice(&app1);
+        jpeg.extend_from_slice(&frame[JPEG_SOI.len()..]);
+        jpeg
+    }
+
+    #[test]
+    fn embedded_thumbnail_does_not_end_the_frame() {
+        let first = with_thumbnail(&jpeg(64, 48), &jpeg(8, 6));
+        let second = jpeg(32, 24);
+        let mut source = VideoSource::new(PathBuf::new());
+        source.pending = [&first[..], &second[..]].concat();
+
+        let frame = source.take_jpeg().unwrap();
+        assert_eq!(frame, first);
+        let reader = ImageReader::with_format(Cursor::new(&frame), ImageFormat::Jpeg);
+        assert_eq!(reader.into_dimensions().unwrap(), (64, 48));
+        assert_eq!(source.take_jpeg().unwrap(), second);
+        assert_eq!(source.take_jpeg(), None);
+
+        // a frame cut off inside the thumbnail waits for the rest to be read
+        source.pending = first[..first.len() / 2].to_vec();
+        assert_eq!(source.take_jpeg(), None);
+        source.pending.extend_from_slice(&first[first.len() / 2..]);
+        assert_eq!(source.take_jpeg().unwrap(), first);
+    }
+}
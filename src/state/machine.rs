@@ -0,0 +1,273 @@
+use std::sync::{Arc, PoisonError, RwLock};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// What the trap as a whole is doing. StateActor owns the state and is the only
+/// place it changes, through `next`, so every transition is checked against the
+/// table below before anything happens.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord,
+    prost::Enumeration, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+#[repr(i32)]
+pub enum TrapState {
+    /// Camera off, no session
+    Idle = 0,
+    /// Session open, waiting for the camera to deliver
+    Starting = 1,
+    /// Detecting into the active session
+    Capturing = 2,
+    /// Capturing and streaming detections to the app
+    Streaming = 3,
+    /// Camera off, the session stays open
+    Paused = 4,
+    /// The camera failed, the session stays open until capture is stopped or retried
+    Error = 5,
+    ShuttingDown = 6,
+}
+
+/// The state StateActor last moved the trap to, for actors that pick what to
+/// ask for by the state the trap is in. Unknown until StateActor has restored
+/// the state saved before a restart.
+#[derive(Clone, Debug, Default)]
+pub struct LiveState(Arc<RwLock<Option<TrapState>>>);
+
+impl LiveState {
+    pub fn get(&self) -> Option<TrapState> {
+        *self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn set(&self, state: Option<TrapState>) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = state;
+    }
+}
+
+/// Something that asks the trap to change state
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trigger {
+    Start,
+    Stop,
+    Pause,
+    Resume,
+    StreamOn,
+    StreamOff,
+    CameraOpened,
+    CameraFailed(String),
+    Shutdown,
+}
+
+/// Work other actors do when the trap moves between states
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Effect {
+    Session(bool),
+    Camera(bool),
+    DetectionStream(bool),
+}
+
+impl TrapState {
+    /// The state after `trigger`. Triggers that are already satisfied leave the
+    /// state as it is, those that make no sense in this state are errors.
+    pub fn next(self, trigger: &Trigger) -> Result<TrapState> {
+        use TrapState::*;
+        let next = match (self, trigger) {
+            (ShuttingDown, _) => bail!("The trap is shutting down"),
+            (_, Trigger::Shutdown) => ShuttingDown,
+
+            (Idle | Error, Trigger::Start) => Starting,
+            (Starting | Capturing | Streaming, Trigger::Start) => self,
+            (Paused, Trigger::Start) => bail!("Capture is paused, resume it instead"),
+            (_, Trigger::Stop) => Idle,
+
+            (Capturing | Streaming, Trigger::Pause) => Paused,
+            (Paused, Trigger::Pause) => self,
+            (Paused, Trigger::Resume) => Starting,
+            (Starting | Capturing | Streaming, Trigger::Resume) => self,
+
+            (Capturing, Trigger::StreamOn) => Streaming,
+            (Streaming, Trigger::StreamOn) => self,
+            (Streaming, Trigger::StreamOff) => Capturing,
+            (_, Trigger::StreamOff) => self,
+
+            (Starting, Trigger::CameraOpened) => Capturing,
+            (_, Trigger::CameraOpened) => self,
+            (Starting | Capturing | Streaming, Trigger::CameraFailed(_)) => Error,
+            (_, Trigger::CameraFailed(_)) => self,
+
+            (state, trigger) => bail!("Cannot {:?} while {:?}", trigger, state),
+        };
+        Ok(next)
+    }
+
+    pub fn is_capturing(self) -> bool {
+        matches!(self, TrapState::Starting | TrapState::Capturing | TrapState::Streaming)
+    }
+
+    pub fn is_streaming(self) -> bool {
+        self == TrapState::Streaming
+    }
+
+    fn has_session(self) -> bool {
+        self.is_capturing() || matches!(self, TrapState::Paused | TrapState::Error)
+    }
+
+    /// The state to come back to after a restart. A trap that was starting or had
+    /// a camera failure tries to capture again, streaming needs a client to ask.
+    pub fn resumable(self) -> Option<TrapState> {
+        match self {
+            TrapState::Idle => Some(TrapState::Idle),
+            TrapState::Starting | TrapState::Capturing | TrapState::Streaming | TrapState::Error => {
+                Some(TrapState::Capturing)
+            }
+            TrapState::Paused => Some(TrapState::Paused),
+            TrapState::ShuttingDown => None,
+        }
+    }
+}
+
+/// Side effects of moving from one state to another, in the order they must
+/// happen: the session opens before the camera starts and closes after it stops.
+/// Shutting down has none, every actor handles `app.shutdown` itself.
+pub fn effects(from: TrapState, to: TrapState) -> Vec<Effect> {
+    if to == TrapState::ShuttingDown {
+        return Vec::new();
+    }
+    let mut effects = Vec::new();
+    if from.has_session() != to.has_session() {
+        effects.push(Effect::Session(to.has_session()));
+    }
+    if from.is_capturing() != to.is_capturing() {
+        effects.push(Effect::Camera(to.is_capturing()));
+    }
+    if from.is_streaming() != to.is_streaming() {
+        effects.push(Effect::DetectionStream(to.is_streaming()));
+    }
+    let stopping = matches!(effects.first(), Some(Effect::Session(false)) | Some(Effect::Camera(false)));
+    if stopping {
+        effects.reverse();
+    }
+    effects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TrapState::*;
+
+    fn triggers() -> [Trigger; 9] {
+        [
+            Trigger::Start,
+            Trigger::Stop,
+            Trigger::Pause,
+            Trigger::Resume,
+            Trigger::StreamOn,
+            Trigger::StreamOff,
+            Trigger::CameraOpened,
+            Trigger::CameraFailed("gone".to_string()),
+            Trigger::Shutdown,
+        ]
+    }
+
+    /// The state each trigger above leads to, None where it is rejected
+    const TABLE: [(TrapState, [Option<TrapState>; 9]); 7] = [
+        (Idle, [
+            Some(Starting), Some(Idle), None, None, None,
+            Some(Idle), Some(Idle), Some(Idle), Some(ShuttingDown),
+        ]),
+        (Starting, [
+            Some(Starting), Some(Idle), None, Some(Starting), None,
+            Some(Starting), Some(Capturing), Some(Error), Some(ShuttingDown),
+        ]),
+        (Capturing, [
+            Some(Capturing), Some(Idle), Some(Paused), Some(Capturing), Some(Streaming),
+            Some(Capturing), Some(Capturing), Some(Error), Some(ShuttingDown),
+        ]),
+        (Streaming, [
+            Some(Streaming), Some(Idle), Some(Paused), Some(Streaming), Some(Streaming),
+            Some(Capturing), Some(Streaming), Some(Error), Some(ShuttingDown),
+        ]),
+        (Paused, [
+            None, Some(Idle), Some(Paused), Some(Starting), None,
+            Some(Paused), Some(Paused), Some(Paused), Some(ShuttingDown),
+        ]),
+        (Error, [
+            Some(Starting), Some(Idle), None, None, None,
+            Some(Error), Some(Error), Some(Error), Some(ShuttingDown),
+        ]),
+        (ShuttingDown, [None; 9]),
+    ];
+
+    /// Every transition that changes the state, with its effects in order
+    fn edges() -> Vec<(TrapState, TrapState, Vec<Effect>)> {
+        use Effect::*;
+        let mut edges = vec![
+            (Idle, Starting, vec![Session(true), Camera(true)]),
+            (Starting, Idle, vec![Camera(false), Session(false)]),
+            (Starting, Capturing, vec![]),
+            (Starting, Error, vec![Camera(false)]),
+            (Capturing, Idle, vec![Camera(false), Session(false)]),
+            (Capturing, Paused, vec![Camera(false)]),
+            (Capturing, Streaming, vec![DetectionStream(true)]),
+            (Capturing, Error, vec![Camera(false)]),
+            (Streaming, Idle, vec![DetectionStream(false), Camera(false), Session(false)]),
+            (Streaming, Paused, vec![DetectionStream(false), Camera(false)]),
+            (Streaming, Capturing, vec![DetectionStream(false)]),
+            (Streaming, Error, vec![DetectionStream(false), Camera(false)]),
+            (Paused, Idle, vec![Session(false)]),
+            (Paused, Starting, vec![Camera(true)]),
+            (Error, Starting, vec![Camera(true)]),
+            (Error, Idle, vec![Session(false)]),
+        ];
+        for from in [Idle, Starting, Capturing, Streaming, Paused, Error] {
+            edges.push((from, ShuttingDown, vec![]));
+        }
+        edges
+    }
+
+    #[test]
+    fn transitions() {
+        for (state, expected) in TABLE {
+            for (trigger, expected) in triggers().iter().zip(expected) {
+                let next = state.next(trigger).ok();
+                assert_eq!(next, expected, "{:?} on {:?}", trigger, state);
+            }
+        }
+    }
+
+    #[test]
+    fn effects_of_every_edge() {
+        let edges = edges();
+        let mut taken = Vec::new();
+        for (state, row) in TABLE {
+            for next in row.into_iter().flatten().filter(|&next| next != state) {
+                if !taken.contains(&(state, next)) {
+                    taken.push((state, next));
+                }
+            }
+        }
+        assert_eq!(taken.len(), edges.len());
+        for (from, to, expected) in edges {
+            assert!(taken.contains(&(from, to)), "{:?} -> {:?} is not a transition", from, to);
+            assert_eq!(effects(from, to), expected, "{:?} -> {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn streaming_needs_capture() {
+        for state in [Idle, Starting, Paused, Error, ShuttingDown] {
+            assert!(state.next(&Trigger::StreamOn).is_err(), "streaming from {:?}", state);
+        }
+        assert_eq!(Capturing.next(&Trigger::StreamOn).unwrap(), Streaming);
+    }
+
+    #[test]
+    fn resumable_states() {
+        assert_eq!(Idle.resumable(), Some(Idle));
+        assert_eq!(Paused.resumable(), Some(Paused));
+        for state in [Starting, Capturing, Streaming, Error] {
+            assert_eq!(state.resumable(), Some(Capturing));
+        }
+        assert_eq!(ShuttingDown.resumable(), None);
+    }
+}
@@ -0,0 +1,97 @@
+/// Constant velocity Kalman filter for a single coordinate.
+/// State is position and velocity, only the position is observed.
+#[derive(Debug, Clone)]
+pub struct Kalman1D {
+    position: f32,
+    velocity: f32,
+    // covariance matrix [[p00, p01], [p01, p11]]
+    p00: f32,
+    p01: f32,
+    p11: f32,
+}
+
+/// How much the velocity is allowed to wander between frames
+const PROCESS_NOISE: f32 = 1.0;
+/// How far a measured box edge is trusted
+const MEASUREMENT_NOISE: f32 = 10.0;
+/// Initial velocity is unknown
+const INITIAL_VELOCITY_VARIANCE: f32 = 1000.0;
+
+impl Kalman1D {
+    pub fn new(position: f32) -> Self {
+        Self {
+            position,
+            velocity: 0.0,
+            p00: MEASUREMENT_NOISE,
+            p01: 0.0,
+            p11: INITIAL_VELOCITY_VARIANCE,
+        }
+    }
+
+    pub fn position(&self) -> f32 {
+        self.position
+    }
+
+    /// Advance the state by one frame
+    pub fn predict(&mut self) {
+        self.position += self.velocity;
+        // P = F P F' + Q with F = [[1, 1], [0, 1]]
+        self.p00 += 2.0 * self.p01 + self.p11 + PROCESS_NOISE / 4.0;
+        self.p01 += self.p11 + PROCESS_NOISE / 2.0;
+        self.p11 += PROCESS_NOISE;
+    }
+
+    /// Correct the state with a measured position
+    pub fn update(&mut self, measured: f32) {
+        let innovation = measured - self.position;
+        let s = self.p00 + MEASUREMENT_NOISE;
+        let k0 = self.p00 / s;
+        let k1 = self.p01 / s;
+
+        self.position += k0 * innovation;
+        self.velocity += k1 * innovation;
+
+        let (p00, p01, p11) = (self.p00, self.p01, self.p11);
+        self.p00 = (1.0 - k0) * p00;
+        self.p01 = (1.0 - k0) * p01;
+        self.p11 = p11 - k1 * p01;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn still_position_stays_put() {
+        let mut filter = Kalman1D::new(50.0);
+        for _ in 0..20 {
+            filter.predict();
+            filter.update(50.0);
+        }
+        filter.predict();
+        assert!((filter.position() - 50.0).abs() < 0.01, "{}", filter.position());
+    }
+
+    #[test]
+    fn learns_a_constant_velocity() {
+        let mut filter = Kalman1D::new(0.0);
+        for frame in 1..=30 {
+            filter.predict();
+            filter.update(frame as f32 * 5.0);
+        }
+        // the prediction runs ahead without a measurement
+        filter.predict();
+        assert!((filter.position() - 155.0).abs() < 1.0, "{}", filter.position());
+        filter.predict();
+        assert!((filter.position() - 160.0).abs() < 1.0, "{}", filter.position());
+    }
+
+    #[test]
+    fn measurement_pulls_towards_it() {
+        let mut filter = Kalman1D::new(0.0);
+        filter.predict();
+        filter.update(10.0);
+        assert!(filter.position() > 0.0 && filter.position() < 10.0, "{}", filter.position());
+    }
+}
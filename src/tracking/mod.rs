@@ -0,0 +1,2 @@
+pub mod kalman;
+pub mod sort;
@@ -0,0 +1,267 @@
+use std::cmp::Ordering;
+
+use crate::detection::yolo::BoundingBox;
+use crate::tracking::kalman::Kalman1D;
+
+/// Minimum overlap between a prediction and a detection to be the same insect
+const IOU_THRESHOLD: f32 = 0.3;
+/// Frames a track survives without a matching detection
+const MAX_AGE: u32 = 30;
+/// Consecutive hits before a track is first reported, filters flickers
+const MIN_HITS: u32 = 3;
+
+/// A detection that has been assigned to a track
+#[derive(Debug, Clone, Copy)]
+pub struct TrackedBox {
+    pub track: u64,
+    pub bbox: BoundingBox,
+    /// First frame the track is reported in
+    pub is_new: bool,
+}
+
+/// The outcome of feeding one frame of detections to the tracker
+#[derive(Debug, Clone, Default)]
+pub struct TrackingResult {
+    pub tracked: Vec<TrackedBox>,
+    /// Reported tracks that have been lost and will not be seen again
+    pub ended: Vec<u64>,
+}
+
+/// A box whose centre and size are each smoothed by a Kalman filter
+struct Track {
+    id: u64,
+    cx: Kalman1D,
+    cy: Kalman1D,
+    width: Kalman1D,
+    height: Kalman1D,
+    /// Only detections of the class the track started with are matched to it
+    clazz: i32,
+    /// Frames in a row the track has been matched
+    hits: u32,
+    missed: u32,
+    reported: bool,
+}
+
+impl Track {
+    fn new(id: u64, bbox: &BoundingBox) -> Self {
+        Self {
+            id,
+            cx: Kalman1D::new((bbox.x1 + bbox.x2) / 2.0),
+            cy: Kalman1D::new((bbox.y1 + bbox.y2) / 2.0),
+            width: Kalman1D::new(bbox.width()),
+            height: Kalman1D::new(bbox.height()),
+            clazz: bbox.clazz,
+            hits: 1,
+            missed: 0,
+            reported: false,
+        }
+    }
+
+    fn predict(&mut self) {
+        self.cx.predict();
+        self.cy.predict();
+        self.width.predict();
+        self.height.predict();
+        self.missed += 1;
+    }
+
+    fn update(&mut self, bbox: &BoundingBox) {
+        self.cx.update((bbox.x1 + bbox.x2) / 2.0);
+        self.cy.update((bbox.y1 + bbox.y2) / 2.0);
+        self.width.update(bbox.width());
+        self.height.update(bbox.height());
+        // missed is 1 after the prediction for this frame, more when the
+        // track went unmatched in earlier frames
+        if self.missed > 1 {
+            self.hits = 0;
+        }
+        self.hits += 1;
+        self.missed = 0;
+    }
+
+    fn predicted_box(&self) -> BoundingBox {
+        let (w, h) = (self.width.position().max(1.0), self.height.position().max(1.0));
+        BoundingBox {
+            x1: self.cx.position() - w / 2.0,
+            y1: self.cy.position() - h / 2.0,
+            x2: self.cx.position() + w / 2.0,
+            y2: self.cy.position() + h / 2.0,
+            score: 0.0,
+            clazz: self.clazz,
+        }
+    }
+}
+
+/// SORT style multi-object tracker: Kalman predicted boxes are matched to new
+/// detections of the same class by IoU so that an insect keeps the same id
+/// across frames. A track is reported once it has been matched in `MIN_HITS`
+/// frames in a row, and from then on whenever it is matched.
+pub struct Tracker {
+    tracks: Vec<Track>,
+    next_id: u64,
+}
+
+impl Default for Tracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self { tracks: Vec::new(), next_id: 1 }
+    }
+
+    pub fn update(&mut self, detections: &[BoundingBox]) -> TrackingResult {
+        for track in self.tracks.iter_mut() {
+            track.predict();
+        }
+
+        // Greedy assignment, best overlaps first
+        let mut candidates = Vec::new();
+        for (t, track) in self.tracks.iter().enumerate() {
+            let predicted = track.predicted_box();
+            for (d, detection) in detections.iter().enumerate().filter(|(_, d)| d.clazz == track.clazz) {
+                let iou = predicted.iou(detection);
+                if iou >= IOU_THRESHOLD {
+                    candidates.push((iou, t, d));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+
+        let mut track_matched = vec![false; self.tracks.len()];
+        let mut detection_track: Vec<Option<usize>> = vec![None; detections.len()];
+        for (_, t, d) in candidates {
+            if !track_matched[t] && detection_track[d].is_none() {
+                track_matched[t] = true;
+                detection_track[d] = Some(t);
+            }
+        }
+
+        let mut result = TrackingResult::default();
+        for (d, detection) in detections.iter().enumerate() {
+            let t = match detection_track[d] {
+                Some(t) => {
+                    self.tracks[t].update(detection);
+                    t
+                }
+                None => {
+                    self.tracks.push(Track::new(self.next_id, detection));
+                    self.next_id += 1;
+                    self.tracks.len() - 1
+                }
+            };
+
+            let track = &mut self.tracks[t];
+            if track.reported || track.hits >= MIN_HITS {
+                result.tracked.push(TrackedBox {
+                    track: track.id,
+                    bbox: *detection,
+                    is_new: !track.reported,
+                });
+                track.reported = true;
+            }
+        }
+
+        self.tracks.retain(|track| {
+            let alive = track.missed <= MAX_AGE;
+            if !alive && track.reported {
+                result.ended.push(track.id);
+            }
+            alive
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn bbox(x: f32, y: f32, clazz: i32) -> BoundingBox {
+        BoundingBox { x1: x, y1: y, x2: x + 40.0, y2: y + 40.0, score: 0.8, clazz }
+    }
+
+    #[test]
+    fn box_that_stays_put_keeps_one_id() {
+        let mut tracker = Tracker::new();
+        for frame in 0..10 {
+            let result = tracker.update(&[bbox(100.0, 100.0, 0)]);
+            if frame < MIN_HITS - 1 {
+                assert!(result.tracked.is_empty(), "reported in frame {}", frame);
+                continue;
+            }
+            assert_eq!(result.tracked.len(), 1);
+            assert_eq!(result.tracked[0].track, 1);
+            assert_eq!(result.tracked[0].is_new, frame == MIN_HITS - 1);
+        }
+    }
+
+    #[test]
+    fn crossing_boxes_keep_their_ids() {
+        let mut tracker = Tracker::new();
+        // one flies right and the other left, passing each other at frame 10
+        let mut rows: HashMap<u64, f32> = HashMap::new();
+        for frame in 0..20 {
+            let step = frame as f32 * 8.0;
+            let result = tracker.update(&[bbox(step, 100.0, 0), bbox(160.0 - step, 120.0, 0)]);
+            for tracked in &result.tracked {
+                let row = *rows.entry(tracked.track).or_insert(tracked.bbox.y1);
+                assert_eq!(row, tracked.bbox.y1, "track {} swapped boxes in frame {}", tracked.track, frame);
+            }
+            assert!(result.ended.is_empty());
+        }
+        assert_eq!(rows.len(), 2);
+    }
+
+    #[test]
+    fn track_is_dropped_after_max_age_misses() {
+        let mut tracker = Tracker::new();
+        for _ in 0..MIN_HITS {
+            tracker.update(&[bbox(100.0, 100.0, 0)]);
+        }
+        for missed in 1..=MAX_AGE {
+            assert!(tracker.update(&[]).ended.is_empty(), "ended after {} misses", missed);
+        }
+        assert_eq!(tracker.update(&[]).ended, [1]);
+        assert!(tracker.tracks.is_empty());
+
+        // the same place again is a new insect
+        for _ in 0..MIN_HITS {
+            tracker.update(&[bbox(100.0, 100.0, 0)]);
+        }
+        assert_eq!(tracker.update(&[bbox(100.0, 100.0, 0)]).tracked[0].track, 2);
+    }
+
+    #[test]
+    fn flickering_box_is_not_reported() {
+        let mut tracker = Tracker::new();
+        for frame in 0..12 {
+            let detections = if frame % 2 == 0 { vec![bbox(100.0, 100.0, 0)] } else { Vec::new() };
+            assert!(tracker.update(&detections).tracked.is_empty(), "reported in frame {}", frame);
+        }
+
+        // a reported track carries on through a missed frame
+        for _ in 0..MIN_HITS {
+            tracker.update(&[bbox(100.0, 100.0, 0)]);
+        }
+        tracker.update(&[]);
+        let result = tracker.update(&[bbox(100.0, 100.0, 0)]);
+        assert_eq!(result.tracked.len(), 1);
+        assert!(!result.tracked[0].is_new);
+    }
+
+    #[test]
+    fn other_class_starts_its_own_track() {
+        let mut tracker = Tracker::new();
+        for _ in 0..MIN_HITS {
+            tracker.update(&[bbox(100.0, 100.0, 0)]);
+        }
+        let result = tracker.update(&[bbox(100.0, 100.0, 1)]);
+        assert!(result.tracked.is_empty(), "class 1 reported as the class 0 track");
+        assert_eq!(tracker.tracks.len(), 2);
+    }
+}